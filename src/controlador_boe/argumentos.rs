@@ -0,0 +1,236 @@
+use std::path::PathBuf;
+
+use super::{cifrar::Permiso, dividir::Division, filtros::Filtros, registro::NivelRegistro};
+
+pub enum Modo {
+    Descargar(String),
+    Vigilar,
+    Servir,
+    MostrarConfiguracion,
+    // Comprueba la firma de un PDF ya guardado
+    Verificar(PathBuf),
+    // Diferencias entre dos informes, paquetes o manifiestos
+    Comparar(PathBuf, PathBuf),
+}
+
+pub struct Argumentos {
+    pub modo: Modo,
+    pub ruta_configuracion: Option<PathBuf>,
+    pub nivel_registro: NivelRegistro,
+    pub progreso_json: bool,
+    // Valores que prevalecen sobre el entorno y el archivo de configuración
+    pub filtros: Filtros,
+    pub url_base: Option<String>,
+    pub apartados: Vec<String>,
+    pub directorio_salida: Option<PathBuf>,
+    pub plantilla_nombre: Option<String>,
+    pub espera: Option<u64>,
+    pub reintentos: Option<u32>,
+    pub proxy: Option<String>,
+    pub sin_proxy: Option<String>,
+    pub certificados_ca: Vec<PathBuf>,
+    pub agente_usuario: Option<String>,
+    pub tiempo_conexion: Option<u64>,
+    pub tiempo_lectura: Option<u64>,
+    // Las opciones de sí o no son None si no se indican, para no pisar el archivo
+    pub http2: Option<bool>,
+    pub reparar_pdf: Option<bool>,
+    pub optimizar_recursos: Option<bool>,
+    pub pdfa: Option<bool>,
+    pub estampar_paginas: Option<bool>,
+    pub division: Option<Division>,
+    pub volumen_maximo_mb: Option<u64>,
+    pub volumen_maximo_paginas: Option<usize>,
+    pub guardar_individuales: Option<bool>,
+    pub empaquetar: Option<bool>,
+    pub verificar_firmas: Option<bool>,
+    pub metadatos_xml: Option<bool>,
+    pub almacen_confianza: Vec<PathBuf>,
+    pub certificado_firma: Option<PathBuf>,
+    pub cifrar_pdf: Option<bool>,
+    pub permisos_pdf: Vec<Permiso>,
+    pub direccion: Option<String>,
+}
+
+pub fn procesar_argumentos(argumentos: &[String]) -> Result<Argumentos, String> {
+    let mut posicionales = Vec::new();
+    let mut resultado = Argumentos {
+        modo: Modo::Vigilar,
+        ruta_configuracion: None,
+        nivel_registro: NivelRegistro::Normal,
+        progreso_json: false,
+        filtros: Filtros::default(),
+        url_base: None,
+        apartados: Vec::new(),
+        directorio_salida: None,
+        plantilla_nombre: None,
+        espera: None,
+        reintentos: None,
+        proxy: None,
+        sin_proxy: None,
+        certificados_ca: Vec::new(),
+        agente_usuario: None,
+        tiempo_conexion: None,
+        tiempo_lectura: None,
+        http2: None,
+        reparar_pdf: None,
+        optimizar_recursos: None,
+        pdfa: None,
+        estampar_paginas: None,
+        division: None,
+        volumen_maximo_mb: None,
+        volumen_maximo_paginas: None,
+        guardar_individuales: None,
+        empaquetar: None,
+        verificar_firmas: None,
+        metadatos_xml: None,
+        almacen_confianza: Vec::new(),
+        certificado_firma: None,
+        cifrar_pdf: None,
+        permisos_pdf: Vec::new(),
+        direccion: None,
+    };
+    // El primer argumento es la ruta del ejecutable
+    let mut iterador = argumentos.iter().skip(1);
+    while let Some(argumento) = iterador.next() {
+        if !argumento.starts_with("--") {
+            posicionales.push(argumento.as_str());
+            continue;
+        }
+        // --pdfa activa la opción y --no-pdfa la desactiva aunque esté en el archivo
+        let (nombre, activar) = match argumento.strip_prefix("--no-") {
+            Some(nombre) => (nombre, false),
+            None => (&argumento[2..], true),
+        };
+        if let Some(opcion) = opcion_booleana(&mut resultado, nombre) {
+            *opcion = Some(activar);
+            continue;
+        }
+        match argumento.as_str() {
+            "--silencioso" => {
+                resultado.nivel_registro = NivelRegistro::Silencioso;
+                continue;
+            }
+            "--verbose" => {
+                resultado.nivel_registro = NivelRegistro::Detallado;
+                continue;
+            }
+            _ => (),
+        }
+        let valor = match iterador.next() {
+            None => {
+                return Err(format!("el argumento {} necesita un valor", argumento));
+            }
+            Some(valor) => valor.to_owned(),
+        };
+        match argumento.as_str() {
+            "--config" => resultado.ruta_configuracion = Some(PathBuf::from(valor)),
+            "--departamento" => resultado.filtros.departamentos.push(valor),
+            "--epigrafe" => resultado.filtros.epigrafes.push(valor),
+            "--rango" => resultado.filtros.rangos.push(valor),
+            "--url-base" => resultado.url_base = Some(valor),
+            "--apartado" => resultado.apartados.push(valor),
+            "--salida" => resultado.directorio_salida = Some(PathBuf::from(valor)),
+            "--plantilla" => resultado.plantilla_nombre = Some(valor),
+            "--espera" => resultado.espera = Some(parsear_numero(argumento, &valor)?),
+            "--reintentos" => resultado.reintentos = Some(parsear_numero(argumento, &valor)?),
+            "--proxy" => resultado.proxy = Some(valor),
+            "--sin-proxy" => resultado.sin_proxy = Some(valor),
+            "--ca" => resultado.certificados_ca.push(PathBuf::from(valor)),
+            "--confianza" => resultado.almacen_confianza.push(PathBuf::from(valor)),
+            "--firmar" => resultado.certificado_firma = Some(PathBuf::from(valor)),
+            "--permiso" => resultado.permisos_pdf.push(Permiso::desde_texto(&valor)?),
+            "--agente" => resultado.agente_usuario = Some(valor),
+            "--tiempo-conexion" => {
+                resultado.tiempo_conexion = Some(parsear_numero(argumento, &valor)?)
+            }
+            "--tiempo-lectura" => {
+                resultado.tiempo_lectura = Some(parsear_numero(argumento, &valor)?)
+            }
+            "--direccion" => resultado.direccion = Some(valor),
+            "--dividir" => resultado.division = Some(Division::desde_texto(&valor)?),
+            "--volumen-mb" => {
+                resultado.volumen_maximo_mb = Some(parsear_numero(argumento, &valor)?)
+            }
+            "--volumen-paginas" => {
+                resultado.volumen_maximo_paginas = Some(parsear_numero(argumento, &valor)?)
+            }
+            "--progreso" => match valor.as_str() {
+                "json" => resultado.progreso_json = true,
+                "texto" => resultado.progreso_json = false,
+                _ => {
+                    return Err(format!(
+                        "el valor {} no es válido para --progreso, se esperaba json o texto",
+                        valor
+                    ));
+                }
+            },
+            _ => {
+                return Err(format!("el argumento {} no es válido", argumento));
+            }
+        }
+    }
+    resultado.modo = match posicionales.as_slice() {
+        [] => {
+            return Err(String::from(
+                "no se ha indicado ninguna fecha, se esperaba DD-MM-AÑO o uno de los modos vigilar, servir, verificar, comparar o config mostrar",
+            ));
+        }
+        ["vigilar"] => Modo::Vigilar,
+        ["servir"] => Modo::Servir,
+        ["config", "mostrar"] => Modo::MostrarConfiguracion,
+        ["verificar", ruta] => Modo::Verificar(PathBuf::from(ruta)),
+        ["verificar"] => {
+            return Err(String::from("el modo verificar necesita la ruta del PDF"));
+        }
+        ["comparar", antes, despues] => {
+            Modo::Comparar(PathBuf::from(antes), PathBuf::from(despues))
+        }
+        ["comparar", ..] => {
+            return Err(String::from(
+                "el modo comparar necesita dos informes JSON, paquetes ZIP o manifiestos",
+            ));
+        }
+        ["config", ..] => {
+            return Err(String::from("el modo config solo admite la orden mostrar"));
+        }
+        [fecha] => Modo::Descargar(fecha.to_string()),
+        [_, sobrante, ..] => {
+            return Err(format!(
+                "solo puede indicarse una fecha o un modo, sobra el argumento {}",
+                sobrante
+            ));
+        }
+    };
+    Ok(resultado)
+}
+
+fn opcion_booleana<'a>(
+    argumentos: &'a mut Argumentos,
+    nombre: &str,
+) -> Option<&'a mut Option<bool>> {
+    let opcion = match nombre {
+        "http2" => &mut argumentos.http2,
+        "reparar" => &mut argumentos.reparar_pdf,
+        "optimizar" => &mut argumentos.optimizar_recursos,
+        "pdfa" => &mut argumentos.pdfa,
+        "estampar" => &mut argumentos.estampar_paginas,
+        "individuales" => &mut argumentos.guardar_individuales,
+        "zip" => &mut argumentos.empaquetar,
+        "verificar-firmas" => &mut argumentos.verificar_firmas,
+        "metadatos-xml" => &mut argumentos.metadatos_xml,
+        "cifrar" => &mut argumentos.cifrar_pdf,
+        _ => return None,
+    };
+    Some(opcion)
+}
+
+fn parsear_numero<T: std::str::FromStr>(argumento: &str, valor: &str) -> Result<T, String> {
+    match valor.parse::<T>() {
+        Err(_) => Err(format!(
+            "el valor {} no es válido para {}, se esperaba un número",
+            valor, argumento
+        )),
+        Ok(ok) => Ok(ok),
+    }
+}
@@ -0,0 +1,386 @@
+use cbc::cipher::{
+    block_padding::{NoPadding, Pkcs7},
+    generic_array::GenericArray,
+    BlockEncrypt, BlockEncryptMut, KeyInit, KeyIvInit,
+};
+use lopdf::{dictionary, Dictionary, Document, Object, StringFormat};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use super::{configuracion::Configuracion, pdfa};
+
+type CifradorAes128 = cbc::Encryptor<aes::Aes128>;
+type CifradorAes256 = cbc::Encryptor<aes::Aes256>;
+
+// Las contraseñas se truncan a 127 bytes, como indica el manejador de seguridad de PDF 2.0
+const LONGITUD_MAXIMA_CLAVE: usize = 127;
+// Bits 7, 8 y 13 a 32 reservados, siempre a 1 en la revisión 6
+const PERMISOS_RESERVADOS: u32 = 0xFFFF_F0C0;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permiso {
+    Imprimir,
+    ImprimirAltaCalidad,
+    Modificar,
+    Copiar,
+    Anotar,
+    Rellenar,
+    Accesibilidad,
+    Ensamblar,
+}
+
+impl Permiso {
+    pub fn desde_texto(texto: &str) -> Result<Permiso, String> {
+        match texto {
+            "imprimir" => Ok(Permiso::Imprimir),
+            "imprimir_alta_calidad" => Ok(Permiso::ImprimirAltaCalidad),
+            "modificar" => Ok(Permiso::Modificar),
+            "copiar" => Ok(Permiso::Copiar),
+            "anotar" => Ok(Permiso::Anotar),
+            "rellenar" => Ok(Permiso::Rellenar),
+            "accesibilidad" => Ok(Permiso::Accesibilidad),
+            "ensamblar" => Ok(Permiso::Ensamblar),
+            _ => Err(format!(
+                "el permiso {} no es válido, se esperaba {}",
+                texto,
+                "imprimir, imprimir_alta_calidad, modificar, copiar, anotar, rellenar, accesibilidad o ensamblar"
+            )),
+        }
+    }
+    // Posición del permiso en la entrada P, contando desde el bit 1
+    fn bit(&self) -> u32 {
+        let posicion = match self {
+            Permiso::Imprimir => 3,
+            Permiso::Modificar => 4,
+            Permiso::Copiar => 5,
+            Permiso::Anotar => 6,
+            Permiso::Rellenar => 9,
+            Permiso::Accesibilidad => 10,
+            Permiso::Ensamblar => 11,
+            Permiso::ImprimirAltaCalidad => 12,
+        };
+        1 << (posicion - 1)
+    }
+}
+
+pub fn permisos_predeterminados() -> Vec<Permiso> {
+    vec![
+        Permiso::Imprimir,
+        Permiso::ImprimirAltaCalidad,
+        Permiso::Accesibilidad,
+    ]
+}
+
+pub struct Cifrado {
+    pub clave_usuario: String,
+    pub clave_propietario: String,
+    pub permisos: Vec<Permiso>,
+}
+
+impl Cifrado {
+    pub fn desde_configuracion(configuracion: &Configuracion) -> Option<Cifrado> {
+        if !configuracion.cifrar_pdf {
+            return None;
+        }
+        let clave_usuario = configuracion.clave_usuario_pdf.clone().unwrap_or_default();
+        // Sin clave de propietario nadie puede levantar las restricciones
+        let clave_propietario = match &configuracion.clave_propietario_pdf {
+            Some(clave) => clave.to_owned(),
+            None => {
+                let mut aleatoria = [0; 16];
+                OsRng.fill_bytes(&mut aleatoria);
+                aleatoria.iter().map(|b| format!("{:02x}", b)).collect()
+            }
+        };
+        Some(Cifrado {
+            clave_usuario,
+            clave_propietario,
+            permisos: configuracion.permisos_pdf.clone(),
+        })
+    }
+}
+
+// Cifra todas las cadenas y flujos con AES-256 (manejador estándar, revisión 6). Debe ser lo
+// último que se hace con el documento antes de serializarlo
+pub fn cifrar_documento(documento: &mut Document, cifrado: &Cifrado) {
+    let mut clave_archivo = [0; 32];
+    OsRng.fill_bytes(&mut clave_archivo);
+    let diccionario = diccionario_cifrado(&clave_archivo, cifrado);
+    for objeto in documento.objects.values_mut() {
+        cifrar_objeto(objeto, &clave_archivo);
+    }
+    let id_cifrado = documento.add_object(diccionario);
+    documento.trailer.set("Encrypt", id_cifrado);
+    // El identificador del archivo es obligatorio en los documentos cifrados
+    if documento.trailer.get(b"ID").is_err() {
+        pdfa::añadir_identificador(documento);
+    }
+    // AESV3 es de PDF 2.0
+    documento.version = String::from("2.0");
+}
+
+pub fn valor_permisos(permisos: &[Permiso]) -> u32 {
+    permisos
+        .iter()
+        .fold(PERMISOS_RESERVADOS, |valor, permiso| valor | permiso.bit())
+}
+
+fn diccionario_cifrado(clave_archivo: &[u8; 32], cifrado: &Cifrado) -> Dictionary {
+    let usuario = preparar_clave(&cifrado.clave_usuario);
+    let propietario = preparar_clave(&cifrado.clave_propietario);
+    let permisos = valor_permisos(&cifrado.permisos);
+
+    // Algoritmo 8: U y UE a partir de la clave de usuario
+    let mut sales = [0; 16];
+    OsRng.fill_bytes(&mut sales);
+    let mut u = hash_r6(usuario, &sales[..8], &[]).to_vec();
+    u.extend_from_slice(&sales);
+    let ue = cifrar_sin_relleno(&hash_r6(usuario, &sales[8..], &[]), clave_archivo);
+
+    // Algoritmo 9: O y OE, que dependen también de U
+    OsRng.fill_bytes(&mut sales);
+    let mut o = hash_r6(propietario, &sales[..8], &u).to_vec();
+    o.extend_from_slice(&sales);
+    let oe = cifrar_sin_relleno(&hash_r6(propietario, &sales[8..], &u), clave_archivo);
+
+    // Algoritmo 10: copia cifrada de los permisos para detectar si se ha cambiado P
+    let mut bloque = [0; 16];
+    bloque[..4].copy_from_slice(&permisos.to_le_bytes());
+    bloque[4..8].copy_from_slice(&[0xFF; 4]);
+    bloque[8..12].copy_from_slice(b"Tadb");
+    OsRng.fill_bytes(&mut bloque[12..]);
+    let mut bloque = GenericArray::from(bloque);
+    aes::Aes256::new(GenericArray::from_slice(clave_archivo)).encrypt_block(&mut bloque);
+
+    let hexadecimal = |bytes: Vec<u8>| Object::String(bytes, StringFormat::Hexadecimal);
+    dictionary! {
+        "Filter" => "Standard",
+        "V" => 5,
+        "R" => 6,
+        "Length" => 256,
+        "CF" => dictionary! {
+            "StdCF" => dictionary! {
+                "AuthEvent" => "DocOpen",
+                "CFM" => "AESV3",
+                "Length" => 32,
+            },
+        },
+        "StmF" => "StdCF",
+        "StrF" => "StdCF",
+        "O" => hexadecimal(o),
+        "U" => hexadecimal(u),
+        "OE" => hexadecimal(oe),
+        "UE" => hexadecimal(ue),
+        "P" => permisos as i32,
+        "Perms" => hexadecimal(bloque.to_vec()),
+    }
+}
+
+// Sin SASLprep: las claves se usan tal cual en UTF-8
+fn preparar_clave(clave: &str) -> &[u8] {
+    let bytes = clave.as_bytes();
+    &bytes[..bytes.len().min(LONGITUD_MAXIMA_CLAVE)]
+}
+
+// Algoritmo 2.B de ISO 32000-2
+pub fn hash_r6(clave: &[u8], sal: &[u8], datos_usuario: &[u8]) -> [u8; 32] {
+    let mut k = Sha256::new()
+        .chain_update(clave)
+        .chain_update(sal)
+        .chain_update(datos_usuario)
+        .finalize()
+        .to_vec();
+    let mut ronda = 0;
+    let mut e = Vec::new();
+    while ronda < 64 || i32::from(*e.last().unwrap_or(&0)) > ronda - 32 {
+        let mut bloque = Vec::new();
+        bloque.extend_from_slice(clave);
+        bloque.extend_from_slice(&k);
+        bloque.extend_from_slice(datos_usuario);
+        let k1 = bloque.repeat(64);
+        e = CifradorAes128::new(
+            GenericArray::from_slice(&k[..16]),
+            GenericArray::from_slice(&k[16..32]),
+        )
+        .encrypt_padded_vec_mut::<NoPadding>(&k1);
+        let resto = e[..16].iter().map(|b| u32::from(*b)).sum::<u32>() % 3;
+        k = match resto {
+            0 => Sha256::digest(&e).to_vec(),
+            1 => Sha384::digest(&e).to_vec(),
+            _ => Sha512::digest(&e).to_vec(),
+        };
+        ronda += 1;
+    }
+    let mut resultado = [0; 32];
+    resultado.copy_from_slice(&k[..32]);
+    resultado
+}
+
+// UE y OE: AES-256 en CBC con vector inicial a cero y sin relleno
+fn cifrar_sin_relleno(clave: &[u8; 32], datos: &[u8]) -> Vec<u8> {
+    CifradorAes256::new(GenericArray::from_slice(clave), &GenericArray::default())
+        .encrypt_padded_vec_mut::<NoPadding>(datos)
+}
+
+// Cadenas y flujos: vector inicial aleatorio delante de los datos cifrados con relleno PKCS#7
+pub fn cifrar_datos(clave_archivo: &[u8; 32], datos: &[u8]) -> Vec<u8> {
+    let mut vector_inicial = [0; 16];
+    OsRng.fill_bytes(&mut vector_inicial);
+    let cifrados = CifradorAes256::new(
+        GenericArray::from_slice(clave_archivo),
+        GenericArray::from_slice(&vector_inicial),
+    )
+    .encrypt_padded_vec_mut::<Pkcs7>(datos);
+    let mut resultado = vector_inicial.to_vec();
+    resultado.extend(cifrados);
+    resultado
+}
+
+fn cifrar_objeto(objeto: &mut Object, clave_archivo: &[u8; 32]) {
+    match objeto {
+        Object::String(contenido, formato) => {
+            *contenido = cifrar_datos(clave_archivo, contenido);
+            // El resultado es binario, en hexadecimal no hace falta escapar nada
+            *formato = StringFormat::Hexadecimal;
+        }
+        Object::Array(elementos) => {
+            for elemento in elementos {
+                cifrar_objeto(elemento, clave_archivo);
+            }
+        }
+        Object::Dictionary(diccionario) => cifrar_diccionario(diccionario, clave_archivo),
+        Object::Stream(flujo) => {
+            cifrar_diccionario(&mut flujo.dict, clave_archivo);
+            let contenido = cifrar_datos(clave_archivo, &flujo.content);
+            flujo.set_content(contenido);
+        }
+        _ => (),
+    }
+}
+
+fn cifrar_diccionario(diccionario: &mut Dictionary, clave_archivo: &[u8; 32]) {
+    for (_, valor) in diccionario.iter_mut() {
+        cifrar_objeto(valor, clave_archivo);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cbc::cipher::{BlockDecrypt, BlockDecryptMut};
+
+    type DescifradorAes256 = cbc::Decryptor<aes::Aes256>;
+
+    fn cifrado(usuario: &str, propietario: &str) -> Cifrado {
+        Cifrado {
+            clave_usuario: String::from(usuario),
+            clave_propietario: String::from(propietario),
+            permisos: vec![Permiso::Imprimir, Permiso::Copiar],
+        }
+    }
+
+    fn cadena(diccionario: &Dictionary, clave: &str) -> Vec<u8> {
+        diccionario
+            .get(clave.as_bytes())
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_vec()
+    }
+
+    fn descifrar_sin_relleno(clave: &[u8; 32], datos: &[u8]) -> Vec<u8> {
+        DescifradorAes256::new(GenericArray::from_slice(clave), &GenericArray::default())
+            .decrypt_padded_vec_mut::<NoPadding>(datos)
+            .unwrap()
+    }
+
+    #[test]
+    fn las_claves_recuperan_la_clave_del_archivo() {
+        let clave_archivo = [7; 32];
+        let diccionario = diccionario_cifrado(&clave_archivo, &cifrado("lector", "editor"));
+        let u = cadena(&diccionario, "U");
+        let o = cadena(&diccionario, "O");
+        assert_eq!((u.len(), o.len()), (48, 48));
+
+        // Validación de la clave de usuario y descifrado de UE
+        assert_eq!(hash_r6(b"lector", &u[32..40], &[]), u[..32]);
+        assert_ne!(hash_r6(b"otra", &u[32..40], &[]), u[..32]);
+        let intermedia = hash_r6(b"lector", &u[40..48], &[]);
+        assert_eq!(
+            descifrar_sin_relleno(&intermedia, &cadena(&diccionario, "UE")),
+            clave_archivo
+        );
+
+        // La de propietario se valida junto con U
+        assert_eq!(hash_r6(b"editor", &o[32..40], &u), o[..32]);
+        let intermedia = hash_r6(b"editor", &o[40..48], &u);
+        assert_eq!(
+            descifrar_sin_relleno(&intermedia, &cadena(&diccionario, "OE")),
+            clave_archivo
+        );
+    }
+
+    #[test]
+    fn perms_guarda_una_copia_cifrada_de_los_permisos() {
+        let clave_archivo = [3; 32];
+        let diccionario = diccionario_cifrado(&clave_archivo, &cifrado("", "editor"));
+        let p = diccionario.get(b"P").unwrap().as_i64().unwrap();
+        assert_eq!(p as u32, 0xFFFF_F0C0 | 0b100 | 0b1_0000);
+
+        let mut bloque = GenericArray::clone_from_slice(&cadena(&diccionario, "Perms"));
+        aes::Aes256::new(GenericArray::from_slice(&clave_archivo)).decrypt_block(&mut bloque);
+        assert_eq!(bloque[..4], (p as u32).to_le_bytes());
+        assert_eq!(&bloque[8..12], b"Tadb");
+    }
+
+    #[test]
+    fn cifra_cadenas_y_flujos_salvo_el_diccionario_de_cifrado() {
+        let mut documento = Document::with_version("1.5");
+        let flujo = lopdf::Stream::new(dictionary! {}, b"BT (hola) Tj ET".to_vec());
+        let id_flujo = documento.add_object(flujo);
+        let id_info = documento.add_object(dictionary! {
+            "Title" => Object::string_literal("Boletín"),
+        });
+        documento.trailer.set("Info", id_info);
+        cifrar_documento(&mut documento, &cifrado("lector", "editor"));
+
+        assert_eq!(documento.version, "2.0");
+        assert!(documento.trailer.get(b"ID").is_ok());
+        let id_cifrado = documento
+            .trailer
+            .get(b"Encrypt")
+            .unwrap()
+            .as_reference()
+            .unwrap();
+        let diccionario = documento.get_dictionary(id_cifrado).unwrap();
+        assert_eq!(diccionario.get(b"V").unwrap().as_i64().unwrap(), 5);
+        assert_eq!(cadena(diccionario, "U").len(), 48);
+
+        // El vector inicial va delante y el relleno completa el último bloque
+        let titulo = documento
+            .get_dictionary(id_info)
+            .unwrap()
+            .get(b"Title")
+            .unwrap();
+        assert_eq!(titulo.as_str().unwrap().len(), 16 + 16);
+        let contenido = &documento
+            .get_object(id_flujo)
+            .unwrap()
+            .as_stream()
+            .unwrap()
+            .content;
+        assert_eq!(contenido.len(), 16 + 16);
+        assert!(!contenido.windows(4).any(|v| v == b"hola"));
+    }
+
+    #[test]
+    fn reconoce_los_permisos_por_nombre() {
+        assert_eq!(Permiso::desde_texto("copiar").unwrap(), Permiso::Copiar);
+        assert!(Permiso::desde_texto("borrar")
+            .unwrap_err()
+            .contains("borrar"));
+        assert_eq!(valor_permisos(&[]), 0xFFFF_F0C0);
+    }
+}
@@ -1,19 +1,188 @@
-use std::time::Duration;
+use std::{env, fs, time::Duration};
 
-use reqwest::{header, Client};
+use reqwest::{header, Certificate, Client, NoProxy, Proxy};
 
-pub fn nuevo_cliente_http() -> Client {
+use super::configuracion::Configuracion;
+
+// Sin proxy configurado reqwest ya respeta HTTPS_PROXY, HTTP_PROXY y NO_PROXY del entorno
+pub fn nuevo_cliente_http(configuracion: &Configuracion) -> Result<Client, String> {
+    crear_cliente_http(configuracion, |nombre| env::var(nombre).ok())
+}
+
+// Recibe cómo leer las variables de entorno para poder probarlo sin modificar las del proceso
+fn crear_cliente_http(
+    configuracion: &Configuracion,
+    variable_entorno: impl Fn(&str) -> Option<String>,
+) -> Result<Client, String> {
     let mut cabeceras = header::HeaderMap::new();
-    cabeceras.insert(
-        header::USER_AGENT,
-        header::HeaderValue::from_static(
-            "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0",
-        ),
-    );
-
-    reqwest::ClientBuilder::new()
-        .timeout(Duration::from_secs(14))
-        .default_headers(cabeceras)
-        .build()
-        .expect("ERROR FATAL: no se ha podido crear el client http")
+    let agente_usuario = match header::HeaderValue::from_str(&configuracion.agente_usuario) {
+        Err(error) => {
+            let mensaje_error = format!(
+                "el agente de usuario {} no es válido {}",
+                configuracion.agente_usuario, error
+            );
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    cabeceras.insert(header::USER_AGENT, agente_usuario);
+
+    let mut constructor = reqwest::ClientBuilder::new()
+        .connect_timeout(Duration::from_secs(configuracion.tiempo_conexion))
+        .read_timeout(Duration::from_secs(configuracion.tiempo_lectura))
+        .default_headers(cabeceras);
+    if !configuracion.http2 {
+        constructor = constructor.http1_only();
+    }
+    if let Some(direccion_proxy) = &configuracion.proxy {
+        let proxy = match Proxy::all(direccion_proxy) {
+            Err(error) => {
+                let mensaje_error = format!("el proxy {} no es válido {}", direccion_proxy, error);
+                return Err(mensaje_error);
+            }
+            Ok(ok) => ok,
+        };
+        // Un proxy explícito desactiva la lectura del entorno, también la de NO_PROXY
+        let excepciones = match &configuracion.sin_proxy {
+            Some(lista) => NoProxy::from_string(lista),
+            None => NoProxy::from_env(),
+        };
+        constructor = constructor.proxy(proxy.no_proxy(excepciones));
+    } else if let Some(lista) = &configuracion.sin_proxy {
+        // reqwest deja de leer el entorno en cuanto se le da un proxy, así que para aplicar
+        // sin_proxy a los del entorno hay que leerlos aquí
+        for proxy in proxies_entorno(&variable_entorno)? {
+            constructor = constructor.proxy(proxy.no_proxy(NoProxy::from_string(lista)));
+        }
+    }
+    for ruta in &configuracion.certificados_ca {
+        let contenido = match fs::read(ruta) {
+            Err(error) => {
+                let mensaje_error = format!(
+                    "no se ha podido leer el certificado {} {}",
+                    ruta.display(),
+                    error
+                );
+                return Err(mensaje_error);
+            }
+            Ok(ok) => ok,
+        };
+        let certificados = match Certificate::from_pem_bundle(&contenido) {
+            Err(error) => {
+                let mensaje_error = format!(
+                    "el archivo {} no contiene certificados PEM válidos {}",
+                    ruta.display(),
+                    error
+                );
+                return Err(mensaje_error);
+            }
+            Ok(ok) => ok,
+        };
+        for certificado in certificados {
+            constructor = constructor.add_root_certificate(certificado);
+        }
+    }
+    match constructor.build() {
+        Err(error) => Err(format!("no se ha podido crear el cliente http {}", error)),
+        Ok(ok) => Ok(ok),
+    }
+}
+
+// Las mismas variables que lee reqwest, con la misma preferencia de las mayúsculas
+fn proxies_entorno(
+    variable_entorno: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<Proxy>, String> {
+    let mut proxies = Vec::new();
+    for (variables, esquema) in [
+        (["HTTPS_PROXY", "https_proxy"], "https"),
+        (["HTTP_PROXY", "http_proxy"], "http"),
+        (["ALL_PROXY", "all_proxy"], "all"),
+    ] {
+        let Some(direccion) = variables
+            .iter()
+            .find_map(|v| variable_entorno(v).filter(|d| !d.is_empty()))
+        else {
+            continue;
+        };
+        let proxy = match esquema {
+            "https" => Proxy::https(&direccion),
+            "http" => Proxy::http(&direccion),
+            _ => Proxy::all(&direccion),
+        };
+        match proxy {
+            Err(error) => {
+                let mensaje_error = format!(
+                    "el proxy {} de {} no es válido {}",
+                    direccion, variables[0], error
+                );
+                return Err(mensaje_error);
+            }
+            Ok(ok) => proxies.push(ok),
+        }
+    }
+    Ok(proxies)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    // Servidor que responde a todo con el mismo texto, sirve de destino y de proxy
+    async fn servidor_fijo(cuerpo: &'static str) -> String {
+        let escucha = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let direccion = escucha.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut conexion, _)) = escucha.accept().await {
+                let mut peticion = [0; 4096];
+                let _ = conexion.read(&mut peticion).await;
+                let respuesta = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    cuerpo.len(),
+                    cuerpo
+                );
+                let _ = conexion.write_all(respuesta.as_bytes()).await;
+            }
+        });
+        format!("http://{}", direccion)
+    }
+
+    async fn pedir(cliente: &Client, url: &str) -> String {
+        cliente.get(url).send().await.unwrap().text().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn aplica_sin_proxy_al_proxy_configurado_y_al_del_entorno() {
+        let destino = servidor_fijo("directo").await;
+        let proxy = servidor_fijo("proxy").await;
+        let sin_entorno = |_: &str| None;
+
+        let mut configuracion = Configuracion {
+            proxy: Some(proxy.to_owned()),
+            ..Configuracion::default()
+        };
+        let cliente = crear_cliente_http(&configuracion, sin_entorno).unwrap();
+        assert_eq!(pedir(&cliente, &destino).await, "proxy");
+        configuracion.sin_proxy = Some(String::from("127.0.0.1"));
+        let cliente = crear_cliente_http(&configuracion, sin_entorno).unwrap();
+        assert_eq!(pedir(&cliente, &destino).await, "directo");
+
+        // Solo sin_proxy: se aplica al proxy del entorno en lugar de ignorarse
+        let entorno = |nombre: &str| (nombre == "HTTP_PROXY").then(|| proxy.to_owned());
+        configuracion.proxy = None;
+        let cliente = crear_cliente_http(&configuracion, entorno).unwrap();
+        assert_eq!(pedir(&cliente, &destino).await, "directo");
+        configuracion.sin_proxy = Some(String::from("otro.local"));
+        let cliente = crear_cliente_http(&configuracion, entorno).unwrap();
+        assert_eq!(pedir(&cliente, &destino).await, "proxy");
+
+        let entorno_erroneo = |nombre: &str| (nombre == "HTTPS_PROXY").then(|| String::from("::"));
+        assert!(crear_cliente_http(&configuracion, entorno_erroneo)
+            .unwrap_err()
+            .contains("HTTPS_PROXY"));
+    }
 }
@@ -0,0 +1,431 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use zip::ZipArchive;
+
+use super::{descargar::InformeDescargas, individuales, paquete};
+
+// Documento tal como aparece en un informe JSON o en un manifiesto, identificado por su
+// identificador del BOE o, si no lo tiene, por el nombre de su copia individual
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentoComparado {
+    pub identificador: String,
+    pub titulo: String,
+    pub seccion: String,
+    pub sha256: String,
+}
+
+#[derive(Default)]
+pub struct Comparacion {
+    pub añadidos: Vec<DocumentoComparado>,
+    pub eliminados: Vec<DocumentoComparado>,
+    // Mismo identificador con distinto contenido, antes y después
+    pub modificados: Vec<(DocumentoComparado, DocumentoComparado)>,
+    pub sin_cambios: usize,
+}
+
+impl Comparacion {
+    pub fn hay_diferencias(&self) -> bool {
+        !self.añadidos.is_empty() || !self.eliminados.is_empty() || !self.modificados.is_empty()
+    }
+}
+
+// Modo comparar: muestra lo que cambia de una ejecución o de un día a otro
+pub fn comparar_archivos(antes: &Path, despues: &Path) -> Result<(), String> {
+    let documentos_antes = leer_documentos(antes)?;
+    let documentos_despues = leer_documentos(despues)?;
+    let comparacion = comparar_documentos(&documentos_antes, &documentos_despues);
+    println!(
+        "{} ({} documentos) -> {} ({} documentos)",
+        antes.display(),
+        documentos_antes.len(),
+        despues.display(),
+        documentos_despues.len()
+    );
+    for documento in &comparacion.añadidos {
+        println!("+ {}", descripcion(documento));
+    }
+    for documento in &comparacion.eliminados {
+        println!("- {}", descripcion(documento));
+    }
+    for (anterior, actual) in &comparacion.modificados {
+        println!(
+            "~ {} (sha256 {} -> {})",
+            descripcion(actual),
+            &anterior.sha256[..anterior.sha256.len().min(12)],
+            &actual.sha256[..actual.sha256.len().min(12)]
+        );
+    }
+    println!(
+        "{} añadidos, {} eliminados, {} modificados y {} sin cambios",
+        comparacion.añadidos.len(),
+        comparacion.eliminados.len(),
+        comparacion.modificados.len(),
+        comparacion.sin_cambios
+    );
+    // Entre días distintos casi todo se añade o se elimina, lo útil es el recuento por sección
+    for (seccion, (cuenta_antes, cuenta_despues)) in
+        recuento_secciones(&documentos_antes, &documentos_despues)
+    {
+        if cuenta_antes != cuenta_despues {
+            println!(
+                "{}: {} -> {} documentos",
+                seccion, cuenta_antes, cuenta_despues
+            );
+        }
+    }
+    Ok(())
+}
+
+// Admite el informe JSON, el ZIP empaquetado o su manifiesto
+pub fn leer_documentos(ruta: &Path) -> Result<Vec<DocumentoComparado>, String> {
+    let contenido = match fs::read(ruta) {
+        Err(error) => {
+            let mensaje_error = format!("no se ha podido leer {} {}", ruta.display(), error);
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    let extension = ruta
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let documentos = match extension.as_str() {
+        "json" => documentos_informe(&contenido)?,
+        "zip" => documentos_paquete(contenido)?,
+        _ => documentos_manifiesto(&String::from_utf8_lossy(&contenido)),
+    };
+    Ok(sin_repetidos(documentos))
+}
+
+pub fn documentos_informe(contenido: &[u8]) -> Result<Vec<DocumentoComparado>, String> {
+    let informe = match serde_json::from_slice::<InformeDescargas>(contenido) {
+        Err(error) => {
+            let mensaje_error = format!("el informe no es válido {}", error);
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    Ok(informe
+        .documentos
+        .iter()
+        .map(|enlace| {
+            // La misma ruta que tendría en el manifiesto, para poder comparar uno con otro
+            let ruta = individuales::ruta_relativa(enlace);
+            let archivo = ruta.file_stem().unwrap_or_default().to_string_lossy();
+            DocumentoComparado {
+                identificador: identificador_archivo(&archivo).to_string(),
+                titulo: enlace.titulo.to_owned(),
+                seccion: ruta.parent().unwrap_or(Path::new("")).display().to_string(),
+                sha256: enlace.sha256.to_owned(),
+            }
+        })
+        .collect())
+}
+
+// Solo cuentan los documentos originales, el PDF unido cambia en cada ejecución
+pub fn documentos_manifiesto(texto: &str) -> Vec<DocumentoComparado> {
+    let mut documentos = Vec::new();
+    for linea in texto.lines() {
+        let Some((sha256, nombre)) = linea.split_once("  ") else {
+            continue;
+        };
+        let Some(ruta) = nombre.strip_prefix("documentos/") else {
+            continue;
+        };
+        // documentos/<sección>/<identificador>_<título>.pdf
+        let (seccion, archivo) = ruta.rsplit_once('/').unwrap_or(("", ruta));
+        let archivo = archivo.strip_suffix(".pdf").unwrap_or(archivo);
+        documentos.push(DocumentoComparado {
+            identificador: identificador_archivo(archivo).to_string(),
+            titulo: String::new(),
+            seccion: seccion.to_string(),
+            sha256: sha256.to_string(),
+        });
+    }
+    documentos
+}
+
+// <identificador>_<título> o solo el título, sin la extensión. Sin identificador se usa el nombre
+// entero, que no lleva guiones
+fn identificador_archivo(archivo: &str) -> &str {
+    match archivo.split_once('_') {
+        Some((identificador, _)) if identificador.contains('-') => identificador,
+        _ => archivo,
+    }
+}
+
+// Los informes del paquete tienen los títulos, el manifiesto solo se usa si no hay ninguno
+fn documentos_paquete(contenido: Vec<u8>) -> Result<Vec<DocumentoComparado>, String> {
+    let mut archivo_zip = match ZipArchive::new(Cursor::new(contenido)) {
+        Err(error) => {
+            let mensaje_error = format!("el paquete no es un ZIP válido {}", error);
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    let nombres = archivo_zip
+        .file_names()
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>();
+    let mut documentos = Vec::new();
+    for nombre in nombres.iter().filter(|n| n.ends_with("_informe.json")) {
+        documentos.extend(documentos_informe(&leer_entrada(
+            &mut archivo_zip,
+            nombre,
+        )?)?);
+    }
+    if documentos.is_empty() {
+        let manifiesto = leer_entrada(&mut archivo_zip, paquete::NOMBRE_MANIFIESTO)?;
+        documentos = documentos_manifiesto(&String::from_utf8_lossy(&manifiesto));
+    }
+    Ok(documentos)
+}
+
+fn leer_entrada(
+    archivo_zip: &mut ZipArchive<Cursor<Vec<u8>>>,
+    nombre: &str,
+) -> Result<Vec<u8>, String> {
+    let mut contenido = Vec::new();
+    let resultado = archivo_zip
+        .by_name(nombre)
+        .map_err(|e| e.to_string())
+        .and_then(|mut entrada| {
+            entrada
+                .read_to_end(&mut contenido)
+                .map_err(|e| e.to_string())
+        });
+    match resultado {
+        Err(error) => {
+            let mensaje_error = format!("no se ha podido leer {} del paquete {}", nombre, error);
+            Err(mensaje_error)
+        }
+        Ok(_) => Ok(contenido),
+    }
+}
+
+// Un boletín dividido repite cada documento en el informe completo y en el de su parte. Solo se
+// quitan las copias exactas, dos documentos distintos pueden compartir el nombre por el título
+fn sin_repetidos(documentos: Vec<DocumentoComparado>) -> Vec<DocumentoComparado> {
+    let mut resultado: Vec<DocumentoComparado> = Vec::new();
+    for documento in documentos {
+        if !resultado.contains(&documento) {
+            resultado.push(documento);
+        }
+    }
+    resultado
+}
+
+pub fn comparar_documentos(
+    antes: &[DocumentoComparado],
+    despues: &[DocumentoComparado],
+) -> Comparacion {
+    // Documentos de antes aún sin emparejar, agrupados porque varios pueden tener el mismo
+    // identificador si no vienen del BOE y comparten título
+    let mut pendientes: HashMap<&str, Vec<&DocumentoComparado>> = HashMap::new();
+    for documento in antes {
+        pendientes
+            .entry(documento.identificador.as_str())
+            .or_default()
+            .push(documento);
+    }
+    let mut comparacion = Comparacion::default();
+    // Primero los que no han cambiado, para no emparejar dos distintos si hay uno igual
+    let mut sin_pareja = Vec::new();
+    for documento in despues {
+        let candidatos = pendientes
+            .entry(documento.identificador.as_str())
+            .or_default();
+        match candidatos.iter().position(|d| d.sha256 == documento.sha256) {
+            None => sin_pareja.push(documento),
+            Some(posicion) => {
+                candidatos.remove(posicion);
+                comparacion.sin_cambios += 1;
+            }
+        }
+    }
+    for documento in sin_pareja {
+        let candidatos = pendientes
+            .entry(documento.identificador.as_str())
+            .or_default();
+        if candidatos.is_empty() {
+            comparacion.añadidos.push(documento.clone());
+            continue;
+        }
+        let anterior = candidatos.remove(0);
+        // Un resumen vacío no permite saber si ha cambiado
+        match anterior.sha256.is_empty() || documento.sha256.is_empty() {
+            true => comparacion.sin_cambios += 1,
+            false => comparacion
+                .modificados
+                .push((anterior.clone(), documento.clone())),
+        }
+    }
+    comparacion.eliminados = antes
+        .iter()
+        .filter(|d| {
+            pendientes[d.identificador.as_str()]
+                .iter()
+                .any(|p| std::ptr::eq(*p, *d))
+        })
+        .cloned()
+        .collect();
+    comparacion
+}
+
+fn recuento_secciones(
+    antes: &[DocumentoComparado],
+    despues: &[DocumentoComparado],
+) -> BTreeMap<String, (usize, usize)> {
+    let mut recuento = BTreeMap::new();
+    for documento in antes {
+        recuento
+            .entry(documento.seccion.to_owned())
+            .or_insert((0, 0))
+            .0 += 1;
+    }
+    for documento in despues {
+        recuento
+            .entry(documento.seccion.to_owned())
+            .or_insert((0, 0))
+            .1 += 1;
+    }
+    recuento
+}
+
+fn descripcion(documento: &DocumentoComparado) -> String {
+    match documento.titulo.is_empty() {
+        true => format!("{} [{}]", documento.identificador, documento.seccion),
+        false => format!(
+            "{} [{}] {}",
+            documento.identificador, documento.seccion, documento.titulo
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controlador_boe::descargar::EnlacePDF;
+
+    fn documento(identificador: &str, sha256: &str) -> DocumentoComparado {
+        DocumentoComparado {
+            identificador: String::from(identificador),
+            titulo: String::new(),
+            seccion: String::from("i_disposiciones_generales"),
+            sha256: String::from(sha256),
+        }
+    }
+
+    #[test]
+    fn distingue_añadidos_eliminados_y_modificados() {
+        let antes = vec![
+            documento("A", "1"),
+            documento("B", "2"),
+            documento("C", "3"),
+        ];
+        let despues = vec![
+            documento("A", "1"),
+            documento("C", "4"),
+            documento("D", "5"),
+        ];
+        let comparacion = comparar_documentos(&antes, &despues);
+        assert_eq!(comparacion.añadidos, vec![documento("D", "5")]);
+        assert_eq!(comparacion.eliminados, vec![documento("B", "2")]);
+        assert_eq!(
+            comparacion.modificados,
+            vec![(documento("C", "3"), documento("C", "4"))]
+        );
+        assert_eq!(comparacion.sin_cambios, 1);
+        assert!(comparacion.hay_diferencias());
+        assert!(!comparar_documentos(&antes, &antes).hay_diferencias());
+    }
+
+    #[test]
+    fn lee_los_documentos_del_manifiesto() {
+        let manifiesto = "aa11  07_12_2024_boe_completo.pdf\n\
+            bb22  documentos/i_disposiciones_generales/BOE-A-2024-25512_real_decreto.pdf\n\
+            cc33  documentos/edictos/sin_identificador.pdf\n";
+        let documentos = documentos_manifiesto(manifiesto);
+        assert_eq!(documentos.len(), 2);
+        assert_eq!(documentos[0].identificador, "BOE-A-2024-25512");
+        assert_eq!(documentos[0].seccion, "i_disposiciones_generales");
+        assert_eq!(documentos[0].sha256, "bb22");
+        assert_eq!(documentos[1].identificador, "sin_identificador");
+    }
+
+    #[test]
+    fn el_informe_y_el_manifiesto_usan_los_mismos_identificadores() {
+        let enlaces = [
+            (
+                "https://boe.es/boe/dias/2024/12/07/pdfs/BOE-A-2024-25512.pdf",
+                "Real Decreto 1155/2024",
+                "I. Disposiciones generales",
+                "aa",
+            ),
+            // Sin identificador del BOE en la url, con el mismo título y distinto contenido
+            (
+                "https://boe.es/ver.php?id=1",
+                "Anuncio de notificación",
+                "",
+                "bb",
+            ),
+            (
+                "https://boe.es/ver.php?id=2",
+                "Anuncio de notificación",
+                "",
+                "cc",
+            ),
+        ]
+        .map(|(url, titulo, seccion, sha256)| EnlacePDF {
+            apartado: String::from("Notificaciones"),
+            url: String::from(url),
+            titulo: String::from(titulo),
+            seccion: String::from(seccion),
+            sha256: String::from(sha256),
+            ..EnlacePDF::default()
+        });
+        let informe = InformeDescargas {
+            fecha: String::from("07/12/2024"),
+            total_documentos: enlaces.len(),
+            documentos: enlaces.to_vec(),
+        };
+        let manifiesto = enlaces
+            .iter()
+            .map(|e| {
+                format!(
+                    "{}  documentos/{}\n",
+                    e.sha256,
+                    individuales::ruta_relativa(e).display()
+                )
+            })
+            .collect::<String>();
+
+        let del_informe =
+            sin_repetidos(documentos_informe(&serde_json::to_vec(&informe).unwrap()).unwrap());
+        let del_manifiesto = sin_repetidos(documentos_manifiesto(&manifiesto));
+        assert_eq!(del_informe.len(), 3);
+        assert_eq!(del_informe[0].identificador, "BOE-A-2024-25512");
+        assert_eq!(
+            del_informe[1].identificador,
+            "verphp_anuncio_de_notificacion"
+        );
+        let comparacion = comparar_documentos(&del_informe, &del_manifiesto);
+        assert!(!comparacion.hay_diferencias());
+        assert_eq!(comparacion.sin_cambios, 3);
+
+        // Si cambia uno de los dos con el mismo nombre solo se marca ese
+        let mut cambiado = del_manifiesto.clone();
+        cambiado[2].sha256 = String::from("dd");
+        let comparacion = comparar_documentos(&del_informe, &cambiado);
+        assert_eq!(
+            comparacion.modificados,
+            vec![(del_informe[2].clone(), cambiado[2].clone())]
+        );
+        assert_eq!(comparacion.sin_cambios, 2);
+    }
+}
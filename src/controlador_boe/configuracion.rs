@@ -0,0 +1,554 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    argumentos::Argumentos,
+    cifrar::{self, Permiso},
+    descargar,
+    dividir::Division,
+    filtros::Filtros,
+};
+
+pub const APARTADOS: [&str; 3] = ["disposiciones", "notificaciones", "edictos"];
+const NOMBRE_ARCHIVO_CONFIGURACION: &str = "config.toml";
+
+// Una clave desconocida en el archivo suele ser un error de escritura, mejor avisar que ignorarla
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Configuracion {
+    // Réplica, también publicada en una subruta, o servidor local en lugar de https://boe.es
+    pub url_base: String,
+    pub directorio_salida: PathBuf,
+    // Admite {dia}, {mes} y {año}, los sufijos de cada archivo se añaden al final
+    pub plantilla_nombre: String,
+    pub apartados: Vec<String>,
+    pub espera: u64,
+    pub reintentos: u32,
+    pub sondeo: u64,
+    pub proxy: Option<String>,
+    // Lista separada por comas con el mismo formato que NO_PROXY
+    pub sin_proxy: Option<String>,
+    pub certificados_ca: Vec<PathBuf>,
+    pub agente_usuario: String,
+    pub tiempo_conexion: u64,
+    pub tiempo_lectura: u64,
+    pub http2: bool,
+    // Intenta reconstruir la tabla de referencias de los PDF que lopdf no consigue leer
+    pub reparar_pdf: bool,
+    // Comparte entre todos los documentos las fuentes e imágenes idénticas
+    pub optimizar_recursos: bool,
+    // Genera el PDF unido conforme a PDF/A-2b para su archivo a largo plazo
+    pub pdfa: bool,
+    // Pie en cada página con el identificador del documento, su sección y el número de página
+    pub estampar_paginas: bool,
+    // Un PDF y un informe por apartado, sección o departamento en lugar del boletín completo
+    pub division: Division,
+    // Límites de cada volumen, sin partir nunca una disposición entre dos
+    pub volumen_maximo_mb: Option<u64>,
+    pub volumen_maximo_paginas: Option<usize>,
+    // Guarda además cada PDF descargado en una carpeta por sección
+    pub guardar_individuales: bool,
+    // Un único ZIP por día con los PDF, los informes y un manifiesto con sus resúmenes
+    pub empaquetar: bool,
+    // Comprueba la firma electrónica de cada PDF descargado contra el almacén de confianza
+    pub verificar_firmas: bool,
+    // Descarga el XML de cada documento y guarda sus metadatos en el informe y en el paquete
+    pub metadatos_xml: bool,
+    // Certificados PEM o DER, o directorios con ellos, en los que termina la cadena de la firma
+    pub almacen_confianza: Vec<PathBuf>,
+    // Certificado PKCS#12 con el que se firma cada PDF unido
+    pub certificado_firma: Option<PathBuf>,
+    // No se muestra con config mostrar, mejor en BOE_COMPLETO_CLAVE_FIRMA que en el archivo
+    #[serde(skip_serializing)]
+    pub clave_firma: Option<String>,
+    // Cifra con AES-256 cada PDF unido, hace falta al menos una de las dos claves
+    pub cifrar_pdf: bool,
+    // Abre el documento, vacía para que se abra sin pedir contraseña
+    #[serde(skip_serializing)]
+    pub clave_usuario_pdf: Option<String>,
+    // Levanta las restricciones, si falta se genera una aleatoria
+    #[serde(skip_serializing)]
+    pub clave_propietario_pdf: Option<String>,
+    // Lo que se permite hacer a quien abre el documento con la clave de usuario
+    pub permisos_pdf: Vec<Permiso>,
+    pub direccion_servidor: String,
+    pub filtros: Filtros,
+    #[serde(skip)]
+    pub archivo: Option<PathBuf>,
+}
+
+impl Default for Configuracion {
+    fn default() -> Self {
+        Configuracion {
+            url_base: String::from("https://boe.es"),
+            directorio_salida: descargar::directorio_ejecutable(),
+            plantilla_nombre: String::from("{dia}_{mes}_{año}_boe_completo"),
+            apartados: APARTADOS.iter().map(|a| a.to_string()).collect(),
+            espera: 3,
+            reintentos: 0,
+            sondeo: 600,
+            proxy: None,
+            sin_proxy: None,
+            certificados_ca: Vec::new(),
+            agente_usuario: String::from(
+                "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0",
+            ),
+            tiempo_conexion: 14,
+            tiempo_lectura: 14,
+            http2: false,
+            reparar_pdf: false,
+            optimizar_recursos: true,
+            pdfa: false,
+            estampar_paginas: false,
+            division: Division::Ninguna,
+            volumen_maximo_mb: None,
+            volumen_maximo_paginas: None,
+            guardar_individuales: false,
+            empaquetar: false,
+            verificar_firmas: false,
+            metadatos_xml: false,
+            almacen_confianza: Vec::new(),
+            certificado_firma: None,
+            clave_firma: None,
+            cifrar_pdf: false,
+            clave_usuario_pdf: None,
+            clave_propietario_pdf: None,
+            permisos_pdf: cifrar::permisos_predeterminados(),
+            direccion_servidor: String::from("127.0.0.1:8080"),
+            filtros: Filtros::default(),
+            archivo: None,
+        }
+    }
+}
+
+impl Configuracion {
+    pub fn incluye_apartado(&self, apartado: &str) -> bool {
+        self.apartados.iter().any(|a| a == apartado)
+    }
+}
+
+// Prioridad de mayor a menor: argumentos, variables de entorno, archivo de configuración
+// y valores por defecto
+pub fn cargar_configuracion(argumentos: &Argumentos) -> Result<Configuracion, String> {
+    cargar_configuracion_con_entorno(argumentos, |nombre| env::var(nombre).ok())
+}
+
+// Recibe cómo leer las variables de entorno para poder probar la prioridad sin modificar las
+// del proceso
+fn cargar_configuracion_con_entorno(
+    argumentos: &Argumentos,
+    variable_entorno: impl Fn(&str) -> Option<String>,
+) -> Result<Configuracion, String> {
+    let mut configuracion = match &argumentos.ruta_configuracion {
+        Some(ruta) => leer_archivo_configuracion(ruta)?,
+        None => match ruta_configuracion_predeterminada() {
+            Some(ruta) if ruta.exists() => leer_archivo_configuracion(&ruta)?,
+            _ => Configuracion::default(),
+        },
+    };
+    aplicar_variables_entorno(&mut configuracion, variable_entorno)?;
+    aplicar_argumentos(&mut configuracion, argumentos);
+    descargar::unir_url_base(&configuracion.url_base, "")?;
+    // Sin espera el modo vigilancia consultaría el BOE sin pausa
+    if configuracion.sondeo == 0 {
+        return Err(String::from("el sondeo debe ser de al menos un segundo"));
+    }
+    // El sello usa Helvetica sin incrustar, que PDF/A no admite
+    if configuracion.pdfa && configuracion.estampar_paginas {
+        return Err(String::from(
+            "no se pueden estampar las páginas de un PDF/A, la fuente del sello no va incrustada",
+        ));
+    }
+    if configuracion.cifrar_pdf {
+        if configuracion.clave_usuario_pdf.is_none()
+            && configuracion.clave_propietario_pdf.is_none()
+        {
+            return Err(String::from(
+                "para cifrar el PDF hace falta la clave de usuario o la de propietario",
+            ));
+        }
+        // PDF/A prohíbe el cifrado y la firma se calcula sobre el archivo ya serializado
+        if configuracion.pdfa {
+            return Err(String::from("no se puede cifrar un PDF/A"));
+        }
+        if configuracion.certificado_firma.is_some() {
+            return Err(String::from(
+                "no se puede firmar un PDF cifrado, hay que elegir entre firmar y cifrar",
+            ));
+        }
+    }
+    for apartado in &configuracion.apartados {
+        if !APARTADOS.contains(&apartado.as_str()) {
+            return Err(format!(
+                "el apartado {} no es válido, se esperaba uno de {}",
+                apartado,
+                APARTADOS.join(", ")
+            ));
+        }
+    }
+    Ok(configuracion)
+}
+
+pub fn mostrar_configuracion(configuracion: &Configuracion) -> Result<(), String> {
+    let contenido = match toml::to_string_pretty(configuracion) {
+        Err(error) => {
+            let mensaje_error = format!("no se ha podido serializar la configuración {}", error);
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    match &configuracion.archivo {
+        None => println!("# sin archivo de configuración"),
+        Some(ruta) => println!("# archivo de configuración: {}", ruta.display()),
+    }
+    print!("{}", contenido);
+    Ok(())
+}
+
+pub fn ruta_configuracion_predeterminada() -> Option<PathBuf> {
+    let directorio = match env::var_os("XDG_CONFIG_HOME") {
+        Some(directorio) => PathBuf::from(directorio),
+        None => match env::var_os("APPDATA") {
+            Some(directorio) => PathBuf::from(directorio),
+            None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        },
+    };
+    Some(
+        directorio
+            .join(env!("CARGO_PKG_NAME"))
+            .join(NOMBRE_ARCHIVO_CONFIGURACION),
+    )
+}
+
+fn leer_archivo_configuracion(ruta: &Path) -> Result<Configuracion, String> {
+    let contenido = match fs::read_to_string(ruta) {
+        Err(error) => {
+            let mensaje_error = format!(
+                "no se ha podido leer el archivo de configuración {} {}",
+                ruta.display(),
+                error
+            );
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    let mut configuracion = match toml::from_str::<Configuracion>(&contenido) {
+        Err(error) => {
+            let mensaje_error = format!(
+                "el archivo de configuración {} no es válido {}",
+                ruta.display(),
+                error
+            );
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    configuracion.archivo = Some(ruta.to_owned());
+    Ok(configuracion)
+}
+
+fn aplicar_variables_entorno(
+    configuracion: &mut Configuracion,
+    variable_entorno: impl Fn(&str) -> Option<String>,
+) -> Result<(), String> {
+    let variable_numerica = |nombre: &str| variable_entorno_numerica(nombre, &variable_entorno);
+    if let Some(url_base) = variable_entorno("BOE_COMPLETO_URL_BASE") {
+        configuracion.url_base = url_base;
+    }
+    if let Some(espera) = variable_numerica("BOE_COMPLETO_ESPERA")? {
+        configuracion.espera = espera;
+    }
+    if let Some(sondeo) = variable_numerica("BOE_COMPLETO_SONDEO")? {
+        configuracion.sondeo = sondeo;
+    }
+    if let Some(reintentos) = variable_numerica("BOE_COMPLETO_REINTENTOS")? {
+        configuracion.reintentos = match u32::try_from(reintentos) {
+            Err(_) => {
+                let mensaje_error = format!(
+                    "el valor {} de BOE_COMPLETO_REINTENTOS es demasiado grande",
+                    reintentos
+                );
+                return Err(mensaje_error);
+            }
+            Ok(ok) => ok,
+        };
+    }
+    if let Some(directorio) = variable_entorno("BOE_COMPLETO_SALIDA") {
+        configuracion.directorio_salida = PathBuf::from(directorio);
+    }
+    if let Some(plantilla) = variable_entorno("BOE_COMPLETO_PLANTILLA") {
+        configuracion.plantilla_nombre = plantilla;
+    }
+    if let Some(proxy) = variable_entorno("BOE_COMPLETO_PROXY") {
+        configuracion.proxy = Some(proxy);
+    }
+    if let Some(sin_proxy) = variable_entorno("BOE_COMPLETO_SIN_PROXY") {
+        configuracion.sin_proxy = Some(sin_proxy);
+    }
+    if let Some(certificados) = variable_entorno("BOE_COMPLETO_CA") {
+        configuracion.certificados_ca = env::split_paths(&certificados).collect();
+    }
+    if let Some(agente) = variable_entorno("BOE_COMPLETO_AGENTE") {
+        configuracion.agente_usuario = agente;
+    }
+    if let Some(tiempo) = variable_numerica("BOE_COMPLETO_TIEMPO_CONEXION")? {
+        configuracion.tiempo_conexion = tiempo;
+    }
+    if let Some(tiempo) = variable_numerica("BOE_COMPLETO_TIEMPO_LECTURA")? {
+        configuracion.tiempo_lectura = tiempo;
+    }
+    if let Some(clave) = variable_entorno("BOE_COMPLETO_CLAVE_FIRMA") {
+        configuracion.clave_firma = Some(clave);
+    }
+    if let Some(clave) = variable_entorno("BOE_COMPLETO_CLAVE_USUARIO") {
+        configuracion.clave_usuario_pdf = Some(clave);
+    }
+    if let Some(clave) = variable_entorno("BOE_COMPLETO_CLAVE_PROPIETARIO") {
+        configuracion.clave_propietario_pdf = Some(clave);
+    }
+    Ok(())
+}
+
+fn variable_entorno_numerica(
+    nombre: &str,
+    variable_entorno: impl Fn(&str) -> Option<String>,
+) -> Result<Option<u64>, String> {
+    let Some(valor) = variable_entorno(nombre) else {
+        return Ok(None);
+    };
+    match valor.parse::<u64>() {
+        Err(_) => {
+            let mensaje_error = format!(
+                "el valor {} de la variable {} no es un número",
+                valor, nombre
+            );
+            Err(mensaje_error)
+        }
+        Ok(ok) => Ok(Some(ok)),
+    }
+}
+
+fn aplicar_argumentos(configuracion: &mut Configuracion, argumentos: &Argumentos) {
+    if let Some(url_base) = &argumentos.url_base {
+        configuracion.url_base = url_base.to_owned();
+    }
+    if let Some(directorio) = &argumentos.directorio_salida {
+        configuracion.directorio_salida = directorio.to_owned();
+    }
+    if let Some(plantilla) = &argumentos.plantilla_nombre {
+        configuracion.plantilla_nombre = plantilla.to_owned();
+    }
+    if !argumentos.apartados.is_empty() {
+        configuracion.apartados = argumentos.apartados.clone();
+    }
+    if let Some(espera) = argumentos.espera {
+        configuracion.espera = espera;
+    }
+    if let Some(reintentos) = argumentos.reintentos {
+        configuracion.reintentos = reintentos;
+    }
+    if let Some(proxy) = &argumentos.proxy {
+        configuracion.proxy = Some(proxy.to_owned());
+    }
+    if let Some(sin_proxy) = &argumentos.sin_proxy {
+        configuracion.sin_proxy = Some(sin_proxy.to_owned());
+    }
+    if !argumentos.certificados_ca.is_empty() {
+        configuracion.certificados_ca = argumentos.certificados_ca.clone();
+    }
+    if let Some(agente) = &argumentos.agente_usuario {
+        configuracion.agente_usuario = agente.to_owned();
+    }
+    if let Some(tiempo) = argumentos.tiempo_conexion {
+        configuracion.tiempo_conexion = tiempo;
+    }
+    if let Some(tiempo) = argumentos.tiempo_lectura {
+        configuracion.tiempo_lectura = tiempo;
+    }
+    if let Some(http2) = argumentos.http2 {
+        configuracion.http2 = http2;
+    }
+    if let Some(reparar_pdf) = argumentos.reparar_pdf {
+        configuracion.reparar_pdf = reparar_pdf;
+    }
+    if let Some(optimizar_recursos) = argumentos.optimizar_recursos {
+        configuracion.optimizar_recursos = optimizar_recursos;
+    }
+    if let Some(pdfa) = argumentos.pdfa {
+        configuracion.pdfa = pdfa;
+    }
+    if let Some(estampar_paginas) = argumentos.estampar_paginas {
+        configuracion.estampar_paginas = estampar_paginas;
+    }
+    if let Some(division) = argumentos.division {
+        configuracion.division = division;
+    }
+    if let Some(megabytes) = argumentos.volumen_maximo_mb {
+        configuracion.volumen_maximo_mb = Some(megabytes);
+    }
+    if let Some(paginas) = argumentos.volumen_maximo_paginas {
+        configuracion.volumen_maximo_paginas = Some(paginas);
+    }
+    if let Some(guardar_individuales) = argumentos.guardar_individuales {
+        configuracion.guardar_individuales = guardar_individuales;
+    }
+    if let Some(empaquetar) = argumentos.empaquetar {
+        configuracion.empaquetar = empaquetar;
+    }
+    if let Some(verificar_firmas) = argumentos.verificar_firmas {
+        configuracion.verificar_firmas = verificar_firmas;
+    }
+    if let Some(metadatos_xml) = argumentos.metadatos_xml {
+        configuracion.metadatos_xml = metadatos_xml;
+    }
+    if !argumentos.almacen_confianza.is_empty() {
+        configuracion.almacen_confianza = argumentos.almacen_confianza.clone();
+    }
+    if let Some(certificado) = &argumentos.certificado_firma {
+        configuracion.certificado_firma = Some(certificado.to_owned());
+    }
+    if let Some(cifrar_pdf) = argumentos.cifrar_pdf {
+        configuracion.cifrar_pdf = cifrar_pdf;
+    }
+    if !argumentos.permisos_pdf.is_empty() {
+        configuracion.permisos_pdf = argumentos.permisos_pdf.clone();
+    }
+    if let Some(direccion) = &argumentos.direccion {
+        configuracion.direccion_servidor = direccion.to_owned();
+    }
+    // Cada tipo de filtro indicado por argumentos sustituye al del archivo
+    let filtros = &argumentos.filtros;
+    if !filtros.departamentos.is_empty() {
+        configuracion.filtros.departamentos = filtros.departamentos.clone();
+    }
+    if !filtros.epigrafes.is_empty() {
+        configuracion.filtros.epigrafes = filtros.epigrafes.clone();
+    }
+    if !filtros.rangos.is_empty() {
+        configuracion.filtros.rangos = filtros.rangos.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controlador_boe::argumentos::procesar_argumentos;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn cargar(
+        archivo: &str,
+        entorno: &[(&str, &str)],
+        argumentos: &[&str],
+    ) -> Result<Configuracion, String> {
+        let directorio = TempDir::new().unwrap();
+        let ruta = directorio.path().join("boe_completo.toml");
+        fs::write(&ruta, archivo).unwrap();
+        let mut linea = vec![
+            String::from("gestor_boe"),
+            String::from("--config"),
+            ruta.display().to_string(),
+        ];
+        linea.extend(argumentos.iter().map(|a| a.to_string()));
+        linea.push(String::from("07-12-2024"));
+        let argumentos = procesar_argumentos(&linea)?;
+        let entorno: HashMap<_, _> = entorno
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        cargar_configuracion_con_entorno(&argumentos, |nombre| entorno.get(nombre).cloned())
+    }
+
+    #[test]
+    fn los_argumentos_pisan_al_entorno_y_este_al_archivo() {
+        let archivo =
+            "reintentos = 1\nespera = 1\npdfa = true\nhttp2 = true\noptimizar_recursos = false\n";
+        let entorno = [
+            ("BOE_COMPLETO_REINTENTOS", "2"),
+            ("BOE_COMPLETO_ESPERA", "2"),
+        ];
+
+        let configuracion = cargar(archivo, &[], &[]).unwrap();
+        assert_eq!(configuracion.reintentos, 1);
+        assert!(configuracion.pdfa);
+
+        let configuracion = cargar(archivo, &entorno, &[]).unwrap();
+        assert_eq!(configuracion.reintentos, 2);
+        assert_eq!(configuracion.espera, 2);
+
+        let configuracion = cargar(
+            archivo,
+            &entorno,
+            &[
+                "--reintentos",
+                "3",
+                "--no-pdfa",
+                "--no-http2",
+                "--optimizar",
+            ],
+        )
+        .unwrap();
+        assert_eq!(configuracion.reintentos, 3);
+        assert_eq!(configuracion.espera, 2);
+        assert!(!configuracion.pdfa);
+        assert!(!configuracion.http2);
+        assert!(configuracion.optimizar_recursos);
+
+        let configuracion = cargar("", &[], &["--no-optimizar", "--zip"]).unwrap();
+        assert!(!configuracion.optimizar_recursos);
+        assert!(configuracion.empaquetar);
+    }
+
+    #[test]
+    fn toma_las_claves_de_cifrado_solo_del_entorno_o_del_archivo() {
+        let error = cargar("", &[], &["--cifrar", "--clave-usuario", "secreto"])
+            .err()
+            .unwrap();
+        assert!(error.contains("--clave-usuario"), "{}", error);
+        assert!(cargar("", &[], &["--sin-optimizar"]).is_err());
+
+        let entorno = [("BOE_COMPLETO_CLAVE_USUARIO", "secreto")];
+        let configuracion = cargar(
+            "clave_propietario_pdf = \"otro\"\n",
+            &entorno,
+            &["--cifrar"],
+        )
+        .unwrap();
+        assert_eq!(configuracion.clave_usuario_pdf.as_deref(), Some("secreto"));
+        assert_eq!(configuracion.clave_propietario_pdf.as_deref(), Some("otro"));
+    }
+
+    #[test]
+    fn rechaza_las_claves_desconocidas() {
+        let error = cargar("reintento = 3\n", &[], &[]).err().unwrap();
+        assert!(error.contains("reintento"), "{}", error);
+        let error = cargar("[filtros]\ndepartamento = [\"trabajo\"]\n", &[], &[])
+            .err()
+            .unwrap();
+        assert!(error.contains("departamento"), "{}", error);
+    }
+
+    #[test]
+    fn rechaza_los_valores_numericos_no_validos() {
+        let error = cargar("", &[("BOE_COMPLETO_REINTENTOS", "4294967296")], &[])
+            .err()
+            .unwrap();
+        assert!(error.contains("BOE_COMPLETO_REINTENTOS"), "{}", error);
+        let error = cargar("", &[("BOE_COMPLETO_ESPERA", "mucho")], &[])
+            .err()
+            .unwrap();
+        assert!(error.contains("BOE_COMPLETO_ESPERA"), "{}", error);
+        assert!(cargar("sondeo = 0\n", &[], &[])
+            .err()
+            .unwrap()
+            .contains("sondeo"));
+        assert!(cargar("", &[("BOE_COMPLETO_SONDEO", "0")], &[])
+            .err()
+            .unwrap()
+            .contains("sondeo"));
+    }
+}
@@ -0,0 +1,216 @@
+use lopdf::{Bookmark, Document, ObjectId};
+
+use super::{
+    descargar::EnlacePDF,
+    documento_xml::{self, ReferenciaXml},
+    filtros, individuales,
+};
+
+const PREFIJOS_CORRECCION: [&str; 2] = ["correccion de errores", "correccion de erratas"];
+
+pub fn es_correccion(titulo: &str) -> bool {
+    let titulo = filtros::normalizar(titulo);
+    PREFIJOS_CORRECCION.iter().any(|p| titulo.starts_with(p))
+}
+
+// "Corrección de errores de la Orden HAC/1460/2024, de 9 de diciembre, por la que..."
+// -> "Orden HAC/1460/2024". Sirve para encontrar el original si se publica el mismo día
+pub fn designacion_corregida(titulo: &str) -> Option<String> {
+    if !es_correccion(titulo) {
+        return None;
+    }
+    let palabras = titulo.split_whitespace().collect::<Vec<&str>>();
+    // Corrección de errores|erratas de [la|el|los|las]
+    let mut inicio = 3;
+    if palabras.get(inicio).map(|p| filtros::normalizar(p)) == Some(String::from("de")) {
+        inicio += 1;
+    }
+    if let Some(articulo) = palabras.get(inicio) {
+        if ["la", "el", "los", "las", "del"].contains(&filtros::normalizar(articulo).as_str()) {
+            inicio += 1;
+        }
+    }
+    let resto = palabras.get(inicio..)?.join(" ");
+    let mut partes = resto.split(',').map(str::trim);
+    let mut designacion = partes.next().unwrap_or_default().to_string();
+    // Sin número oficial, como en las resoluciones, la fecha sola no basta y se añade el órgano
+    if !designacion.contains('/') {
+        if let Some(organo) = partes.next() {
+            designacion = format!("{}, {}", designacion, organo);
+        }
+    }
+    match designacion.is_empty() {
+        true => None,
+        false => Some(designacion),
+    }
+}
+
+// Identificador de la disposición corregida según las referencias anteriores del XML del documento
+pub fn referencia_corregida(anteriores: &[ReferenciaXml]) -> Option<String> {
+    anteriores
+        .iter()
+        .find(|anterior| {
+            let relacion = filtros::normalizar(&anterior.relacion);
+            relacion.contains("correccion") || relacion.contains("corrige")
+        })
+        .map(|anterior| anterior.identificador.to_owned())
+}
+
+pub fn referencia_corregida_xml(xml: &str) -> Result<Option<String>, String> {
+    let documento = documento_xml::analizar(xml)?;
+    Ok(referencia_corregida(&documento_xml::referencias(
+        &documento,
+        "anteriores",
+    )))
+}
+
+// Para las correcciones sin referencia en el XML busca el original entre los demás documentos
+// del mismo boletín por el comienzo de su título
+pub fn vincular_por_titulo(enlaces: &mut [EnlacePDF]) {
+    let originales = enlaces
+        .iter()
+        .filter(|e| !es_correccion(&e.titulo))
+        .map(|e| {
+            (
+                filtros::normalizar(&e.titulo),
+                individuales::identificador_boe(&e.url),
+            )
+        })
+        .filter(|(_, identificador)| !identificador.is_empty())
+        .collect::<Vec<_>>();
+    for enlace in enlaces.iter_mut() {
+        if enlace.corrige.is_some() {
+            continue;
+        }
+        let Some(designacion) = designacion_corregida(&enlace.titulo) else {
+            continue;
+        };
+        let designacion = filtros::normalizar(&designacion);
+        if let Some((_, identificador)) = originales
+            .iter()
+            .find(|(titulo, _)| titulo.starts_with(&designacion))
+        {
+            enlace.corrige = Some(identificador.to_owned());
+        }
+    }
+}
+
+// Marcador de primer nivel de cada documento del PDF unido
+pub struct MarcadorDocumento {
+    pub identificador: String,
+    pub titulo: String,
+    pub corrige: Option<String>,
+    pub marcador: u32,
+    pub pagina: ObjectId,
+}
+
+// Cuando la corrección y el original van en el mismo PDF, cada uno lleva bajo su marcador otro
+// que lleva al primero del otro documento. Van en cursiva para distinguirlos de su índice
+pub fn añadir_referencias_cruzadas(documento: &mut Document, marcadores: &[MarcadorDocumento]) {
+    for correccion in marcadores {
+        let Some(original) = correccion
+            .corrige
+            .as_ref()
+            .and_then(|c| marcadores.iter().find(|m| &m.identificador == c))
+        else {
+            continue;
+        };
+        let corrige = Bookmark::new(
+            format!("Corrige: {}", original.titulo),
+            [0.0, 0.0, 1.0],
+            1,
+            original.pagina,
+        );
+        documento.add_bookmark(corrige, Some(correccion.marcador));
+        let corregido = Bookmark::new(
+            format!("Corregido por: {}", correccion.titulo),
+            [0.0, 0.0, 1.0],
+            1,
+            correccion.pagina,
+        );
+        documento.add_bookmark(corregido, Some(original.marcador));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XML_CORRECCION: &str =
+        include_str!("../../tests/fixtures/boe.es/diario_boe/xml/BOE-A-2024-25902.xml");
+
+    fn enlace(identificador: &str, titulo: &str) -> EnlacePDF {
+        EnlacePDF {
+            apartado: String::from("Disposiciones y anuncios"),
+            url: format!(
+                "https://boe.es/boe/dias/2024/12/12/pdfs/{}.pdf",
+                identificador
+            ),
+            titulo: String::from(titulo),
+            ..EnlacePDF::default()
+        }
+    }
+
+    #[test]
+    fn extrae_la_disposicion_corregida_del_titulo() {
+        assert_eq!(
+            designacion_corregida(
+                "Corrección de errores de la Orden HAC/1460/2024, de 9 de diciembre, por la que se aprueba el modelo 232."
+            )
+            .unwrap(),
+            "Orden HAC/1460/2024"
+        );
+        assert_eq!(
+            designacion_corregida(
+                "Corrección de erratas del Real Decreto 1155/2024, de 19 de noviembre."
+            )
+            .unwrap(),
+            "Real Decreto 1155/2024"
+        );
+        assert_eq!(
+            designacion_corregida(
+                "Corrección de errores de la Resolución de 28 de noviembre de 2024, de la Secretaría de Estado de Energía, por la que se publica el acuerdo."
+            )
+            .unwrap(),
+            "Resolución de 28 de noviembre de 2024, de la Secretaría de Estado de Energía"
+        );
+        assert_eq!(
+            designacion_corregida("Orden HAC/1460/2024, de 9 de diciembre."),
+            None
+        );
+    }
+
+    #[test]
+    fn lee_la_referencia_del_xml() {
+        assert_eq!(
+            referencia_corregida_xml(XML_CORRECCION).unwrap().unwrap(),
+            "BOE-A-2024-24800"
+        );
+        let sin_referencias = "<documento><analisis><referencias><anteriores>\
+            <anterior referencia=\"BOE-A-2020-1\"><palabra>DEROGA</palabra></anterior>\
+            </anteriores></referencias></analisis></documento>";
+        assert_eq!(referencia_corregida_xml(sin_referencias).unwrap(), None);
+    }
+
+    #[test]
+    fn vincula_con_el_original_del_mismo_boletin() {
+        let mut enlaces = vec![
+            enlace(
+                "BOE-A-2024-25900",
+                "Orden HAC/1460/2024, de 9 de diciembre, por la que se aprueba el modelo 232.",
+            ),
+            enlace(
+                "BOE-A-2024-25901",
+                "Corrección de errores de la Orden HAC/1460/2024, de 9 de diciembre.",
+            ),
+            enlace(
+                "BOE-A-2024-25903",
+                "Corrección de errores de la Orden HAC/1111/2024, de 1 de octubre.",
+            ),
+        ];
+        vincular_por_titulo(&mut enlaces);
+        assert_eq!(enlaces[0].corrige, None);
+        assert_eq!(enlaces[1].corrige.as_deref(), Some("BOE-A-2024-25900"));
+        assert_eq!(enlaces[2].corrige, None);
+    }
+}
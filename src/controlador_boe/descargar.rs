@@ -1,159 +1,486 @@
 use futures::future::join_all;
-use lopdf::Document;
-use reqwest::Client;
-use scraper::{Html, Selector};
+use reqwest::{header, Client, Url};
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
 use tokio::time::sleep;
+use tracing::{debug, info, warn};
 
-use std::{collections::BTreeMap, env::{self, current_exe}, fs::File, io::{self, Write}, path::PathBuf, time::Duration};
-
-use lopdf::{Bookmark, Object, ObjectId};
+use std::{
+    env::current_exe,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use super::{
     cliente_http,
+    configuracion::Configuracion,
+    correcciones,
+    dividir::{self, Division},
+    documento_xml::{self, MetadatosXml},
+    duplicados, filtros,
+    firmar::{self, Firmante},
+    firmas::{self, EstadoFirma, VerificacionFirma},
+    individuales,
+    optimizar::ResultadoOptimizacion,
+    paquete::{self, EntradaPaquete, OrigenEntrada},
+    progreso::{self, EventoProgreso},
+    unir_pdf::{self, OpcionesUnion, PdfCargado},
     validar_fecha::{self, FechaBoe},
+    validar_pdf,
+    volumenes::{self, LimitesVolumen},
 };
 
 pub struct GestorDescargaBoe {
     pub cliente: Client,
     pub fecha: FechaBoe,
     pub enlaces_pdf: Vec<EnlacePDF>,
-    pub pdf_memoria: Vec<Vec<u8>>,
+    pub pdf_memoria: Vec<DocumentoDescargado>,
+    pub configuracion: Configuracion,
     pub error_critico: Option<String>,
+    // PDF unidos e informes escritos en esta ejecución, para el paquete ZIP
+    pub archivos_generados: Vec<PathBuf>,
+    // Clave y certificado con los que se firma cada PDF unido, si se ha indicado
+    pub firmante: Option<Firmante>,
 }
-#[derive(Clone)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct EnlacePDF {
     pub apartado: String,
     pub url: String,
     pub titulo: String,
+    pub seccion: String,
+    pub departamento: String,
+    pub epigrafe: String,
+    pub rango: String,
+    // Subsección con letra de las secciones II y V, como "A. Anuncios de licitaciones públicas..."
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub subseccion: String,
+    #[serde(default)]
+    pub sha256: String,
+    // Otros lugares del sumario que enlazaban el mismo documento
+    #[serde(default)]
+    pub tambien_en: Vec<UbicacionEnlace>,
+    // Archivo del volumen en el que se ha unido, solo si se limita el tamaño de los PDF
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volumen: Option<String>,
+    // Copia individual del PDF, relativa al directorio de salida
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ruta_local: Option<String>,
+    // Resultado de verificar la firma del PDF descargado, solo si se ha pedido
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub firma: Option<VerificacionFirma>,
+    // Identificador de la disposición que corrige, si es una corrección de errores
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corrige: Option<String>,
+    // Metadatos del XML del documento, solo si se ha pedido
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadatos_xml: Option<MetadatosXml>,
 }
 
-impl From<&str> for GestorDescargaBoe {
-    fn from(f: &str) -> Self {
-        let fecha = validar_fecha::comprobar_formato(f);
-        println!("🟢 fecha incorporada válida");
-        GestorDescargaBoe {
-            cliente: cliente_http::nuevo_cliente_http(),
-            fecha,
-            enlaces_pdf: Vec::new(),
-            pdf_memoria: Vec::new(),
-            error_critico: None,
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct UbicacionEnlace {
+    pub apartado: String,
+    pub seccion: String,
+    pub departamento: String,
+    pub epigrafe: String,
+}
+
+impl EnlacePDF {
+    pub fn ubicacion(&self) -> UbicacionEnlace {
+        UbicacionEnlace {
+            apartado: self.apartado.to_owned(),
+            seccion: self.seccion.to_owned(),
+            departamento: self.departamento.to_owned(),
+            epigrafe: self.epigrafe.to_owned(),
         }
     }
 }
 
+impl UbicacionEnlace {
+    pub fn descripcion(&self) -> String {
+        [
+            &self.apartado,
+            &self.seccion,
+            &self.departamento,
+            &self.epigrafe,
+        ]
+        .into_iter()
+        .filter(|parte| !parte.is_empty())
+        .map(|parte| parte.as_str())
+        .collect::<Vec<&str>>()
+        .join(" > ")
+    }
+}
+
+// Cada PDF conserva su enlace para poder identificarlo si falla más adelante
+pub struct DocumentoDescargado {
+    pub enlace: EnlacePDF,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct InformeDescargas {
+    pub fecha: String,
+    pub total_documentos: usize,
+    pub documentos: Vec<EnlacePDF>,
+}
+
 impl GestorDescargaBoe {
+    pub fn nuevo(f: &str, configuracion: &Configuracion) -> Result<Self, String> {
+        let fecha = validar_fecha::comprobar_formato(f)?;
+        info!("fecha incorporada válida");
+        // Un certificado o contraseña incorrectos se detectan antes de descargar nada
+        let firmante = match &configuracion.certificado_firma {
+            None => None,
+            Some(ruta) => {
+                let clave = configuracion.clave_firma.as_deref().unwrap_or_default();
+                Some(firmar::cargar_firmante(ruta, clave)?)
+            }
+        };
+        Ok(GestorDescargaBoe {
+            cliente: cliente_http::nuevo_cliente_http(configuracion)?,
+            fecha,
+            enlaces_pdf: Vec::new(),
+            pdf_memoria: Vec::new(),
+            configuracion: configuracion.clone(),
+            error_critico: None,
+            archivos_generados: Vec::new(),
+            firmante,
+        })
+    }
     fn crear_ruta_descarga(&self, sufijo: &str) -> PathBuf {
-        let ruta_raiz = current_exe()
-            .expect("no ha podido recuperarse la ruta del ejecutable")
-            .parent()
-            .expect("no ha podido recuperarse la ruta raiz del ejecutable")
-            .to_owned();
-        let nombre_archivo = format!(
-            "{:02}_{:02}_{}_boe_completo{}",
-            self.fecha.dia, self.fecha.mes, self.fecha.año, sufijo
-        );
-        let ruta_descarga = ruta_raiz.join(nombre_archivo);
-        return ruta_descarga;
+        ruta_salida(&self.configuracion, &self.fecha, sufijo)
     }
     async fn buscar_pdf_disposiciones(&mut self) {
-        let endpoint = format!(
-            "https://boe.es/boe/dias/{}/{:02}/{:02}/",
+        if !self.configuracion.incluye_apartado("disposiciones") {
+            return;
+        }
+        let ruta = format!(
+            "boe/dias/{}/{:02}/{:02}/",
             self.fecha.año, self.fecha.mes, self.fecha.dia
         );
-        let html = match realizar_get_devolver_html_parseado(&endpoint, &self.cliente).await {
+        let endpoint = match unir_url_base(&self.configuracion.url_base, &ruta) {
+            Err(error) => {
+                self.error_critico = Some(error);
+                return;
+            }
+            Ok(ok) => ok,
+        };
+        let html = realizar_get_devolver_html_parseado(endpoint.as_str(), &self.cliente).await;
+        let html = match html {
             Err(error) => {
                 self.error_critico = Some(error);
                 return;
             }
             Ok(ok) => ok,
         };
-        let selector = Selector::parse("#indiceSumario .sumario .puntoPDF a")
+        let selector = Selector::parse("#indiceSumario .sumario")
             .expect("ha fallado el selector para los enlaces de las disposiciones y anuncios");
         let enlaces_coincidentes = devolver_coincidencias_enlace_pdf(
             html,
             &selector,
             String::from("Disposiciones y anuncios"),
+            &self.configuracion.url_base,
+            &endpoint,
         );
         for v in enlaces_coincidentes.clone() {
             self.enlaces_pdf.push(v);
         }
     }
     async fn buscar_pdf_notificaciones(&mut self) {
-        if self.error_critico.is_some() {
+        if self.error_critico.is_some() || !self.configuracion.incluye_apartado("notificaciones") {
             return;
         }
         // https://boe.es/boe_n/dias/2024/12/26/index.php?l=N
-        let endpoint = format!(
-            "https://boe.es/boe_n/dias/{}/{:02}/{:02}/index.php?l=N",
+        let ruta = format!(
+            "boe_n/dias/{}/{:02}/{:02}/index.php?l=N",
             self.fecha.año, self.fecha.mes, self.fecha.dia
         );
-        let html = match realizar_get_devolver_html_parseado(&endpoint, &self.cliente).await {
+        let endpoint = match unir_url_base(&self.configuracion.url_base, &ruta) {
+            Err(error) => {
+                self.error_critico = Some(error);
+                return;
+            }
+            Ok(ok) => ok,
+        };
+        let html = realizar_get_devolver_html_parseado(endpoint.as_str(), &self.cliente).await;
+        let html = match html {
             Err(error) => {
                 self.error_critico = Some(error);
                 return;
             }
             Ok(ok) => ok,
         };
-        let selector = Selector::parse("#indiceSumarioN .sumario .puntoPDF a")
+        let selector = Selector::parse("#indiceSumarioN .sumario")
             .expect("ha fallado el selector para los enlaces de las notificaciones");
-        let enlaces_coincidentes =
-            devolver_coincidencias_enlace_pdf(html, &selector, String::from("Notificaciones"));
+        let enlaces_coincidentes = devolver_coincidencias_enlace_pdf(
+            html,
+            &selector,
+            String::from("Notificaciones"),
+            &self.configuracion.url_base,
+            &endpoint,
+        );
         for v in enlaces_coincidentes.clone() {
             self.enlaces_pdf.push(v);
         }
     }
     async fn buscar_pdf_edictos(&mut self) {
-        if self.error_critico.is_some() {
+        if self.error_critico.is_some() || !self.configuracion.incluye_apartado("edictos") {
             return;
         }
         // https://boe.es/boe_j/dias/2024/12/26/index.php?l=J
-        let endpoint = format!(
-            "https://boe.es/boe_j/dias/{}/{:02}/{:02}/index.php?l=J",
+        let ruta = format!(
+            "boe_j/dias/{}/{:02}/{:02}/index.php?l=J",
             self.fecha.año, self.fecha.mes, self.fecha.dia
         );
-        let html = match realizar_get_devolver_html_parseado(&endpoint, &self.cliente).await {
+        let endpoint = match unir_url_base(&self.configuracion.url_base, &ruta) {
             Err(error) => {
                 self.error_critico = Some(error);
                 return;
             }
             Ok(ok) => ok,
         };
-        let selector = Selector::parse("#indiceSumarioN .sumario .puntoPDF a")
+        let html = realizar_get_devolver_html_parseado(endpoint.as_str(), &self.cliente).await;
+        let html = match html {
+            Err(error) => {
+                self.error_critico = Some(error);
+                return;
+            }
+            Ok(ok) => ok,
+        };
+        let selector = Selector::parse("#indiceSumarioN .sumario")
             .expect("ha fallado el selector para los enlaces de las notificaciones");
-        let enlaces_coincidentes =
-            devolver_coincidencias_enlace_pdf(html, &selector, String::from("Edictos Judiciales"));
+        let enlaces_coincidentes = devolver_coincidencias_enlace_pdf(
+            html,
+            &selector,
+            String::from("Edictos Judiciales"),
+            &self.configuracion.url_base,
+            &endpoint,
+        );
         for v in enlaces_coincidentes {
             self.enlaces_pdf.push(v);
         }
     }
-    fn generar_informe_descargas(&self) {
+    fn aplicar_filtros(&mut self) {
+        if self.error_critico.is_some() || self.configuracion.filtros.esta_vacio() {
+            return;
+        }
+        let total_enlaces = self.enlaces_pdf.len();
+        let filtros = &self.configuracion.filtros;
+        self.enlaces_pdf.retain(|e| filtros.admite(e));
+        if self.enlaces_pdf.is_empty() {
+            let mensaje_error = format!(
+                "ninguno de los {} documentos encontrados coincide con los filtros indicados",
+                total_enlaces
+            );
+            self.error_critico = Some(mensaje_error);
+            return;
+        }
+        info!(
+            "{} de {} documentos coinciden con los filtros indicados",
+            self.enlaces_pdf.len(),
+            total_enlaces
+        );
+    }
+    fn eliminar_duplicados(&mut self) {
         if self.error_critico.is_some() {
             return;
         }
-        let mut contenido = String::from("Apartado\tTítulo\tUrl");
-        for e in &self.enlaces_pdf {
-            let linea = format!("{}\t{}\t{}\n", e.apartado, e.titulo, e.url);
+        let total_enlaces = self.enlaces_pdf.len();
+        let enlaces = std::mem::take(&mut self.enlaces_pdf);
+        self.enlaces_pdf = duplicados::eliminar_duplicados_url(enlaces);
+        if self.enlaces_pdf.len() < total_enlaces {
+            info!(
+                "{} enlaces repetidos en varias secciones se descargarán una sola vez",
+                total_enlaces - self.enlaces_pdf.len()
+            );
+        }
+    }
+    // Se escribe antes de descargar, para que quede aunque falle la descarga o la unión, y se
+    // vuelve a escribir al final con los resúmenes, las firmas y los volúmenes
+    fn generar_informe_descargas(&mut self) {
+        if self.enlaces_pdf.is_empty() {
+            return;
+        }
+        let enlaces = self.enlaces_pdf.clone();
+        self.escribir_informe(&enlaces, "");
+        if self.configuracion.division == Division::Ninguna {
+            return;
+        }
+        // Cada parte del boletín dividido tiene además su propio informe
+        let grupos = dividir::agrupar(enlaces, self.configuracion.division, |e| e);
+        for (grupo, enlaces_grupo) in grupos {
+            let sufijo = format!("_{}", grupo.unwrap_or_default());
+            self.escribir_informe(&enlaces_grupo, &sufijo);
+        }
+    }
+    fn escribir_informe(&mut self, enlaces: &[EnlacePDF], sufijo: &str) {
+        let mut contenido = String::from(
+            "Apartado\tSección\tDepartamento\tEpígrafe\tRango\tTítulo\tUrl\tSha256\tTambién en\tVolumen\tRuta local\tFirma\tCorrige\n",
+        );
+        for e in enlaces {
+            let tambien_en = e
+                .tambien_en
+                .iter()
+                .map(|u| u.descripcion())
+                .collect::<Vec<String>>()
+                .join("; ");
+            let volumen = e.volumen.as_deref().unwrap_or_default();
+            let firma = e.firma.as_ref().map(|f| f.resumen()).unwrap_or_default();
+            let linea = format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                e.apartado,
+                e.seccion,
+                e.departamento,
+                e.epigrafe,
+                e.rango,
+                e.titulo,
+                e.url,
+                e.sha256,
+                tambien_en,
+                volumen,
+                e.ruta_local.as_deref().unwrap_or_default(),
+                firma,
+                e.corrige.as_deref().unwrap_or_default()
+            );
             contenido.push_str(&linea);
         }
-        let ruta_tsv = self.crear_ruta_descarga("_informe.tsv");
+        let ruta_tsv = self.crear_ruta_descarga(&format!("{}_informe.tsv", sufijo));
         let mut archivo_tsv = match File::create(&ruta_tsv) {
             Err(error) => {
-                eprintln!("🟡 no se ha podido crear el archivo para el informe {}", error);
+                warn!("no se ha podido crear el archivo para el informe {}", error);
                 return;
             }
             Ok(ok) => ok,
         };
         match archivo_tsv.write_all(contenido.as_bytes()) {
             Err(error) => {
-                eprintln!(
-                    "🟡 ha fallado la escritura del archivo para el informe {}",
+                warn!(
+                    "ha fallado la escritura del archivo para el informe {}",
                     error
                 );
+            }
+            Ok(_) => {
+                info!(
+                    "informe de descargas creado correctamente en {}",
+                    ruta_tsv.display()
+                );
+                self.registrar_archivo(ruta_tsv);
+            }
+        }
+        let informe = InformeDescargas {
+            fecha: format!(
+                "{:02}-{:02}-{}",
+                self.fecha.dia, self.fecha.mes, self.fecha.año
+            ),
+            total_documentos: enlaces.len(),
+            documentos: enlaces.to_vec(),
+        };
+        let contenido_json = match serde_json::to_vec_pretty(&informe) {
+            Err(error) => {
+                warn!("no se ha podido serializar el informe en JSON {}", error);
                 return;
             }
+            Ok(ok) => ok,
+        };
+        let ruta_json = self.crear_ruta_descarga(&format!("{}_informe.json", sufijo));
+        match escribir_de_una_vez(&ruta_json, &contenido_json) {
+            Err(error) => {
+                warn!(
+                    "ha fallado la escritura del archivo para el informe JSON {}",
+                    error
+                );
+            }
             Ok(_) => {
-                println!("🟢 informe de descargas creado correctamente en {}", ruta_tsv.display());
-            },
+                info!(
+                    "informe JSON creado correctamente en {}",
+                    ruta_json.display()
+                );
+                self.registrar_archivo(ruta_json);
+            }
+        }
+    }
+    // El informe se escribe dos veces y solo debe ir una en el paquete
+    fn registrar_archivo(&mut self, ruta: PathBuf) {
+        if !self.archivos_generados.contains(&ruta) {
+            self.archivos_generados.push(ruta);
+        }
+    }
+    // Con metadatos_xml se añade a cada documento lo que publica el BOE en su versión XML
+    async fn leer_metadatos_xml(&mut self) {
+        if self.error_critico.is_some() || !self.configuracion.metadatos_xml {
+            return;
+        }
+        let total_documentos = self.enlaces_pdf.len();
+        let mut documentos_leidos = 0;
+        for enlace in &mut self.enlaces_pdf {
+            let identificador = individuales::identificador_boe(&enlace.url);
+            if identificador.is_empty() {
+                continue;
+            }
+            sleep(Duration::from_secs(self.configuracion.espera)).await;
+            let url_base = &self.configuracion.url_base;
+            match documento_xml::descargar_documento(url_base, &identificador, &self.cliente).await
+            {
+                Err(error) => warn!("no se ha podido leer el XML de {} {}", identificador, error),
+                Ok(documento) => {
+                    enlace.metadatos_xml = Some(documento_xml::extraer_metadatos(&documento));
+                    documentos_leidos += 1;
+                }
+            }
+        }
+        info!(
+            "metadatos XML leídos de {} de {} documentos",
+            documentos_leidos, total_documentos
+        );
+    }
+    // Las correcciones de errores se enlazan con la disposición que corrigen, primero por las
+    // referencias del XML del documento y si no las hay por el título dentro del mismo boletín
+    async fn vincular_correcciones(&mut self) {
+        if self.error_critico.is_some() {
+            return;
+        }
+        for enlace in &mut self.enlaces_pdf {
+            if !correcciones::es_correccion(&enlace.titulo) {
+                continue;
+            }
+            // Si ya se han leído los metadatos no hace falta volver a pedir el XML
+            let anteriores = match &enlace.metadatos_xml {
+                Some(metadatos) => metadatos.referencias_anteriores.clone(),
+                None => {
+                    let identificador = individuales::identificador_boe(&enlace.url);
+                    if identificador.is_empty() {
+                        continue;
+                    }
+                    sleep(Duration::from_secs(self.configuracion.espera)).await;
+                    let url_base = &self.configuracion.url_base;
+                    match documento_xml::descargar_documento(
+                        url_base,
+                        &identificador,
+                        &self.cliente,
+                    )
+                    .await
+                    {
+                        Err(error) => {
+                            warn!("no se ha podido leer el XML de {} {}", identificador, error);
+                            continue;
+                        }
+                        Ok(documento) => documento_xml::referencias(&documento, "anteriores"),
+                    }
+                }
+            };
+            enlace.corrige = correcciones::referencia_corregida(&anteriores);
+        }
+        correcciones::vincular_por_titulo(&mut self.enlaces_pdf);
+        for enlace in &self.enlaces_pdf {
+            if let Some(original) = &enlace.corrige {
+                info!(
+                    "{} corrige {}",
+                    individuales::identificador_boe(&enlace.url),
+                    original
+                );
+            }
         }
     }
     async fn descargar_pdf_memoria(&mut self) {
@@ -162,225 +489,377 @@ impl GestorDescargaBoe {
         }
         let total_archivos_descargar = self.enlaces_pdf.len();
         let mut archivos_descargados = 0;
+        let mut duplicados_contenido = Vec::new();
         for lote in self.enlaces_pdf.chunks(1) {
             // Sleep por si las peticiones son demasiado seguidas
-            sleep(Duration::from_secs(tiempo_espera_descargas())).await;
+            sleep(Duration::from_secs(self.configuracion.espera)).await;
             let mut futuros = Vec::new();
             for enlace in lote {
-                archivos_descargados += 1;
-                print!("\r⏳ descargando archivo {:03} de {}", archivos_descargados, total_archivos_descargar);
-                let _ = io::stdout().flush();
-                futuros.push(realizar_get_devolver_bytes(&enlace.url, &self.cliente));
+                debug!(url = %enlace.url, "descargando archivo");
+                futuros.push(realizar_get_devolver_bytes_con_reintentos(
+                    &enlace.url,
+                    &self.cliente,
+                    self.configuracion.reintentos,
+                    self.configuracion.espera,
+                ));
             }
-            for r in join_all(futuros).await {
+            for (enlace, r) in lote.iter().zip(join_all(futuros).await) {
+                archivos_descargados += 1;
                 match r {
                     Err(error) => {
-                        eprintln!("{}", error)
+                        warn!("{}", error);
+                        progreso::emitir(EventoProgreso::DescargaFallida {
+                            indice: archivos_descargados,
+                            total: total_archivos_descargar,
+                            url: &enlace.url,
+                            error: &error,
+                        });
                     }
                     Ok(ok) => {
-                        self.pdf_memoria.push(ok);
+                        info!(
+                            "descargado archivo {:03} de {}",
+                            archivos_descargados, total_archivos_descargar
+                        );
+                        progreso::emitir(EventoProgreso::DescargaCompletada {
+                            indice: archivos_descargados,
+                            total: total_archivos_descargar,
+                            url: &enlace.url,
+                            bytes: ok.len(),
+                        });
+                        let sha256 = duplicados::resumen_sha256(&ok);
+                        let repetido = self
+                            .pdf_memoria
+                            .iter_mut()
+                            .find(|d| d.enlace.sha256 == sha256);
+                        match repetido {
+                            Some(conservado) => {
+                                info!(
+                                    "{} tiene el mismo contenido que {}, se unirá una sola vez",
+                                    enlace.url, conservado.enlace.url
+                                );
+                                duplicados::anotar_ubicacion(&mut conservado.enlace, enlace);
+                                duplicados_contenido.push(enlace.url.to_owned());
+                            }
+                            None => {
+                                let mut enlace = enlace.clone();
+                                enlace.sha256 = sha256;
+                                self.pdf_memoria
+                                    .push(DocumentoDescargado { enlace, bytes: ok });
+                            }
+                        }
                     }
                 }
             }
         }
-        println!("\n🟢 todos los archivos descargados en memoria")
+        // El informe refleja el resumen de cada documento y solo una entrada por contenido
+        self.enlaces_pdf
+            .retain(|e| !duplicados_contenido.contains(&e.url));
+        for enlace in &mut self.enlaces_pdf {
+            if let Some(descargado) = self.pdf_memoria.iter().find(|d| d.enlace.url == enlace.url) {
+                *enlace = descargado.enlace.clone();
+            }
+        }
+        info!("todos los archivos descargados en memoria")
     }
-    fn unir_pdf_memoria(&mut self) {
-        if self.error_critico.is_some() {
+    // Se comprueba sobre los bytes descargados, la unión elimina las firmas de cada documento
+    fn verificar_firmas(&mut self) {
+        if self.error_critico.is_some() || !self.configuracion.verificar_firmas {
             return;
         }
-        // Generate a stack of Documents to merge
-        let mut documents = Vec::new();
-        for pdf_data in &self.pdf_memoria {
-            let pdf = Document::load_mem(&pdf_data).unwrap();
-            documents.push(pdf);
-        }
-        // Define a starting max_id (will be used as start index for object_ids)
-        let mut max_id = 1;
-        let mut pagenum = 1;
-        // Collect all Documents Objects grouped by a map
-        let mut documents_pages = BTreeMap::new();
-        let mut documents_objects = BTreeMap::new();
-        let mut document = Document::with_version("1.5");
-
-        for mut doc in documents {
-            let mut first = false;
-            doc.renumber_objects_with(max_id);
-
-            max_id = doc.max_id + 1;
-
-            documents_pages.extend(
-                doc.get_pages()
-                    .into_iter()
-                    .map(|(_, object_id)| {
-                        if !first {
-                            let bookmark = Bookmark::new(
-                                String::from(format!("Page_{}", pagenum)),
-                                [0.0, 0.0, 1.0],
-                                0,
-                                object_id,
-                            );
-                            document.add_bookmark(bookmark, None);
-                            first = true;
-                            pagenum += 1;
-                        }
-
-                        (object_id, doc.get_object(object_id).unwrap().to_owned())
-                    })
-                    .collect::<BTreeMap<ObjectId, Object>>(),
-            );
-            documents_objects.extend(doc.objects);
+        let almacen = match firmas::cargar_almacen(&self.configuracion.almacen_confianza) {
+            Err(error) => {
+                self.error_critico = Some(error);
+                return;
+            }
+            Ok(ok) => ok,
+        };
+        if almacen.is_empty() {
+            warn!("no se ha indicado ningún almacén de confianza, ninguna firma será válida");
         }
-
-        // Catalog and Pages are mandatory
-        let mut catalog_object: Option<(ObjectId, Object)> = None;
-        let mut pages_object: Option<(ObjectId, Object)> = None;
-
-        // Process all objects except "Page" type
-        for (object_id, object) in documents_objects.iter() {
-            // We have to ignore "Page" (as are processed later), "Outlines" and "Outline" objects
-            // All other objects should be collected and inserted into the main Document
-            match object.type_name().unwrap_or("") {
-                "Catalog" => {
-                    // Collect a first "Catalog" object and use it for the future "Pages"
-                    catalog_object = Some((
-                        if let Some((id, _)) = catalog_object {
-                            id
-                        } else {
-                            *object_id
-                        },
-                        object.clone(),
-                    ));
+        let mut validas = 0;
+        for descargado in &mut self.pdf_memoria {
+            let verificacion = firmas::verificar_documento(&descargado.bytes, &almacen);
+            let enlace = &descargado.enlace;
+            match verificacion.estado {
+                EstadoFirma::Valida => validas += 1,
+                EstadoFirma::SinFirma => {
+                    warn!("{} ({}) no está firmado", enlace.titulo, enlace.url)
                 }
-                "Pages" => {
-                    // Collect and update a first "Pages" object and use it for the future "Catalog"
-                    // We have also to merge all dictionaries of the old and the new "Pages" object
-                    if let Ok(dictionary) = object.as_dict() {
-                        let mut dictionary = dictionary.clone();
-                        if let Some((_, ref object)) = pages_object {
-                            if let Ok(old_dictionary) = object.as_dict() {
-                                dictionary.extend(old_dictionary);
-                            }
-                        }
-
-                        pages_object = Some((
-                            if let Some((id, _)) = pages_object {
-                                id
-                            } else {
-                                *object_id
-                            },
-                            Object::Dictionary(dictionary),
-                        ));
-                    }
+                _ => warn!(
+                    "la firma de {} ({}) no es válida {}",
+                    enlace.titulo, enlace.url, verificacion.detalle
+                ),
+            }
+            descargado.enlace.firma = Some(verificacion);
+        }
+        for enlace in &mut self.enlaces_pdf {
+            if let Some(descargado) = self.pdf_memoria.iter().find(|d| d.enlace.url == enlace.url) {
+                enlace.firma = descargado.enlace.firma.to_owned();
+            }
+        }
+        info!(
+            "{} de {} documentos con firma válida",
+            validas,
+            self.pdf_memoria.len()
+        );
+    }
+    fn guardar_pdf_individuales(&mut self) {
+        if self.error_critico.is_some() || !self.configuracion.guardar_individuales {
+            return;
+        }
+        let carpeta = format!(
+            "{}_documentos",
+            ruta_salida(&self.configuracion, &self.fecha, "")
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+        );
+        let mut guardados = 0;
+        for descargado in &mut self.pdf_memoria {
+            let ruta_relativa =
+                PathBuf::from(&carpeta).join(individuales::ruta_relativa(&descargado.enlace));
+            let ruta = self.configuracion.directorio_salida.join(&ruta_relativa);
+            if let Some(directorio) = ruta.parent() {
+                if let Err(error) = fs::create_dir_all(directorio) {
+                    warn!(
+                        "no se ha podido crear el directorio {} {}",
+                        directorio.display(),
+                        error
+                    );
+                    continue;
                 }
-                "Page" => {}     // Ignored, processed later and separately
-                "Outlines" => {} // Ignored, not supported yet
-                "Outline" => {}  // Ignored, not supported yet
-                _ => {
-                    document.objects.insert(*object_id, object.clone());
+            }
+            match fs::write(&ruta, &descargado.bytes) {
+                Err(error) => {
+                    // Sin la copia individual el boletín unido se genera igualmente
+                    warn!("ha fallado el guardado de {} {}", ruta.display(), error);
+                }
+                Ok(_) => {
+                    debug!(ruta = %ruta.display(), "documento guardado");
+                    descargado.enlace.ruta_local = Some(ruta_relativa.display().to_string());
+                    guardados += 1;
                 }
             }
         }
-
-        // If no "Pages" found abort
-        if pages_object.is_none() {
-            let mensaje_error =
-                format!("ha fallado el proceso de unión de los PDF. Pages root not found.");
-            self.error_critico = Some(mensaje_error);
+        for enlace in &mut self.enlaces_pdf {
+            if let Some(descargado) = self.pdf_memoria.iter().find(|d| d.enlace.url == enlace.url) {
+                enlace.ruta_local = descargado.enlace.ruta_local.to_owned();
+            }
+        }
+        info!(
+            "{} documentos guardados por separado en {}",
+            guardados,
+            self.configuracion
+                .directorio_salida
+                .join(&carpeta)
+                .display()
+        );
+    }
+    fn unir_pdf_memoria(&mut self) {
+        if self.error_critico.is_some() {
             return;
         }
-
-        // Iter over all "Page" and collect with the parent "Pages" created before
-        for (object_id, object) in documents_pages.iter() {
-            if let Ok(dictionary) = object.as_dict() {
-                let mut dictionary = dictionary.clone();
-                dictionary.set("Parent", pages_object.as_ref().unwrap().0);
-
-                document
-                    .objects
-                    .insert(*object_id, Object::Dictionary(dictionary));
+        progreso::emitir(EventoProgreso::UnionIniciada {
+            documentos: self.pdf_memoria.len(),
+        });
+        // Generate a stack of Documents to merge
+        let mut documentos = Vec::new();
+        for descargado in &self.pdf_memoria {
+            let reparar = self.configuracion.reparar_pdf;
+            match validar_pdf::cargar_documento(&descargado.bytes, reparar) {
+                Err(error) => {
+                    // Un documento dañado no debe impedir la unión del resto
+                    let enlace = &descargado.enlace;
+                    warn!("se descarta {} ({}) {}", enlace.titulo, enlace.url, error);
+                    progreso::emitir(EventoProgreso::DocumentoDescartado {
+                        enlace,
+                        error: &error,
+                    });
+                }
+                Ok(documento) => documentos.push(PdfCargado {
+                    enlace: descargado.enlace.to_owned(),
+                    documento,
+                    tamaño: descargado.bytes.len(),
+                }),
             }
         }
-
-        // If no "Catalog" found abort
-        if catalog_object.is_none() {
-            let mensaje_error =
-                format!("ha fallado el proceso de unión de los PDF. Catalog root not found.");
+        if documentos.is_empty() {
+            let mensaje_error = format!(
+                "ninguno de los {} documentos descargados es un PDF válido",
+                self.enlaces_pdf.len()
+            );
             self.error_critico = Some(mensaje_error);
             return;
         }
-
-        let catalog_object = catalog_object.unwrap();
-        let pages_object = pages_object.unwrap();
-
-        // Build a new "Pages" with updated fields
-        if let Ok(dictionary) = pages_object.1.as_dict() {
-            let mut dictionary = dictionary.clone();
-
-            // Set new pages count
-            dictionary.set("Count", documents_pages.len() as u32);
-
-            // Set new "Kids" list (collected from documents pages) for "Pages"
-            dictionary.set(
-                "Kids",
-                documents_pages
-                    .into_iter()
-                    .map(|(object_id, _)| Object::Reference(object_id))
-                    .collect::<Vec<_>>(),
-            );
-
-            document
-                .objects
-                .insert(pages_object.0, Object::Dictionary(dictionary));
-        }
-
-        // Build a new "Catalog" with updated fields
-        if let Ok(dictionary) = catalog_object.1.as_dict() {
-            let mut dictionary = dictionary.clone();
-            dictionary.set("Pages", pages_object.0);
-            dictionary.remove(b"Outlines"); // Outlines not supported in merged PDFs
-
-            document
-                .objects
-                .insert(catalog_object.0, Object::Dictionary(dictionary));
+        let grupos = dividir::agrupar(documentos, self.configuracion.division, |d| &d.enlace);
+        for (grupo, documentos_grupo) in grupos {
+            let sufijo = match grupo {
+                None => String::new(),
+                Some(grupo) => format!("_{}", grupo),
+            };
+            match LimitesVolumen::desde_configuracion(&self.configuracion) {
+                None => self.guardar_pdf_unido(documentos_grupo, &sufijo),
+                Some(limites) => self.guardar_volumenes(documentos_grupo, &limites, &sufijo),
+            }
+            if self.error_critico.is_some() {
+                return;
+            }
         }
-
-        document.trailer.set("Root", catalog_object.0);
-
-        // Update the max internal ID as wasn't updated before due to direct objects insertion
-        document.max_id = document.objects.len() as u32;
-
-        // Reorder all new Document objects
-        document.renumber_objects();
-
-        //Set any Bookmarks to the First child if they are not set to a page
-        document.adjust_zero_pages();
-
-        //Set all bookmarks to the PDF Object tree then set the Outlines to the Bookmark content map.
-        if let Some(n) = document.build_outline() {
-            if let Ok(x) = document.get_object_mut(catalog_object.0) {
-                if let Object::Dictionary(ref mut dict) = x {
-                    dict.set("Outlines", Object::Reference(n));
+    }
+    fn guardar_pdf_unido(&mut self, documentos: Vec<PdfCargado>, sufijo: &str) {
+        let documentos = documentos
+            .into_iter()
+            .map(|d| (d.enlace, d.documento))
+            .collect();
+        let opciones = OpcionesUnion::desde_configuracion(&self.configuracion, &self.fecha);
+        let unido = match unir_pdf::unir_documentos(documentos, &opciones) {
+            Err(error) => {
+                self.error_critico = Some(error);
+                return;
+            }
+            Ok(ok) => ok,
+        };
+        let mut document = unido.documento;
+        let contenido = match unir_pdf::serializar(&mut document) {
+            Err(error) => {
+                self.error_critico = Some(error);
+                return;
+            }
+            Ok(ok) => ok,
+        };
+        let paginas = document.get_pages().len();
+        let contenido = match &self.firmante {
+            None => contenido,
+            Some(firmante) => match firmar::firmar_pdf(&contenido, firmante) {
+                Err(error) => {
+                    self.error_critico = Some(error);
+                    return;
+                }
+                Ok(ok) => ok,
+            },
+        };
+        self.escribir_pdf_unido(
+            &contenido,
+            paginas,
+            unido.optimizacion,
+            &format!("{}.pdf", sufijo),
+        );
+    }
+    fn guardar_volumenes(
+        &mut self,
+        documentos: Vec<PdfCargado>,
+        limites: &LimitesVolumen,
+        sufijo: &str,
+    ) {
+        let opciones = OpcionesUnion::desde_configuracion(&self.configuracion, &self.fecha);
+        // Los volúmenes vuelven ya firmados para que la firma no les haga pasar del límite
+        let firmante = self.firmante.as_ref();
+        let volumenes =
+            match volumenes::repartir_en_volumenes(documentos, limites, &opciones, firmante) {
+                Err(error) => {
+                    self.error_critico = Some(error);
+                    return;
+                }
+                Ok(ok) => ok,
+            };
+        info!("el boletín se guarda en {} volúmenes", volumenes.len());
+        for (indice, volumen) in volumenes.into_iter().enumerate() {
+            let sufijo_volumen = format!("{}_vol{}.pdf", sufijo, indice + 1);
+            // El informe indica en qué archivo ha quedado cada documento, porque con --dividir
+            // cada parte tiene su propio volumen 1
+            let ruta_volumen = self.crear_ruta_descarga(&sufijo_volumen);
+            let nombre_volumen = ruta_volumen
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            for enlace in &mut self.enlaces_pdf {
+                if volumen.enlaces.iter().any(|e| e.url == enlace.url) {
+                    enlace.volumen = Some(nombre_volumen.to_string());
                 }
             }
+            self.escribir_pdf_unido(
+                &volumen.contenido,
+                volumen.paginas,
+                volumen.optimizacion,
+                &sufijo_volumen,
+            );
+            if self.error_critico.is_some() {
+                return;
+            }
         }
-
-        document.compress();
-        let ruta_pdf_guardado = self.crear_ruta_descarga(".pdf");
-        match document.save(&ruta_pdf_guardado) {
+    }
+    fn escribir_pdf_unido(
+        &mut self,
+        contenido: &[u8],
+        paginas: usize,
+        optimizacion: Option<ResultadoOptimizacion>,
+        sufijo: &str,
+    ) {
+        let mut bytes_ahorrados = 0;
+        if let Some(optimizacion) = optimizacion {
+            bytes_ahorrados = optimizacion.bytes_ahorrados;
+            info!(
+                "{} recursos repetidos compartidos, unos {} KB menos antes de comprimir",
+                optimizacion.objetos_eliminados,
+                bytes_ahorrados / 1024
+            );
+        }
+        let ruta_pdf_guardado = self.crear_ruta_descarga(sufijo);
+        match escribir_de_una_vez(&ruta_pdf_guardado, contenido) {
             Err(error) => {
                 let mensaje_error = format!("ha fallado el guardado del PDF unido {}", error);
                 self.error_critico = Some(mensaje_error);
-                return;
             }
             Ok(_) => {
-                println!(
-                    "🏆 boe completo generado y guardado en {}",
+                info!(
+                    "boe completo generado y guardado en {}",
                     ruta_pdf_guardado.display()
                 );
+                progreso::emitir(EventoProgreso::UnionCompletada {
+                    paginas,
+                    ruta: ruta_pdf_guardado.display().to_string(),
+                    bytes_ahorrados,
+                });
+                self.archivos_generados.push(ruta_pdf_guardado);
             }
         }
     }
+    fn empaquetar(&mut self) {
+        if self.error_critico.is_some() || !self.configuracion.empaquetar {
+            return;
+        }
+        let mut entradas = Vec::new();
+        for ruta in &self.archivos_generados {
+            let nombre = ruta.file_name().unwrap_or_default().to_string_lossy();
+            entradas.push(EntradaPaquete {
+                nombre: nombre.to_string(),
+                origen: OrigenEntrada::Archivo(ruta.to_owned()),
+                metadatos_xml: None,
+            });
+        }
+        // Los PDF originales se toman de memoria aunque no se hayan guardado por separado
+        for descargado in &self.pdf_memoria {
+            let ruta_relativa = individuales::ruta_relativa(&descargado.enlace);
+            let partes = ruta_relativa
+                .iter()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>();
+            entradas.push(EntradaPaquete {
+                nombre: format!("documentos/{}", partes.join("/")),
+                origen: OrigenEntrada::Memoria(&descargado.bytes),
+                metadatos_xml: descargado.enlace.metadatos_xml.as_ref(),
+            });
+        }
+        let ruta_zip = self.crear_ruta_descarga(".zip");
+        match paquete::crear_paquete(&ruta_zip, &entradas) {
+            Err(error) => self.error_critico = Some(error),
+            Ok(_) => info!(
+                "{} archivos empaquetados en {}",
+                entradas.len(),
+                ruta_zip.display()
+            ),
+        }
+    }
     // fn testear_numero_reducido(&mut self) {
     //     let mut muestra_reducida = Vec::new();
     //     for (i, v) in self.enlaces_pdf.iter().enumerate() {
@@ -398,7 +877,52 @@ impl GestorDescargaBoe {
     // }
 }
 
-async fn realizar_get_devolver_html_parseado(url: &str, cliente: &Client) -> Result<Html, String> {
+pub fn ruta_salida(configuracion: &Configuracion, fecha: &FechaBoe, sufijo: &str) -> PathBuf {
+    let nombre_archivo = configuracion
+        .plantilla_nombre
+        .replace("{dia}", &format!("{:02}", fecha.dia))
+        .replace("{mes}", &format!("{:02}", fecha.mes))
+        .replace("{año}", &fecha.año.to_string());
+    configuracion
+        .directorio_salida
+        .join(format!("{}{}", nombre_archivo, sufijo))
+}
+
+// La url base puede apuntar a una réplica publicada en una subruta, así que se trata siempre
+// como un directorio para que join no descarte su último segmento
+pub fn unir_url_base(url_base: &str, ruta: &str) -> Result<Url, String> {
+    let mut base = match Url::parse(url_base) {
+        Err(error) => {
+            let mensaje_error = format!("la url base {} no es válida {}", url_base, error);
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    if !base.path().ends_with('/') {
+        let ruta_directorio = format!("{}/", base.path());
+        base.set_path(&ruta_directorio);
+    }
+    match base.join(ruta.trim_start_matches('/')) {
+        Err(error) => Err(format!(
+            "no se ha podido unir {} a la url base {} {}",
+            ruta, url_base, error
+        )),
+        Ok(ok) => Ok(ok),
+    }
+}
+
+pub fn directorio_ejecutable() -> PathBuf {
+    current_exe()
+        .expect("no ha podido recuperarse la ruta del ejecutable")
+        .parent()
+        .expect("no ha podido recuperarse la ruta raiz del ejecutable")
+        .to_owned()
+}
+
+pub async fn realizar_get_devolver_html_parseado(
+    url: &str,
+    cliente: &Client,
+) -> Result<Html, String> {
     let res = match cliente.get(url).send().await {
         Err(error) => {
             let mensaje_error = format!("ha fallado la petición a {} {} ", url, error);
@@ -425,6 +949,54 @@ async fn realizar_get_devolver_html_parseado(url: &str, cliente: &Client) -> Res
     Ok(Html::parse_document(&html))
 }
 
+pub async fn realizar_get_devolver_texto(url: &str, cliente: &Client) -> Result<String, String> {
+    let res = match cliente.get(url).send().await {
+        Err(error) => {
+            let mensaje_error = format!("ha fallado la petición a {} {} ", url, error);
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    if !res.status().is_success() {
+        let mensaje_error = format!(
+            "la peticion a {} ha devuelto un status code no deseado {} ",
+            url,
+            res.status()
+        );
+        return Err(mensaje_error);
+    }
+    match res.text().await {
+        Err(error) => {
+            let mensaje_error =
+                format!("ha fallado la lectura de la respuesta a {} {} ", url, error);
+            Err(mensaje_error)
+        }
+        Ok(ok) => Ok(ok),
+    }
+}
+
+async fn realizar_get_devolver_bytes_con_reintentos(
+    url: &str,
+    cliente: &Client,
+    reintentos: u32,
+    espera: u64,
+) -> Result<Vec<u8>, String> {
+    let mut intento = 0;
+    loop {
+        match realizar_get_devolver_bytes(url, cliente).await {
+            Ok(ok) => return Ok(ok),
+            Err(error) => {
+                if intento >= reintentos {
+                    return Err(error);
+                }
+                intento += 1;
+                warn!("{}(reintento {} de {})", error, intento, reintentos);
+                sleep(Duration::from_secs(espera)).await;
+            }
+        }
+    }
+}
+
 async fn realizar_get_devolver_bytes(url: &str, cliente: &Client) -> Result<Vec<u8>, String> {
     let res = match cliente.get(url).send().await {
         Err(error) => {
@@ -441,6 +1013,11 @@ async fn realizar_get_devolver_bytes(url: &str, cliente: &Client) -> Result<Vec<
         );
         return Err(mensaje_error);
     }
+    let tipo_contenido = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_owned());
     let bytes = match res.bytes().await {
         Err(error) => {
             let mensaje_error = format!(
@@ -451,63 +1028,303 @@ async fn realizar_get_devolver_bytes(url: &str, cliente: &Client) -> Result<Vec<
         }
         Ok(ok) => ok,
     };
+    if let Err(error) = validar_pdf::comprobar_pdf(tipo_contenido.as_deref(), &bytes) {
+        let mensaje_error = format!("la respuesta a {} no es un PDF {} ", url, error);
+        return Err(mensaje_error);
+    }
     Ok(bytes.to_vec())
 }
+// Los enlaces desde la raíz (/boe/dias/...) se resuelven respecto a la url base para no perder la
+// subruta de una réplica, los relativos respecto a la página y los absolutos se mantienen
+fn resolver_enlace(url_base: &str, url_pagina: &Url, href: &str) -> Result<Url, String> {
+    if href.starts_with('/') && !href.starts_with("//") {
+        return unir_url_base(url_base, href);
+    }
+    match url_pagina.join(href) {
+        Err(error) => Err(format!("{} {}", href, error)),
+        Ok(ok) => Ok(ok),
+    }
+}
 fn devolver_coincidencias_enlace_pdf(
     html: Html,
     selector: &Selector,
     apartado: String,
+    url_base: &str,
+    url_pagina: &Url,
 ) -> Vec<EnlacePDF> {
+    // Los encabezados preceden a las disposiciones que agrupan: h3 sección, h4 departamento,
+    // h5 epígrafe. En las secciones II y V el h4 es una subsección con letra y el departamento
+    // pasa al h5
+    let selector_elementos = Selector::parse("h3, h4, h5, .puntoPDF a")
+        .expect("ha fallado el selector para los encabezados y enlaces del sumario");
     let mut enlaces = Vec::new();
-    let coincidencias = html.select(&selector);
-    for coincidencia in coincidencias {
-        let href = coincidencia.attr("href");
-        if href.is_none() {
-            continue;
+    for contenedor in html.select(selector) {
+        let mut seccion = String::new();
+        let mut subseccion = String::new();
+        let mut departamento = String::new();
+        let mut epigrafe = String::new();
+        for coincidencia in contenedor.select(&selector_elementos) {
+            match coincidencia.value().name() {
+                "h3" => {
+                    seccion = texto_limpio(&coincidencia);
+                    subseccion.clear();
+                    departamento.clear();
+                    epigrafe.clear();
+                }
+                "h4" => {
+                    let texto = texto_limpio(&coincidencia);
+                    departamento.clear();
+                    epigrafe.clear();
+                    match es_subseccion(&texto) {
+                        true => subseccion = texto,
+                        false => {
+                            subseccion.clear();
+                            departamento = texto;
+                        }
+                    }
+                }
+                "h5" if !subseccion.is_empty() => {
+                    departamento = texto_limpio(&coincidencia);
+                    epigrafe.clear();
+                }
+                "h5" => epigrafe = texto_limpio(&coincidencia),
+                _ => {
+                    let href = coincidencia.attr("href");
+                    if href.is_none() {
+                        continue;
+                    }
+                    let url = match resolver_enlace(url_base, url_pagina, href.unwrap()) {
+                        Err(error) => {
+                            warn!("se ignora el enlace {}", error);
+                            continue;
+                        }
+                        Ok(ok) => ok,
+                    };
+                    let titulo = match extraer_titulo_disposicion(&coincidencia) {
+                        None => texto_limpio(&coincidencia),
+                        Some(titulo) => titulo,
+                    };
+                    enlaces.push(EnlacePDF {
+                        url: url.to_string(),
+                        rango: filtros::deducir_rango(&titulo),
+                        titulo,
+                        apartado: apartado.to_owned(),
+                        seccion: seccion.to_owned(),
+                        subseccion: subseccion.to_owned(),
+                        departamento: departamento.to_owned(),
+                        epigrafe: epigrafe.to_owned(),
+                        ..EnlacePDF::default()
+                    });
+                }
+            }
         }
-        let url = href.unwrap().to_string();
-        let titulo = coincidencia.text().collect::<String>();
-        enlaces.push(EnlacePDF {
-            url: format!("https://boe.es{}", url),
-            titulo,
-            apartado: apartado.to_owned(),
-        });
     }
-    if enlaces.len() == 0 {
-            println!(
-                "🟡 no se han encontrado enlaces {}", apartado,
-            );
+    if enlaces.is_empty() {
+        warn!("no se han encontrado enlaces {}", apartado);
     } else {
-        println!("🟢 {} enlaces encontrados en el apartado {}", enlaces.len(), apartado)
+        info!(
+            "{} enlaces encontrados en el apartado {}",
+            enlaces.len(),
+            apartado
+        )
+    }
+    for enlace in &enlaces {
+        progreso::emitir(EventoProgreso::EnlaceEncontrado { enlace });
     }
     enlaces
 }
-fn tiempo_espera_descargas() -> u64{
-    match env::var("BOE_COMPLETO_ESPERA") {
-        Err(_) => {
-            return 3;
-        },
-        Ok(var) => match var.parse::<u64>() {
-            Err(_) => {
-                return 3;
+// Se escribe con otro nombre y se renombra al terminar, para que el servidor u otro proceso no
+// lean nunca un PDF o un informe a medias
+fn escribir_de_una_vez(ruta: &Path, contenido: &[u8]) -> io::Result<()> {
+    let mut ruta_parcial = ruta.as_os_str().to_owned();
+    ruta_parcial.push(".parcial");
+    fs::write(&ruta_parcial, contenido)?;
+    fs::rename(&ruta_parcial, ruta)
+}
+// "A. Anuncios de licitaciones públicas y adjudicaciones", una letra mayúscula y un punto
+fn es_subseccion(encabezado: &str) -> bool {
+    let mut caracteres = encabezado.chars();
+    matches!(
+        (caracteres.next(), caracteres.next(), caracteres.next()),
+        (Some(letra), Some('.'), Some(' ')) if letra.is_ascii_uppercase()
+    )
+}
+// El texto del enlace solo indica "PDF (BOE-A-...)", el título real está en el párrafo
+// de la disposición que contiene la lista de enlaces
+fn extraer_titulo_disposicion(enlace: &ElementRef) -> Option<String> {
+    for ancestro in enlace.ancestors().filter_map(ElementRef::wrap) {
+        if ancestro.value().name() != "li" {
+            continue;
+        }
+        for hijo in ancestro.children().filter_map(ElementRef::wrap) {
+            if hijo.value().name() != "p" {
+                continue;
+            }
+            let titulo = texto_limpio(&hijo);
+            if !titulo.is_empty() {
+                return Some(titulo);
             }
-            Ok(ok) => ok,
         }
-    } 
+    }
+    None
+}
+fn texto_limpio(elemento: &ElementRef) -> String {
+    elemento
+        .text()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
 }
 
-pub async fn crear_gestor_descargas(fecha: &str) -> Result<(), String> {
-    let mut gdb = GestorDescargaBoe::from(fecha);
+pub async fn crear_gestor_descargas(
+    fecha: &str,
+    configuracion: &Configuracion,
+) -> Result<(), String> {
+    if let Err(error) = fs::create_dir_all(&configuracion.directorio_salida) {
+        let mensaje_error = format!(
+            "no se ha podido crear el directorio de salida {} {}",
+            configuracion.directorio_salida.display(),
+            error
+        );
+        return Err(mensaje_error);
+    }
+    let mut gdb = GestorDescargaBoe::nuevo(fecha, configuracion)?;
     gdb.buscar_pdf_disposiciones().await;
     gdb.buscar_pdf_notificaciones().await;
     gdb.buscar_pdf_edictos().await;
+    gdb.aplicar_filtros();
+    gdb.eliminar_duplicados();
+    gdb.leer_metadatos_xml().await;
+    gdb.vincular_correcciones().await;
     gdb.generar_informe_descargas();
     //gdb.testear_numero_reducido();
     gdb.descargar_pdf_memoria().await;
+    gdb.verificar_firmas();
+    gdb.guardar_pdf_individuales();
     gdb.unir_pdf_memoria();
+    gdb.generar_informe_descargas();
+    gdb.empaquetar();
 
-    if gdb.error_critico.is_some() {
-        return Err(gdb.error_critico.unwrap());
+    progreso::emitir(EventoProgreso::Finalizado {
+        correcto: gdb.error_critico.is_none(),
+        error: gdb.error_critico.as_deref(),
+    });
+    if let Some(error) = gdb.error_critico {
+        return Err(error);
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUMARIO_DISPOSICIONES: &str =
+        include_str!("../../tests/fixtures/boe.es/boe/dias/2024/12/07/index.html");
+
+    #[test]
+    fn extrae_enlaces_y_metadatos_del_sumario() {
+        let html = Html::parse_document(SUMARIO_DISPOSICIONES);
+        let selector = Selector::parse("#indiceSumario .sumario").unwrap();
+        let url_pagina = Url::parse("http://localhost/boe/dias/2024/12/07/").unwrap();
+        let enlaces = devolver_coincidencias_enlace_pdf(
+            html,
+            &selector,
+            String::from("Disposiciones y anuncios"),
+            "http://localhost",
+            &url_pagina,
+        );
+        assert_eq!(enlaces.len(), 3);
+
+        let real_decreto = &enlaces[0];
+        assert_eq!(
+            real_decreto.url,
+            "http://localhost/boe/dias/2024/12/07/pdfs/BOE-A-2024-25512.pdf"
+        );
+        assert_eq!(
+            real_decreto.titulo,
+            "Real Decreto 1155/2024, de 19 de noviembre, por el que se aprueba el Reglamento de prestaciones por desempleo."
+        );
+        assert_eq!(real_decreto.seccion, "I. Disposiciones generales");
+        assert_eq!(
+            real_decreto.departamento,
+            "MINISTERIO DE TRABAJO Y ECONOMÍA SOCIAL"
+        );
+        assert_eq!(real_decreto.epigrafe, "Seguridad Social");
+        assert_eq!(real_decreto.rango, "Real Decreto");
+
+        // Cada encabezado reinicia los de nivel inferior y el contenedor siguiente empieza de cero
+        assert_eq!(enlaces[1].departamento, "MINISTERIO DE HACIENDA");
+        assert_eq!(enlaces[1].rango, "Resolución");
+        assert_eq!(enlaces[2].seccion, "V. Anuncios");
+        // En los anuncios el h4 es la subsección y el departamento viene en el h5
+        assert_eq!(
+            enlaces[2].subseccion,
+            "A. Anuncios de licitaciones públicas y adjudicaciones"
+        );
+        assert_eq!(enlaces[2].departamento, "MINISTERIO DE DEFENSA");
+        assert_eq!(enlaces[2].epigrafe, "");
+        assert!(!es_subseccion("MINISTERIO DE DEFENSA"));
+    }
+
+    #[test]
+    fn mantiene_los_enlaces_absolutos() {
+        let html = Html::parse_document(
+            r#"<div class="sumario"><ul><li class="dispo"><p>Orden de prueba</p>
+            <ul><li class="puntoPDF"><a href="https://www.boe.es/otro/BOE-A-2024-1.pdf">PDF</a></li>
+            <li class="puntoPDF"><a href="pdfs/BOE-A-2024-2.pdf">PDF</a></li>
+            <li class="puntoPDF"><a href="/boe/dias/2024/12/07/pdfs/BOE-A-2024-3.pdf">PDF</a></li></ul></li></ul></div>"#,
+        );
+        let selector = Selector::parse(".sumario").unwrap();
+        let url_base = "http://replica.local/espejo";
+        let url_pagina = Url::parse("http://replica.local/espejo/boe/dias/2024/12/07/").unwrap();
+        let enlaces = devolver_coincidencias_enlace_pdf(
+            html,
+            &selector,
+            String::from("Prueba"),
+            url_base,
+            &url_pagina,
+        );
+        assert_eq!(enlaces[0].url, "https://www.boe.es/otro/BOE-A-2024-1.pdf");
+        assert_eq!(
+            enlaces[1].url,
+            "http://replica.local/espejo/boe/dias/2024/12/07/pdfs/BOE-A-2024-2.pdf"
+        );
+        // Los enlaces desde la raíz conservan la subruta de la réplica
+        assert_eq!(
+            enlaces[2].url,
+            "http://replica.local/espejo/boe/dias/2024/12/07/pdfs/BOE-A-2024-3.pdf"
+        );
+    }
+
+    #[test]
+    fn une_la_url_base_con_subruta() {
+        let ruta = "boe_n/dias/2024/12/07/index.php?l=N";
+        assert_eq!(
+            unir_url_base("https://boe.es", ruta).unwrap().as_str(),
+            "https://boe.es/boe_n/dias/2024/12/07/index.php?l=N"
+        );
+        assert_eq!(
+            unir_url_base("http://espejo.local/replica", ruta)
+                .unwrap()
+                .as_str(),
+            "http://espejo.local/replica/boe_n/dias/2024/12/07/index.php?l=N"
+        );
+        assert!(unir_url_base("espejo sin esquema", ruta).is_err());
+    }
+
+    #[test]
+    fn aplica_la_plantilla_de_nombre() {
+        let configuracion = Configuracion {
+            directorio_salida: PathBuf::from("/tmp/boe"),
+            plantilla_nombre: String::from("boe_{año}{mes}{dia}"),
+            ..Configuracion::default()
+        };
+        let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+        assert_eq!(
+            ruta_salida(&configuracion, &fecha, "_informe.tsv"),
+            PathBuf::from("/tmp/boe/boe_20241207_informe.tsv")
+        );
+    }
+}
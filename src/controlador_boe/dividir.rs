@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+use super::{descargar::EnlacePDF, filtros};
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Division {
+    #[default]
+    Ninguna,
+    Apartado,
+    Seccion,
+    Departamento,
+}
+
+impl Division {
+    pub fn desde_texto(texto: &str) -> Result<Division, String> {
+        match texto {
+            "ninguna" => Ok(Division::Ninguna),
+            "apartado" => Ok(Division::Apartado),
+            "seccion" => Ok(Division::Seccion),
+            "departamento" => Ok(Division::Departamento),
+            _ => Err(format!(
+                "la división {} no es válida, se esperaba {}",
+                texto, "ninguna, apartado, seccion o departamento"
+            )),
+        }
+    }
+    // Parte del nombre de archivo a la que pertenece el enlace, None si no se divide
+    pub fn grupo(&self, enlace: &EnlacePDF) -> Option<String> {
+        let valor = match self {
+            Division::Ninguna => return None,
+            Division::Apartado => &enlace.apartado,
+            Division::Seccion => &enlace.seccion,
+            Division::Departamento => &enlace.departamento,
+        };
+        Some(nombre_grupo(valor))
+    }
+}
+
+// Reparte los elementos en grupos conservando el orden en que aparece cada grupo en el sumario
+pub fn agrupar<T>(
+    elementos: Vec<T>,
+    division: Division,
+    enlace: impl Fn(&T) -> &EnlacePDF,
+) -> Vec<(Option<String>, Vec<T>)> {
+    let mut grupos: Vec<(Option<String>, Vec<T>)> = Vec::new();
+    for elemento in elementos {
+        let grupo = division.grupo(enlace(&elemento));
+        match grupos.iter_mut().find(|(g, _)| *g == grupo) {
+            Some((_, miembros)) => miembros.push(elemento),
+            None => grupos.push((grupo, vec![elemento])),
+        }
+    }
+    grupos
+}
+
+// "III. Otras disposiciones" -> "iii_otras_disposiciones"
+pub fn nombre_grupo(texto: &str) -> String {
+    let nombre = filtros::normalizar(texto)
+        .chars()
+        .map(|c| match c {
+            'ñ' => 'n',
+            _ if c.is_ascii_alphanumeric() => c,
+            _ => '_',
+        })
+        .collect::<String>()
+        .split('_')
+        .filter(|parte| !parte.is_empty())
+        .collect::<Vec<&str>>()
+        .join("_");
+    if nombre.is_empty() {
+        return String::from("sin_clasificar");
+    }
+    nombre
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn genera_nombres_de_archivo_legibles() {
+        assert_eq!(
+            nombre_grupo("III. Otras disposiciones"),
+            "iii_otras_disposiciones"
+        );
+        assert_eq!(
+            nombre_grupo("MINISTERIO DE TRABAJO Y ECONOMÍA SOCIAL"),
+            "ministerio_de_trabajo_y_economia_social"
+        );
+        assert_eq!(nombre_grupo("Señalamientos"), "senalamientos");
+        assert_eq!(nombre_grupo(""), "sin_clasificar");
+    }
+}
@@ -0,0 +1,322 @@
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+
+use super::descargar;
+
+// Elemento del XML de un documento del BOE con sus atributos, hijos y el texto que contiene
+// directamente, sin el de sus hijos
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ElementoXml {
+    pub nombre: String,
+    pub atributos: Vec<(String, String)>,
+    pub hijos: Vec<ElementoXml>,
+    pub texto: String,
+}
+
+impl ElementoXml {
+    pub fn atributo(&self, nombre: &str) -> Option<&str> {
+        self.atributos
+            .iter()
+            .find(|(clave, _)| clave == nombre)
+            .map(|(_, valor)| valor.as_str())
+    }
+    pub fn hijos<'a>(&'a self, nombre: &'a str) -> impl Iterator<Item = &'a ElementoXml> {
+        self.hijos.iter().filter(move |h| h.nombre == nombre)
+    }
+    // Ruta de hijos separada por /, como "analisis/referencias/anteriores"
+    pub fn buscar(&self, ruta: &str) -> Option<&ElementoXml> {
+        ruta.split('/').try_fold(self, |elemento, nombre| {
+            elemento.hijos.iter().find(|h| h.nombre == nombre)
+        })
+    }
+    // Texto del elemento de la ruta con los espacios normalizados, None si no existe o está vacío
+    pub fn texto_de(&self, ruta: &str) -> Option<String> {
+        let texto = normalizar_espacios(&self.buscar(ruta)?.texto);
+        match texto.is_empty() {
+            true => None,
+            false => Some(texto),
+        }
+    }
+}
+
+// http://boe.es/diario_boe/xml.php?id=BOE-A-2024-25512
+pub fn url_documento(url_base: &str, identificador: &str) -> Result<Url, String> {
+    descargar::unir_url_base(
+        url_base,
+        &format!("diario_boe/xml.php?id={}", identificador),
+    )
+}
+
+// Descarga el XML del documento y lo analiza
+pub async fn descargar_documento(
+    url_base: &str,
+    identificador: &str,
+    cliente: &Client,
+) -> Result<ElementoXml, String> {
+    let url = url_documento(url_base, identificador)?;
+    let xml = descargar::realizar_get_devolver_texto(url.as_str(), cliente).await?;
+    analizar(&xml)
+}
+
+// Metadatos del XML de un documento que no aparecen en el sumario. Las fechas van como AAAA-MM-DD
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetadatosXml {
+    pub identificador: String,
+    pub rango: Option<String>,
+    pub departamento: Option<String>,
+    pub numero_oficial: Option<String>,
+    pub fecha_disposicion: Option<String>,
+    pub fecha_publicacion: Option<String>,
+    pub fecha_vigencia: Option<String>,
+    pub pagina_inicial: Option<u32>,
+    pub pagina_final: Option<u32>,
+    pub url_eli: Option<String>,
+    pub estado_consolidacion: Option<String>,
+    pub materias: Vec<String>,
+    pub notas: Vec<String>,
+    pub alertas: Vec<String>,
+    // Disposiciones a las que afecta este documento y las que le afectan a él después
+    pub referencias_anteriores: Vec<ReferenciaXml>,
+    pub referencias_posteriores: Vec<ReferenciaXml>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReferenciaXml {
+    pub identificador: String,
+    // "MODIFICA", "DEROGA", "CORRECCIÓN de errores"...
+    pub relacion: String,
+    pub texto: String,
+}
+
+pub fn extraer_metadatos(documento: &ElementoXml) -> MetadatosXml {
+    let metadatos = |nombre: &str| documento.texto_de(&format!("metadatos/{}", nombre));
+    MetadatosXml {
+        identificador: metadatos("identificador").unwrap_or_default(),
+        rango: metadatos("rango"),
+        departamento: metadatos("departamento"),
+        numero_oficial: metadatos("numero_oficial"),
+        fecha_disposicion: metadatos("fecha_disposicion").map(|f| formatear_fecha(&f)),
+        fecha_publicacion: metadatos("fecha_publicacion").map(|f| formatear_fecha(&f)),
+        fecha_vigencia: metadatos("fecha_vigencia").map(|f| formatear_fecha(&f)),
+        pagina_inicial: metadatos("pagina_inicial").and_then(|p| p.parse().ok()),
+        pagina_final: metadatos("pagina_final").and_then(|p| p.parse().ok()),
+        url_eli: metadatos("url_eli"),
+        estado_consolidacion: metadatos("estado_consolidacion"),
+        materias: textos(documento, "analisis/materias", "materia"),
+        notas: textos(documento, "analisis/notas", "nota"),
+        alertas: textos(documento, "analisis/alertas", "alerta"),
+        referencias_anteriores: referencias(documento, "anteriores"),
+        referencias_posteriores: referencias(documento, "posteriores"),
+    }
+}
+
+pub fn metadatos_xml(xml: &str) -> Result<MetadatosXml, String> {
+    Ok(extraer_metadatos(&analizar(xml)?))
+}
+
+// Referencias "anteriores" o "posteriores" del análisis del documento
+pub fn referencias(documento: &ElementoXml, tipo: &str) -> Vec<ReferenciaXml> {
+    let Some(lista) = documento.buscar(&format!("analisis/referencias/{}", tipo)) else {
+        return Vec::new();
+    };
+    let elemento = tipo.strip_suffix("es").unwrap_or(tipo);
+    lista
+        .hijos(elemento)
+        .filter_map(|referencia| {
+            Some(ReferenciaXml {
+                identificador: referencia.atributo("referencia")?.to_string(),
+                relacion: referencia.texto_de("palabra").unwrap_or_default(),
+                texto: referencia.texto_de("texto").unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+fn textos(documento: &ElementoXml, ruta: &str, elemento: &str) -> Vec<String> {
+    match documento.buscar(ruta) {
+        None => Vec::new(),
+        Some(lista) => lista
+            .hijos(elemento)
+            .map(|h| normalizar_espacios(&h.texto))
+            .filter(|t| !t.is_empty())
+            .collect(),
+    }
+}
+
+// 20241128 -> 2024-11-28, cualquier otro formato se deja como está
+fn formatear_fecha(fecha: &str) -> String {
+    match fecha.len() == 8 && fecha.chars().all(|c| c.is_ascii_digit()) {
+        true => format!("{}-{}-{}", &fecha[..4], &fecha[4..6], &fecha[6..]),
+        false => fecha.to_string(),
+    }
+}
+
+// Analiza el XML con roxmltree y lo copia en un ElementoXml, que no depende del texto original y
+// se puede guardar después de descargarlo. El BOE no usa el DOCTYPE, pero se admite por si acaso
+pub fn analizar(xml: &str) -> Result<ElementoXml, String> {
+    let opciones = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..roxmltree::ParsingOptions::default()
+    };
+    let documento = match roxmltree::Document::parse_with_options(xml, opciones) {
+        Err(error) => {
+            let mensaje_error = format!("el XML no es válido {}", error);
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    Ok(copiar_elemento(documento.root_element()))
+}
+
+fn copiar_elemento(nodo: roxmltree::Node) -> ElementoXml {
+    ElementoXml {
+        nombre: nodo.tag_name().name().to_string(),
+        atributos: nodo
+            .attributes()
+            .map(|a| (a.name().to_string(), a.value().to_string()))
+            .collect(),
+        hijos: nodo
+            .children()
+            .filter(|h| h.is_element())
+            .map(copiar_elemento)
+            .collect(),
+        texto: nodo
+            .children()
+            .filter(|h| h.is_text())
+            .filter_map(|h| h.text())
+            .collect(),
+    }
+}
+
+pub fn normalizar_espacios(texto: &str) -> String {
+    texto.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XML_ORDEN: &str =
+        include_str!("../../tests/fixtures/boe.es/diario_boe/xml/BOE-A-2024-25900.xml");
+    const XML_CORRECCION: &str =
+        include_str!("../../tests/fixtures/boe.es/diario_boe/xml/BOE-A-2024-25902.xml");
+
+    #[test]
+    fn lee_elementos_atributos_y_texto() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- documento de prueba -->
+<documento fecha_actualizacion="20241212">
+  <metadatos>
+    <rango codigo="1350">Orden</rango>
+    <titulo>Orden  HAC/1460/2024, de 9 de diciembre &amp; otras &#xE1;&#233;</titulo>
+    <subseccion/>
+  </metadatos>
+  <texto><![CDATA[<p>sin analizar</p>]]></texto>
+  <enlace url='a>b' />
+</documento>"#;
+        let documento = analizar(xml).unwrap();
+        assert_eq!(documento.nombre, "documento");
+        assert_eq!(documento.atributo("fecha_actualizacion"), Some("20241212"));
+        let rango = documento.buscar("metadatos/rango").unwrap();
+        assert_eq!(
+            (rango.texto.as_str(), rango.atributo("codigo")),
+            ("Orden", Some("1350"))
+        );
+        assert_eq!(
+            documento.texto_de("metadatos/titulo").unwrap(),
+            "Orden HAC/1460/2024, de 9 de diciembre & otras áé"
+        );
+        assert_eq!(documento.texto_de("metadatos/subseccion"), None);
+        assert_eq!(documento.texto_de("texto").unwrap(), "<p>sin analizar</p>");
+        assert_eq!(
+            documento.hijos("enlace").next().unwrap().atributo("url"),
+            Some("a>b")
+        );
+    }
+
+    #[test]
+    fn rechaza_el_xml_mal_formado() {
+        assert!(analizar("<a><b></a>")
+            .unwrap_err()
+            .contains("el XML no es válido"));
+        assert!(analizar("<a><b>").is_err());
+        assert!(analizar("solo texto").is_err());
+    }
+
+    #[test]
+    fn extrae_los_metadatos_de_una_orden() {
+        let metadatos = metadatos_xml(XML_ORDEN).unwrap();
+        assert_eq!(metadatos.identificador, "BOE-A-2024-25900");
+        assert_eq!(metadatos.rango.as_deref(), Some("Orden"));
+        assert_eq!(
+            metadatos.departamento.as_deref(),
+            Some("Ministerio de Hacienda")
+        );
+        assert_eq!(metadatos.numero_oficial.as_deref(), Some("HAC/1460/2024"));
+        assert_eq!(metadatos.fecha_disposicion.as_deref(), Some("2024-12-09"));
+        assert_eq!(metadatos.fecha_publicacion.as_deref(), Some("2024-12-12"));
+        assert_eq!(metadatos.fecha_vigencia.as_deref(), Some("2025-01-01"));
+        assert_eq!(
+            (metadatos.pagina_inicial, metadatos.pagina_final),
+            (Some(170498), Some(170510))
+        );
+        assert_eq!(
+            metadatos.url_eli.as_deref(),
+            Some("https://www.boe.es/eli/es/o/2024/12/09/hac1460")
+        );
+        assert_eq!(
+            metadatos.estado_consolidacion.as_deref(),
+            Some("Finalizado")
+        );
+        assert_eq!(
+            metadatos.materias,
+            vec![
+                "Impuesto sobre Sociedades",
+                "Operaciones vinculadas",
+                "Modelos tributarios"
+            ]
+        );
+        assert_eq!(
+            metadatos.notas,
+            vec!["Entrada en vigor: 1 de enero de 2025."]
+        );
+        assert_eq!(metadatos.alertas, vec!["Impuestos"]);
+        assert_eq!(metadatos.referencias_anteriores.len(), 2);
+        assert_eq!(
+            metadatos.referencias_anteriores[0],
+            ReferenciaXml {
+                identificador: String::from("BOE-A-2017-11880"),
+                relacion: String::from("MODIFICA"),
+                texto: String::from(
+                    "el art. 2 y el anexo de la Orden HFP/816/2017, de 28 de agosto"
+                ),
+            }
+        );
+        assert_eq!(
+            metadatos.referencias_posteriores[0].identificador,
+            "BOE-A-2024-25901"
+        );
+        assert_eq!(
+            metadatos.referencias_posteriores[0].relacion,
+            "SE CORRIGEN errores"
+        );
+    }
+
+    #[test]
+    fn deja_vacios_los_metadatos_que_faltan() {
+        let metadatos = metadatos_xml(XML_CORRECCION).unwrap();
+        assert_eq!(metadatos.rango.as_deref(), Some("Resolución"));
+        assert_eq!(metadatos.fecha_disposicion.as_deref(), Some("2024-11-28"));
+        assert_eq!(metadatos.numero_oficial, None);
+        assert_eq!(metadatos.url_eli, None);
+        assert_eq!(metadatos.fecha_vigencia, None);
+        assert!(metadatos.notas.is_empty() && metadatos.alertas.is_empty());
+        assert!(metadatos.referencias_posteriores.is_empty());
+        assert_eq!(
+            metadatos.referencias_anteriores[0].relacion,
+            "CORRECCIÓN de errores"
+        );
+        assert_eq!(formatear_fecha("2024-11-28"), "2024-11-28");
+    }
+}
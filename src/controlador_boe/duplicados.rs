@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use sha2::{Digest, Sha256};
+
+use super::descargar::EnlacePDF;
+
+// Conserva el primer enlace de cada url y anota en él dónde más aparecía
+pub fn eliminar_duplicados_url(enlaces: Vec<EnlacePDF>) -> Vec<EnlacePDF> {
+    let mut unicos: Vec<EnlacePDF> = Vec::new();
+    let mut posiciones: HashMap<String, usize> = HashMap::new();
+    for enlace in enlaces {
+        match posiciones.get(&enlace.url) {
+            Some(posicion) => anotar_ubicacion(&mut unicos[*posicion], &enlace),
+            None => {
+                posiciones.insert(enlace.url.to_owned(), unicos.len());
+                unicos.push(enlace);
+            }
+        }
+    }
+    unicos
+}
+
+// El duplicado puede traer a su vez otras ubicaciones si ya se había fusionado por url
+pub fn anotar_ubicacion(conservado: &mut EnlacePDF, duplicado: &EnlacePDF) {
+    let propia = conservado.ubicacion();
+    let ubicaciones = std::iter::once(duplicado.ubicacion()).chain(duplicado.tambien_en.to_owned());
+    for ubicacion in ubicaciones {
+        if ubicacion != propia && !conservado.tambien_en.contains(&ubicacion) {
+            conservado.tambien_en.push(ubicacion);
+        }
+    }
+}
+
+pub fn resumen_sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enlace(url: &str, seccion: &str) -> EnlacePDF {
+        EnlacePDF {
+            apartado: String::from("Disposiciones y anuncios"),
+            url: url.to_string(),
+            titulo: String::from("Orden de prueba"),
+            seccion: seccion.to_string(),
+            departamento: String::from("MINISTERIO DE HACIENDA"),
+            rango: String::from("Orden"),
+            ..EnlacePDF::default()
+        }
+    }
+
+    #[test]
+    fn conserva_el_primero_y_anota_las_demas_secciones() {
+        let enlaces = vec![
+            enlace("https://boe.es/a.pdf", "I. Disposiciones generales"),
+            enlace("https://boe.es/b.pdf", "III. Otras disposiciones"),
+            enlace("https://boe.es/a.pdf", "III. Otras disposiciones"),
+            enlace("https://boe.es/a.pdf", "III. Otras disposiciones"),
+        ];
+        let unicos = eliminar_duplicados_url(enlaces);
+        assert_eq!(unicos.len(), 2);
+        assert_eq!(unicos[0].seccion, "I. Disposiciones generales");
+        assert_eq!(unicos[0].tambien_en.len(), 1);
+        assert_eq!(unicos[0].tambien_en[0].seccion, "III. Otras disposiciones");
+        assert!(unicos[1].tambien_en.is_empty());
+    }
+
+    #[test]
+    fn calcula_el_resumen_en_hexadecimal() {
+        assert_eq!(
+            resumen_sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use lopdf::{
+    content::{Content, Operation},
+    dictionary, Dictionary, Document, Object, ObjectId, Stream, StringFormat,
+};
+
+use super::{descargar::EnlacePDF, individuales};
+
+// Nombre del recurso de fuente del sello, poco probable en los PDF del BOE
+const FUENTE_SELLO: &str = "FSelloBoe";
+const TAMAÑO_LETRA: f32 = 7.0;
+// Distancia del sello al borde inferior de la página
+const MARGEN: f32 = 12.0;
+// A4 en puntos, por si la página no indica su tamaño
+const PAGINA_A4: [f32; 4] = [0.0, 0.0, 595.0, 842.0];
+
+// Identificador con el que se conoce el documento, o su título si la url no lo incluye
+pub fn identificador(enlace: &EnlacePDF) -> String {
+    match individuales::identificador_boe(&enlace.url) {
+        identificador if !identificador.is_empty() => identificador,
+        _ => enlace.titulo.to_owned(),
+    }
+}
+
+// Añade al pie de cada página del documento el identificador, la sección y el número de página
+// dentro del PDF unido. primera es el número que tendrá su primera página una vez unido
+pub fn estampar_paginas(
+    documento: &mut Document,
+    enlace: &EnlacePDF,
+    primera: usize,
+    total: usize,
+) {
+    let seccion = match enlace.seccion.is_empty() {
+        true => &enlace.apartado,
+        false => &enlace.seccion,
+    };
+    let id_fuente = documento.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    // El contenido original va entre q y Q para que sus cambios de estado no afecten al sello
+    let guardar = documento.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+    for (indice, id_pagina) in documento.get_pages().into_values().enumerate() {
+        let texto = format!(
+            "{} · {} · pág. {} de {}",
+            identificador(enlace),
+            seccion,
+            primera + indice,
+            total
+        );
+        let caja = caja_pagina(documento, id_pagina);
+        let contenido = Content {
+            operations: vec![
+                Operation::new("Q", vec![]),
+                Operation::new("q", vec![]),
+                Operation::new("BT", vec![]),
+                Operation::new("g", vec![0.4.into()]),
+                Operation::new("Tf", vec![FUENTE_SELLO.into(), TAMAÑO_LETRA.into()]),
+                Operation::new(
+                    "Td",
+                    vec![(caja[0] + 2.0 * MARGEN).into(), (caja[1] + MARGEN).into()],
+                ),
+                Operation::new(
+                    "Tj",
+                    vec![Object::String(win_ansi(&texto), StringFormat::Literal)],
+                ),
+                Operation::new("ET", vec![]),
+                Operation::new("Q", vec![]),
+            ],
+        };
+        let sello = match contenido.encode() {
+            Err(_) => continue,
+            Ok(ok) => documento.add_object(Stream::new(Dictionary::new(), ok)),
+        };
+        let mut contenidos = vec![Object::Reference(guardar)];
+        contenidos.extend(
+            documento
+                .get_page_contents(id_pagina)
+                .into_iter()
+                .map(Object::Reference),
+        );
+        contenidos.push(Object::Reference(sello));
+        let recursos = recursos_con_fuente(documento, id_pagina, id_fuente);
+        if let Ok(pagina) = documento.get_dictionary_mut(id_pagina) {
+            pagina.set("Contents", contenidos);
+            pagina.set("Resources", recursos);
+        }
+    }
+}
+
+// Copia directa de los recursos de la página, propios o heredados, con la fuente del sello
+fn recursos_con_fuente(
+    documento: &Document,
+    id_pagina: ObjectId,
+    id_fuente: ObjectId,
+) -> Dictionary {
+    let mut recursos = match documento.get_page_resources(id_pagina) {
+        Ok((Some(recursos), _)) => recursos.clone(),
+        Ok((None, ids)) => ids
+            .first()
+            .and_then(|id| documento.get_dictionary(*id).ok())
+            .cloned()
+            .unwrap_or_default(),
+        Err(_) => Dictionary::new(),
+    };
+    let mut fuentes = match recursos.get(b"Font") {
+        Ok(Object::Dictionary(fuentes)) => fuentes.clone(),
+        Ok(Object::Reference(id)) => documento.get_dictionary(*id).cloned().unwrap_or_default(),
+        _ => Dictionary::new(),
+    };
+    fuentes.set(FUENTE_SELLO, id_fuente);
+    recursos.set("Font", fuentes);
+    recursos
+}
+
+// CropBox o MediaBox de la página, que pueden heredarse del árbol de páginas
+fn caja_pagina(documento: &Document, id_pagina: ObjectId) -> [f32; 4] {
+    let mut nodo = documento.get_dictionary(id_pagina).ok();
+    // Un límite de saltos evita bucles con árboles de páginas mal formados
+    for _ in 0..32 {
+        let diccionario = match nodo {
+            None => break,
+            Some(diccionario) => diccionario,
+        };
+        for clave in [b"CropBox".as_slice(), b"MediaBox"] {
+            let caja = diccionario
+                .get(clave)
+                .and_then(|c| match c {
+                    Object::Reference(id) => documento.get_object(*id),
+                    _ => Ok(c),
+                })
+                .and_then(Object::as_array);
+            if let Ok(caja) = caja {
+                let valores = caja
+                    .iter()
+                    .filter_map(|v| v.as_float().ok())
+                    .collect::<Vec<f32>>();
+                if let [x0, y0, x1, y1] = valores[..] {
+                    return [x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)];
+                }
+            }
+        }
+        nodo = diccionario
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .and_then(|id| documento.get_dictionary(id))
+            .ok();
+    }
+    PAGINA_A4
+}
+
+// Helvetica con WinAnsiEncoding coincide con Latin-1 en las letras del castellano
+fn win_ansi(texto: &str) -> Vec<u8> {
+    texto
+        .chars()
+        .map(|c| match c as u32 {
+            codigo @ 0x20..=0x7e | codigo @ 0xa0..=0xff => codigo as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+// Etiquetas de página para que los visores muestren "BOE-A-2024-25512 p.3". Cada elemento
+// indica la posición (desde 0) de la primera página de un documento en el PDF unido
+pub fn añadir_etiquetas(documento: &mut Document, inicios: &[(usize, String)]) {
+    let mut numeros = Vec::new();
+    for (inicio, identificador) in inicios {
+        numeros.push(Object::Integer(*inicio as i64));
+        numeros.push(Object::Dictionary(dictionary! {
+            "S" => "D",
+            "P" => lopdf::text_string(&format!("{} p.", identificador)),
+        }));
+    }
+    if let Ok(catalogo) = documento.catalog_mut() {
+        catalogo.set("PageLabels", dictionary! { "Nums" => numeros });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn documento_dos_paginas() -> Document {
+        let mut documento = Document::with_version("1.5");
+        let id_paginas = documento.new_object_id();
+        let fuente =
+            documento.add_object(dictionary! { "Type" => "Font", "BaseFont" => "BOEFont" });
+        let recursos = documento.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => fuente },
+        });
+        let mut paginas = Vec::new();
+        for _ in 0..2 {
+            let contenido =
+                documento.add_object(Stream::new(dictionary! {}, b"1 0 0 1 50 50 cm".to_vec()));
+            paginas.push(Object::Reference(documento.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => id_paginas,
+                "Contents" => contenido,
+            })));
+        }
+        documento.objects.insert(
+            id_paginas,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => paginas,
+                "Count" => 2,
+                "Resources" => recursos,
+                "MediaBox" => vec![0.into(), 0.into(), 420.into(), 595.into()],
+            }),
+        );
+        let catalogo =
+            documento.add_object(dictionary! { "Type" => "Catalog", "Pages" => id_paginas });
+        documento.trailer.set("Root", catalogo);
+        documento
+    }
+
+    fn enlace() -> EnlacePDF {
+        EnlacePDF {
+            apartado: String::from("Disposiciones y anuncios"),
+            url: String::from("https://boe.es/boe/dias/2024/12/07/pdfs/BOE-A-2024-25512.pdf"),
+            titulo: String::from("Real Decreto de prueba"),
+            seccion: String::from("I. Disposiciones generales"),
+            ..EnlacePDF::default()
+        }
+    }
+
+    #[test]
+    fn estampa_cada_pagina_sin_perder_sus_recursos() {
+        let mut documento = documento_dos_paginas();
+        estampar_paginas(&mut documento, &enlace(), 346, 812);
+
+        let paginas = documento.get_pages().into_values().collect::<Vec<_>>();
+        let contenido = documento.get_page_content(paginas[1]).unwrap();
+        let sello = win_ansi("BOE-A-2024-25512 · I. Disposiciones generales · pág. 347 de 812");
+        assert!(contenido
+            .windows(sello.len())
+            .any(|v| v == sello.as_slice()));
+        assert!(contenido.starts_with(b"q\n1 0 0 1 50 50 cm"));
+
+        let (recursos, _) = documento.get_page_resources(paginas[0]).unwrap();
+        let fuentes = recursos
+            .unwrap()
+            .get(b"Font")
+            .and_then(Object::as_dict)
+            .unwrap();
+        assert!(fuentes.has(b"F1"));
+        assert!(fuentes.has(FUENTE_SELLO.as_bytes()));
+        assert_eq!(
+            caja_pagina(&documento, paginas[0]),
+            [0.0, 0.0, 420.0, 595.0]
+        );
+    }
+
+    #[test]
+    fn añade_las_etiquetas_de_pagina() {
+        let mut documento = documento_dos_paginas();
+        let inicios = vec![
+            (0, identificador(&enlace())),
+            (2, String::from("BOE-B-2024-41020")),
+        ];
+        añadir_etiquetas(&mut documento, &inicios);
+
+        let numeros = documento
+            .catalog()
+            .unwrap()
+            .get(b"PageLabels")
+            .and_then(Object::as_dict)
+            .and_then(|e| e.get(b"Nums"))
+            .and_then(Object::as_array)
+            .unwrap();
+        assert_eq!(numeros.len(), 4);
+        assert_eq!(numeros[2].as_i64().unwrap(), 2);
+        let prefijo = numeros[1].as_dict().unwrap().get(b"P").unwrap();
+        assert_eq!(
+            lopdf::decode_text_string(prefijo).unwrap(),
+            "BOE-A-2024-25512 p."
+        );
+    }
+}
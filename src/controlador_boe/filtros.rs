@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+
+use super::descargar::EnlacePDF;
+
+// Rangos reconocidos al inicio del título de la disposición. El orden importa:
+// los más específicos deben ir antes que sus prefijos ("Real Decreto-ley" antes que "Real Decreto")
+const RANGOS: [&str; 14] = [
+    "Ley Orgánica",
+    "Ley",
+    "Real Decreto Legislativo",
+    "Real Decreto-ley",
+    "Real Decreto",
+    "Decreto",
+    "Orden",
+    "Resolución",
+    "Acuerdo",
+    "Instrucción",
+    "Circular",
+    "Corrección de errores",
+    "Edicto",
+    "Anuncio",
+];
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filtros {
+    pub departamentos: Vec<String>,
+    pub epigrafes: Vec<String>,
+    pub rangos: Vec<String>,
+}
+
+impl Filtros {
+    pub fn esta_vacio(&self) -> bool {
+        self.departamentos.is_empty() && self.epigrafes.is_empty() && self.rangos.is_empty()
+    }
+    // Dentro de un mismo campo basta con que coincida uno de los valores indicados,
+    // entre campos distintos deben cumplirse todos
+    pub fn admite(&self, enlace: &EnlacePDF) -> bool {
+        coincide_alguno(&self.departamentos, &enlace.departamento)
+            && coincide_alguno(&self.epigrafes, &enlace.epigrafe)
+            && coincide_alguno(&self.rangos, &enlace.rango)
+    }
+}
+
+fn coincide_alguno(buscados: &[String], valor: &str) -> bool {
+    if buscados.is_empty() {
+        return true;
+    }
+    let valor = normalizar(valor);
+    buscados.iter().any(|b| valor.contains(&normalizar(b)))
+}
+
+pub fn normalizar(texto: &str) -> String {
+    texto
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'á' | 'à' => 'a',
+            'é' | 'è' => 'e',
+            'í' | 'ì' => 'i',
+            'ó' | 'ò' => 'o',
+            'ú' | 'ù' | 'ü' => 'u',
+            _ => c,
+        })
+        .collect()
+}
+
+pub fn deducir_rango(titulo: &str) -> String {
+    let titulo = titulo.trim();
+    for rango in RANGOS {
+        if let Some(resto) = titulo.strip_prefix(rango) {
+            // Evitar que "Ley" coincida con palabras como "Leyes"
+            if resto.is_empty() || !resto.starts_with(char::is_alphanumeric) {
+                return rango.to_string();
+            }
+        }
+    }
+    String::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enlace(departamento: &str, epigrafe: &str, titulo: &str) -> EnlacePDF {
+        EnlacePDF {
+            apartado: String::from("Disposiciones y anuncios"),
+            titulo: titulo.to_string(),
+            departamento: departamento.to_string(),
+            epigrafe: epigrafe.to_string(),
+            rango: deducir_rango(titulo),
+            ..EnlacePDF::default()
+        }
+    }
+
+    #[test]
+    fn deduce_el_rango_mas_especifico() {
+        assert_eq!(
+            deducir_rango("Real Decreto-ley 9/2024, de 23 de diciembre"),
+            "Real Decreto-ley"
+        );
+        assert_eq!(
+            deducir_rango("Real Decreto 1155/2024, de 19 de noviembre"),
+            "Real Decreto"
+        );
+        assert_eq!(
+            deducir_rango("Ley Orgánica 1/2024, de 10 de junio"),
+            "Ley Orgánica"
+        );
+        assert_eq!(deducir_rango("Ley 7/2024, de 20 de diciembre"), "Ley");
+        assert_eq!(deducir_rango("Leyes publicadas"), "");
+        assert_eq!(
+            deducir_rango("Corrección de errores de la Orden HAC/1432/2024"),
+            "Corrección de errores"
+        );
+    }
+
+    #[test]
+    fn filtra_sin_distinguir_mayusculas_ni_tildes() {
+        let filtros = Filtros {
+            departamentos: vec![String::from("economia social")],
+            epigrafes: Vec::new(),
+            rangos: vec![String::from("REAL DECRETO"), String::from("orden")],
+        };
+        let real_decreto = enlace(
+            "MINISTERIO DE TRABAJO Y ECONOMÍA SOCIAL",
+            "Seguridad Social",
+            "Real Decreto 1155/2024, de 19 de noviembre",
+        );
+        let resolucion = enlace(
+            "MINISTERIO DE TRABAJO Y ECONOMÍA SOCIAL",
+            "Convenios colectivos",
+            "Resolución de 2 de diciembre de 2024",
+        );
+        let otro_departamento = enlace(
+            "MINISTERIO DE HACIENDA",
+            "Impuestos",
+            "Orden HAC/1432/2024, de 4 de diciembre",
+        );
+        assert!(filtros.admite(&real_decreto));
+        assert!(!filtros.admite(&resolucion));
+        assert!(!filtros.admite(&otro_departamento));
+        assert!(Filtros::default().admite(&otro_departamento));
+    }
+}
@@ -0,0 +1,634 @@
+use std::{fs, io::Write, path::Path, time::SystemTime};
+
+use chrono::Utc;
+use cms::{
+    cert::{CertificateChoices, IssuerAndSerialNumber},
+    content_info::{CmsVersion, ContentInfo},
+    signed_data::{
+        CertificateSet, EncapsulatedContentInfo, SignedData, SignerIdentifier, SignerInfo,
+        SignerInfos,
+    },
+};
+use der::{
+    asn1::{OctetString, SetOfVec},
+    oid::db::{rfc5911, rfc5912},
+    Any, Decode, Encode,
+};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, StringFormat};
+use p12_keystore::KeyStore;
+use rsa::{
+    pkcs1::EncodeRsaPublicKey, pkcs8::DecodePrivateKey, Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey,
+};
+use sha2::{Digest, Sha256};
+use x509_cert::{attr::Attribute, spki::AlgorithmIdentifierOwned, time::Time, Certificate};
+
+use super::{firmas, metadatos};
+
+// Bytes reservados para la firma CMS, suficiente para el certificado y su cadena
+const TAMAÑO_FIRMA: usize = 16384;
+// Anchura fija de cada número de ByteRange para poder escribirlo una vez conocido el tamaño
+const ANCHURA_RANGO: usize = 10;
+const NOMBRE_CAMPO: &str = "FirmaBoeCompleto";
+
+pub struct Firmante {
+    clave: RsaPrivateKey,
+    certificado: Certificate,
+    // Certificados de la cadena que se incluyen en la firma, el del firmante el primero
+    cadena: Vec<Certificate>,
+}
+
+// Lee la clave privada y los certificados de un archivo PKCS#12 (.p12 o .pfx)
+pub fn cargar_firmante(ruta: &Path, contraseña: &str) -> Result<Firmante, String> {
+    let contenido = match fs::read(ruta) {
+        Err(error) => {
+            let mensaje_error = format!(
+                "no se ha podido leer el certificado de firma {} {}",
+                ruta.display(),
+                error
+            );
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    let almacen = match KeyStore::from_pkcs12(&contenido, contraseña) {
+        Err(error) => {
+            let mensaje_error = format!(
+                "no se ha podido abrir el certificado de firma {}, puede que la contraseña no sea correcta {}",
+                ruta.display(),
+                error
+            );
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    let (_, entrada) = almacen.private_key_chain().ok_or_else(|| {
+        format!(
+            "el certificado de firma {} no contiene ninguna clave privada",
+            ruta.display()
+        )
+    })?;
+    let clave = RsaPrivateKey::from_pkcs8_der(entrada.key())
+        .map_err(|error| format!("la clave del certificado de firma no es RSA {}", error))?;
+    let mut cadena = entrada
+        .chain()
+        .iter()
+        .map(|c| Certificate::from_der(c.as_der()))
+        .collect::<Result<Vec<Certificate>, _>>()
+        .map_err(|error| format!("el certificado de firma no es válido {}", error))?;
+    // El certificado del firmante es el que corresponde a la clave privada
+    let publica = RsaPublicKey::from(&clave)
+        .to_pkcs1_der()
+        .map_err(|error| format!("no se ha podido codificar la clave pública {}", error))?;
+    let posicion = cadena
+        .iter()
+        .position(|c| {
+            c.tbs_certificate
+                .subject_public_key_info
+                .subject_public_key
+                .raw_bytes()
+                == publica.as_bytes()
+        })
+        .ok_or_else(|| {
+            format!(
+                "el certificado de firma {} no incluye el certificado de su clave privada",
+                ruta.display()
+            )
+        })?;
+    let certificado = cadena.remove(posicion);
+    cadena.insert(0, certificado.clone());
+    Ok(Firmante {
+        clave,
+        certificado,
+        cadena,
+    })
+}
+
+// Añade una firma PAdES básica (adbe.pkcs7.detached) en una actualización incremental, de
+// modo que el contenido original del PDF queda intacto y cubierto por la firma
+pub fn firmar_pdf(contenido: &[u8], firmante: &Firmante) -> Result<Vec<u8>, String> {
+    let documento = match Document::load_mem(contenido) {
+        Err(error) => {
+            let mensaje_error = format!("no se ha podido leer el PDF a firmar {}", error);
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    let id_catalogo = documento
+        .trailer
+        .get(b"Root")
+        .and_then(Object::as_reference)
+        .map_err(|_| String::from("el PDF a firmar no tiene catálogo"))?;
+    let id_pagina = *documento
+        .get_pages()
+        .values()
+        .next()
+        .ok_or_else(|| String::from("el PDF a firmar no tiene páginas"))?;
+    let primer_id = documento
+        .trailer
+        .get(b"Size")
+        .and_then(Object::as_i64)
+        .map(|s| s as u32)
+        .unwrap_or(documento.max_id + 1)
+        .max(documento.max_id + 1);
+    let id_firma = (primer_id, 0);
+    let id_campo = (primer_id + 1, 0);
+
+    let mut catalogo = documento
+        .get_dictionary(id_catalogo)
+        .cloned()
+        .map_err(|_| String::from("el PDF a firmar no tiene catálogo"))?;
+    let mut formulario = resolver_diccionario(&documento, catalogo.get(b"AcroForm").ok());
+    let mut campos = resolver_lista(&documento, formulario.get(b"Fields").ok());
+    campos.push(Object::Reference(id_campo));
+    formulario.set("Fields", campos);
+    // SignaturesExist y AppendOnly
+    formulario.set("SigFlags", 3);
+    catalogo.set("AcroForm", formulario);
+
+    let mut pagina = documento
+        .get_dictionary(id_pagina)
+        .cloned()
+        .map_err(|_| String::from("no se ha podido leer la primera página del PDF a firmar"))?;
+    let mut anotaciones = resolver_lista(&documento, pagina.get(b"Annots").ok());
+    anotaciones.push(Object::Reference(id_campo));
+    pagina.set("Annots", anotaciones);
+
+    // Campo invisible, con rectángulo nulo, que también hace de anotación en la primera página
+    let campo = dictionary! {
+        "Type" => "Annot",
+        "Subtype" => "Widget",
+        "FT" => "Sig",
+        "T" => Object::string_literal(NOMBRE_CAMPO),
+        "Rect" => vec![0.into(), 0.into(), 0.into(), 0.into()],
+        "F" => 132,
+        "P" => id_pagina,
+        "V" => id_firma,
+    };
+    let nombre = firmas::nombre_comun(&firmante.certificado.tbs_certificate.subject);
+    let marcador_rango = format!(
+        "[0 {:<ancho$} {:<ancho$} {:<ancho$}]",
+        "",
+        "",
+        "",
+        ancho = ANCHURA_RANGO
+    );
+    let mut firma = Vec::new();
+    firma
+        .extend_from_slice(b"<< /Type /Sig /Filter /Adobe.PPKLite /SubFilter /adbe.pkcs7.detached");
+    firma.extend_from_slice(format!(" /ByteRange {}", marcador_rango).as_bytes());
+    firma.extend_from_slice(b" /Contents <");
+    firma.extend_from_slice(&vec![b'0'; TAMAÑO_FIRMA * 2]);
+    firma.extend_from_slice(b"> /M ");
+    escribir_objeto(
+        &mut firma,
+        &Object::string_literal(metadatos::fecha_pdf(&Utc::now())),
+    )?;
+    firma.extend_from_slice(b" /Name ");
+    escribir_objeto(&mut firma, &lopdf::text_string(&nombre))?;
+    firma.extend_from_slice(b" >>");
+
+    let mut salida = contenido.to_vec();
+    if salida.last() != Some(&b'\n') {
+        salida.push(b'\n');
+    }
+    let mut desplazamientos = Vec::new();
+    for (id, cuerpo) in [
+        (id_catalogo, serializar(&Object::Dictionary(catalogo))?),
+        (id_pagina, serializar(&Object::Dictionary(pagina))?),
+        (id_firma, firma),
+        (id_campo, serializar(&Object::Dictionary(campo))?),
+    ] {
+        desplazamientos.push((id, salida.len()));
+        salida.extend_from_slice(format!("{} {} obj\n", id.0, id.1).as_bytes());
+        salida.extend_from_slice(&cuerpo);
+        salida.extend_from_slice(b"\nendobj\n");
+    }
+    let inicio_firma = desplazamientos[2].1;
+    escribir_referencias(&mut salida, &documento, desplazamientos, primer_id + 2)?;
+
+    // Con el archivo completo ya se conocen los rangos que cubre la firma
+    let posicion_rango = buscar(&salida[inicio_firma..], marcador_rango.as_bytes())
+        .ok_or_else(|| String::from("no se ha encontrado el hueco de ByteRange"))?
+        + inicio_firma;
+    let inicio_hueco = buscar(&salida[inicio_firma..], b"/Contents <")
+        .ok_or_else(|| String::from("no se ha encontrado el hueco de la firma"))?
+        + inicio_firma
+        + b"/Contents ".len();
+    let fin_hueco = inicio_hueco + TAMAÑO_FIRMA * 2 + 2;
+    let rango = format!(
+        "[0 {:<ancho$} {:<ancho$} {:<ancho$}]",
+        inicio_hueco,
+        fin_hueco,
+        salida.len() - fin_hueco,
+        ancho = ANCHURA_RANGO
+    );
+    if rango.len() != marcador_rango.len() {
+        return Err(String::from("el PDF es demasiado grande para firmarlo"));
+    }
+    salida[posicion_rango..posicion_rango + rango.len()].copy_from_slice(rango.as_bytes());
+
+    let mut firmado = salida[..inicio_hueco].to_vec();
+    firmado.extend_from_slice(&salida[fin_hueco..]);
+    let cms = firma_cms(&firmado, firmante)?;
+    if cms.len() > TAMAÑO_FIRMA {
+        return Err(format!(
+            "la firma ocupa {} bytes y solo hay {} reservados",
+            cms.len(),
+            TAMAÑO_FIRMA
+        ));
+    }
+    let hexadecimal = cms.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    salida[inicio_hueco + 1..inicio_hueco + 1 + hexadecimal.len()]
+        .copy_from_slice(hexadecimal.as_bytes());
+    Ok(salida)
+}
+
+// Tabla de referencias de la actualización, del mismo tipo que la del PDF original
+fn escribir_referencias(
+    salida: &mut Vec<u8>,
+    documento: &Document,
+    mut desplazamientos: Vec<(ObjectId, usize)>,
+    tamaño: u32,
+) -> Result<(), String> {
+    let anterior = ultima_referencia(salida).ok_or_else(|| {
+        String::from("el PDF a firmar no indica dónde está su tabla de referencias")
+    })?;
+    let mut trailer = Dictionary::new();
+    for clave in [b"Root".as_slice(), b"Info", b"ID"] {
+        if let Ok(valor) = documento.trailer.get(clave) {
+            trailer.set(clave, valor.clone());
+        }
+    }
+    trailer.set("Prev", anterior as i64);
+    let inicio = salida.len();
+    let es_tabla = salida.get(anterior..anterior + 4) == Some(b"xref".as_slice());
+    if es_tabla {
+        trailer.set("Size", tamaño as i64);
+        desplazamientos.sort();
+        salida.extend_from_slice(b"xref\n");
+        for ((id, generacion), desplazamiento) in desplazamientos {
+            let entrada = format!("{} 1\n{:010} {:05} n \n", id, desplazamiento, generacion);
+            salida.extend_from_slice(entrada.as_bytes());
+        }
+        salida.extend_from_slice(b"trailer\n");
+        escribir_objeto(salida, &Object::Dictionary(trailer))?;
+    } else {
+        // La propia tabla es un objeto más de la actualización
+        desplazamientos.push(((tamaño, 0), inicio));
+        desplazamientos.sort();
+        let mut datos = Vec::new();
+        let mut indices = Vec::new();
+        for ((id, generacion), desplazamiento) in &desplazamientos {
+            datos.push(1u8);
+            datos.extend_from_slice(&(*desplazamiento as u64).to_be_bytes());
+            datos.extend_from_slice(&(*generacion).to_be_bytes());
+            indices.push(Object::Integer(*id as i64));
+            indices.push(Object::Integer(1));
+        }
+        trailer.set("Type", "XRef");
+        trailer.set("Size", tamaño as i64 + 1);
+        trailer.set("W", vec![1.into(), 8.into(), 2.into()]);
+        trailer.set("Index", indices);
+        trailer.set("Length", datos.len() as i64);
+        salida.extend_from_slice(format!("{} 0 obj\n", tamaño).as_bytes());
+        escribir_objeto(salida, &Object::Dictionary(trailer))?;
+        salida.extend_from_slice(b"\nstream\n");
+        salida.extend_from_slice(&datos);
+        salida.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+    salida.extend_from_slice(format!("\nstartxref\n{}\n%%EOF\n", inicio).as_bytes());
+    Ok(())
+}
+
+// Posición de la última tabla de referencias según el startxref del final del archivo
+pub fn ultima_referencia(contenido: &[u8]) -> Option<usize> {
+    let posicion = contenido.windows(9).rposition(|v| v == b"startxref")?;
+    let resto = String::from_utf8_lossy(&contenido[posicion + 9..]);
+    resto.split_whitespace().next()?.parse().ok()
+}
+
+fn buscar(contenido: &[u8], patron: &[u8]) -> Option<usize> {
+    contenido.windows(patron.len()).position(|v| v == patron)
+}
+
+fn resolver_diccionario(documento: &Document, valor: Option<&Object>) -> Dictionary {
+    match valor {
+        Some(Object::Dictionary(diccionario)) => diccionario.clone(),
+        Some(Object::Reference(id)) => documento.get_dictionary(*id).cloned().unwrap_or_default(),
+        _ => Dictionary::new(),
+    }
+}
+
+fn resolver_lista(documento: &Document, valor: Option<&Object>) -> Vec<Object> {
+    match valor {
+        Some(Object::Array(lista)) => lista.clone(),
+        Some(Object::Reference(id)) => documento
+            .get_object(*id)
+            .and_then(Object::as_array)
+            .cloned()
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+// CMS SignedData separado con los atributos que exige PAdES: tipo de contenido, resumen y fecha
+fn firma_cms(datos: &[u8], firmante: &Firmante) -> Result<Vec<u8>, String> {
+    let error_cms = |error: der::Error| format!("no se ha podido generar la firma {}", error);
+    let atributo = |oid, valor: Any| -> Result<Attribute, String> {
+        Ok(Attribute {
+            oid,
+            values: SetOfVec::try_from(vec![valor]).map_err(error_cms)?,
+        })
+    };
+    let resumen = OctetString::new(Sha256::digest(datos).to_vec()).map_err(error_cms)?;
+    let fecha = Time::try_from(SystemTime::now()).map_err(error_cms)?;
+    let atributos = SetOfVec::try_from(vec![
+        atributo(
+            rfc5911::ID_CONTENT_TYPE,
+            Any::encode_from(&rfc5911::ID_DATA).map_err(error_cms)?,
+        )?,
+        atributo(
+            rfc5911::ID_SIGNING_TIME,
+            Any::encode_from(&fecha).map_err(error_cms)?,
+        )?,
+        atributo(
+            rfc5911::ID_MESSAGE_DIGEST,
+            Any::encode_from(&resumen).map_err(error_cms)?,
+        )?,
+    ])
+    .map_err(error_cms)?;
+    let firmado = Sha256::digest(atributos.to_der().map_err(error_cms)?);
+    let firma = firmante
+        .clave
+        .sign(Pkcs1v15Sign::new::<Sha256>(), &firmado)
+        .map_err(|error| format!("no se ha podido firmar {}", error))?;
+    let sha256 = AlgorithmIdentifierOwned {
+        oid: rfc5912::ID_SHA_256,
+        parameters: None,
+    };
+    let informacion = SignerInfo {
+        version: CmsVersion::V1,
+        sid: SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+            issuer: firmante.certificado.tbs_certificate.issuer.clone(),
+            serial_number: firmante.certificado.tbs_certificate.serial_number.clone(),
+        }),
+        digest_alg: sha256.clone(),
+        signed_attrs: Some(atributos),
+        signature_algorithm: AlgorithmIdentifierOwned {
+            oid: rfc5912::RSA_ENCRYPTION,
+            parameters: Some(Any::null()),
+        },
+        signature: OctetString::new(firma).map_err(error_cms)?,
+        unsigned_attrs: None,
+    };
+    let certificados = firmante
+        .cadena
+        .iter()
+        .map(|c| CertificateChoices::Certificate(c.clone()))
+        .collect::<Vec<CertificateChoices>>();
+    let datos_firmados = SignedData {
+        version: CmsVersion::V1,
+        digest_algorithms: SetOfVec::try_from(vec![sha256]).map_err(error_cms)?,
+        encap_content_info: EncapsulatedContentInfo {
+            econtent_type: rfc5911::ID_DATA,
+            econtent: None,
+        },
+        certificates: Some(CertificateSet(
+            SetOfVec::try_from(certificados).map_err(error_cms)?,
+        )),
+        crls: None,
+        signer_infos: SignerInfos(SetOfVec::try_from(vec![informacion]).map_err(error_cms)?),
+    };
+    ContentInfo {
+        content_type: rfc5911::ID_SIGNED_DATA,
+        content: Any::encode_from(&datos_firmados).map_err(error_cms)?,
+    }
+    .to_der()
+    .map_err(error_cms)
+}
+
+fn serializar(objeto: &Object) -> Result<Vec<u8>, String> {
+    let mut contenido = Vec::new();
+    escribir_objeto(&mut contenido, objeto)?;
+    Ok(contenido)
+}
+
+// Sintaxis PDF de los objetos de la actualización, que nunca contienen flujos
+fn escribir_objeto(salida: &mut Vec<u8>, objeto: &Object) -> Result<(), String> {
+    let error_escritura = |error: std::io::Error| format!("no se ha podido escribir {}", error);
+    match objeto {
+        Object::Null => salida.extend_from_slice(b"null"),
+        Object::Boolean(valor) => write!(salida, "{}", valor).map_err(error_escritura)?,
+        Object::Integer(valor) => write!(salida, "{}", valor).map_err(error_escritura)?,
+        Object::Real(valor) => write!(salida, "{}", valor).map_err(error_escritura)?,
+        Object::Name(nombre) => escribir_nombre(salida, nombre),
+        Object::String(texto, StringFormat::Literal)
+            if texto.iter().all(|c| c.is_ascii_graphic() || *c == b' ') =>
+        {
+            salida.push(b'(');
+            for c in texto {
+                if matches!(c, b'(' | b')' | b'\\') {
+                    salida.push(b'\\');
+                }
+                salida.push(*c);
+            }
+            salida.push(b')');
+        }
+        Object::String(texto, _) => {
+            salida.push(b'<');
+            for c in texto {
+                write!(salida, "{:02x}", c).map_err(error_escritura)?;
+            }
+            salida.push(b'>');
+        }
+        Object::Array(lista) => {
+            salida.push(b'[');
+            for (indice, valor) in lista.iter().enumerate() {
+                if indice > 0 {
+                    salida.push(b' ');
+                }
+                escribir_objeto(salida, valor)?;
+            }
+            salida.push(b']');
+        }
+        Object::Dictionary(diccionario) => {
+            salida.extend_from_slice(b"<<");
+            for (clave, valor) in diccionario.iter() {
+                escribir_nombre(salida, clave);
+                salida.push(b' ');
+                escribir_objeto(salida, valor)?;
+            }
+            salida.extend_from_slice(b">>");
+        }
+        Object::Reference((id, generacion)) => {
+            write!(salida, "{} {} R", id, generacion).map_err(error_escritura)?
+        }
+        Object::Stream(_) => {
+            return Err(String::from("un flujo no puede ir dentro de otro objeto"));
+        }
+    }
+    Ok(())
+}
+
+fn escribir_nombre(salida: &mut Vec<u8>, nombre: &[u8]) {
+    salida.push(b'/');
+    for c in nombre {
+        match c {
+            b'!'..=b'~' if !b"#()<>[]{}/%".contains(c) => salida.push(*c),
+            _ => salida.extend_from_slice(format!("#{:02X}", c).as_bytes()),
+        }
+    }
+}
+
+// Las firmas de cada documento dejan de ser válidas al unirlo, así que se quitan sus campos,
+// anotaciones y diccionarios para que los visores no las muestren como alteradas
+pub fn quitar_firmas(documento: &mut Document) {
+    let firmas = documento
+        .objects
+        .iter()
+        .filter(|(_, objeto)| match objeto.as_dict() {
+            Ok(diccionario) => {
+                matches!(diccionario.get(b"FT"), Ok(Object::Name(tipo)) if tipo == b"Sig")
+                    || (diccionario.has(b"ByteRange") && diccionario.has(b"Contents"))
+            }
+            Err(_) => false,
+        })
+        .map(|(id, _)| *id)
+        .collect::<Vec<ObjectId>>();
+    if firmas.is_empty() {
+        return;
+    }
+    let es_firma = |valor: &Object| matches!(valor, Object::Reference(id) if firmas.contains(id));
+    for objeto in documento.objects.values_mut() {
+        let diccionario = match objeto.as_dict_mut() {
+            Err(_) => continue,
+            Ok(ok) => ok,
+        };
+        for clave in [b"Annots".as_slice(), b"Fields"] {
+            if let Ok(Object::Array(lista)) = diccionario.get_mut(clave) {
+                lista.retain(|valor| !es_firma(valor));
+            }
+        }
+        if let Ok(Object::Dictionary(formulario)) = diccionario.get_mut(b"AcroForm") {
+            if let Ok(Object::Array(campos)) = formulario.get_mut(b"Fields") {
+                campos.retain(|valor| !es_firma(valor));
+            }
+            formulario.remove(b"SigFlags");
+        }
+        // Permisos de certificación que apuntan a la firma eliminada
+        diccionario.remove(b"Perms");
+    }
+    for id in firmas {
+        documento.objects.remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use lopdf::xref::XrefType;
+
+    use super::*;
+    use crate::controlador_boe::firmas::{self, EstadoFirma};
+
+    const TABLA_CLASICA: &[u8] =
+        include_bytes!("../../tests/fixtures/boe.es/boe/dias/2024/12/07/pdfs/BOE-A-2024-25530.pdf");
+    const FIRMADO_BOE: &[u8] =
+        include_bytes!("../../tests/fixtures/boe.es/boe/dias/2024/12/11/pdfs/BOE-A-2024-25800.pdf");
+
+    fn fixture(nombre: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/firma")
+            .join(nombre)
+    }
+
+    fn firmante() -> Firmante {
+        cargar_firmante(&fixture("firmante.p12"), "boe").unwrap()
+    }
+
+    fn comprobar(firmado: &[u8]) {
+        let almacen = firmas::cargar_almacen(&[fixture("ca.pem")]).unwrap();
+        let verificacion = firmas::verificar_documento(firmado, &almacen);
+        assert_eq!(
+            verificacion.estado,
+            EstadoFirma::Valida,
+            "{}",
+            verificacion.detalle
+        );
+        assert_eq!(
+            verificacion.firmante.as_deref(),
+            Some("Agencia Estatal BOE de pruebas")
+        );
+        let documento = Document::load_mem(firmado).unwrap();
+        let formulario = documento
+            .catalog()
+            .unwrap()
+            .get(b"AcroForm")
+            .and_then(Object::as_dict)
+            .unwrap();
+        assert_eq!(formulario.get(b"SigFlags").unwrap().as_i64().unwrap(), 3);
+    }
+
+    #[test]
+    fn firma_con_tabla_de_referencias_clasica() {
+        let firmado = firmar_pdf(TABLA_CLASICA, &firmante()).unwrap();
+        assert!(firmado.starts_with(TABLA_CLASICA));
+        comprobar(&firmado);
+    }
+
+    #[test]
+    fn firma_con_flujo_de_referencias() {
+        let mut documento = Document::load_mem(TABLA_CLASICA).unwrap();
+        // Como el PDF unido, que lopdf guarda con flujo de referencias
+        documento.reference_table.cross_reference_type = XrefType::CrossReferenceStream;
+        let mut contenido = Vec::new();
+        documento.save_to(&mut contenido).unwrap();
+        let inicio = ultima_referencia(&contenido).unwrap();
+        assert_ne!(&contenido[inicio..inicio + 4], b"xref");
+
+        let firmado = firmar_pdf(&contenido, &firmante()).unwrap();
+        comprobar(&firmado);
+        assert_eq!(Document::load_mem(&firmado).unwrap().get_pages().len(), 1);
+    }
+
+    #[test]
+    fn no_abre_el_certificado_con_otra_contraseña() {
+        let error = cargar_firmante(&fixture("firmante.p12"), "otra")
+            .err()
+            .unwrap();
+        assert!(error.contains("contraseña"));
+    }
+
+    #[test]
+    fn quita_las_firmas_de_los_documentos_originales() {
+        let mut documento = Document::load_mem(FIRMADO_BOE).unwrap();
+        quitar_firmas(&mut documento);
+        let mut contenido = Vec::new();
+        documento.save_to(&mut contenido).unwrap();
+        let verificacion = firmas::verificar_documento(&contenido, &[]);
+        assert_eq!(verificacion.estado, EstadoFirma::SinFirma);
+        let pagina = *documento.get_pages().values().next().unwrap();
+        let anotaciones = documento
+            .get_dictionary(pagina)
+            .unwrap()
+            .get(b"Annots")
+            .and_then(Object::as_array)
+            .unwrap();
+        assert!(anotaciones.is_empty());
+    }
+
+    #[test]
+    fn escribe_nombres_y_cadenas_con_escapes() {
+        let objeto = Object::Array(vec![
+            Object::Name(b"A B#".to_vec()),
+            Object::string_literal("(x)"),
+            Object::String(vec![0xfe, 0xff], StringFormat::Literal),
+            Object::Reference((7, 0)),
+        ]);
+        assert_eq!(
+            serializar(&objeto).unwrap(),
+            b"[/A#20B#23 (\\(x\\)) <feff> 7 0 R]"
+        );
+    }
+}
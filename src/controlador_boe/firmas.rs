@@ -0,0 +1,925 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use cms::{
+    cert::CertificateChoices,
+    content_info::ContentInfo,
+    signed_data::{SignedData, SignerIdentifier, SignerInfo},
+};
+use der::{
+    asn1::{OctetString, PrintableStringRef, Utf8StringRef},
+    oid::{
+        db::{rfc4519, rfc5911, rfc5912},
+        ObjectIdentifier,
+    },
+    Decode, Encode, SliceReader,
+};
+use lopdf::{Dictionary, Document, Object};
+use rsa::{pkcs1::DecodeRsaPublicKey, Pkcs1v15Sign, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use x509_cert::{
+    ext::pkix::{AuthorityKeyIdentifier, BasicConstraints, KeyUsage, SubjectKeyIdentifier},
+    name::Name,
+    time::Time,
+    Certificate,
+};
+
+use super::configuracion::Configuracion;
+
+// Archivos que se leen de los directorios del almacén de confianza
+const EXTENSIONES_CERTIFICADO: [&str; 4] = ["pem", "crt", "cer", "der"];
+// Evita recorrer sin fin cadenas de certificados circulares
+const MAXIMO_CADENA: usize = 8;
+// No se consultan listas de revocación ni OCSP, una firma válida puede usar un certificado
+// revocado después
+const SIN_REVOCACION: &str = "no se comprueba la revocación del certificado";
+
+// Ordenado de mejor a peor para quedarse con el peor resultado si hay varias firmas
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EstadoFirma {
+    SinFirma,
+    Valida,
+    // La firma corresponde al documento pero su certificado no llega al almacén de confianza
+    NoConfiable,
+    Invalida,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VerificacionFirma {
+    pub estado: EstadoFirma,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub firmante: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fecha: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub detalle: String,
+    // Hay actualizaciones después de la última firma, que ninguna firma garantiza
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cambios_posteriores: bool,
+}
+
+impl EstadoFirma {
+    pub fn texto(&self) -> &'static str {
+        match self {
+            EstadoFirma::SinFirma => "sin_firma",
+            EstadoFirma::Valida => "valida",
+            EstadoFirma::NoConfiable => "no_confiable",
+            EstadoFirma::Invalida => "invalida",
+        }
+    }
+}
+
+impl VerificacionFirma {
+    fn nueva(estado: EstadoFirma, detalle: &str) -> VerificacionFirma {
+        VerificacionFirma {
+            estado,
+            firmante: None,
+            fecha: None,
+            detalle: detalle.to_string(),
+            cambios_posteriores: false,
+        }
+    }
+    // Texto para la columna del informe TSV
+    pub fn resumen(&self) -> String {
+        match self.detalle.is_empty() {
+            true => self.estado.texto().to_string(),
+            false => format!("{}: {}", self.estado.texto(), self.detalle),
+        }
+    }
+}
+
+// Certificados PEM o DER de confianza, cada ruta puede ser un archivo o un directorio con ellos
+pub fn cargar_almacen(rutas: &[PathBuf]) -> Result<Vec<Certificate>, String> {
+    let mut almacen = Vec::new();
+    for ruta in rutas {
+        if !ruta.is_dir() {
+            almacen.extend(leer_certificados(ruta)?);
+            continue;
+        }
+        let entradas = match fs::read_dir(ruta) {
+            Err(error) => {
+                let mensaje_error = format!(
+                    "no se ha podido leer el almacén de confianza {} {}",
+                    ruta.display(),
+                    error
+                );
+                return Err(mensaje_error);
+            }
+            Ok(ok) => ok,
+        };
+        let mut archivos = entradas
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|r| r.is_file())
+            .filter(|r| {
+                let extension = r.extension().and_then(|e| e.to_str()).unwrap_or_default();
+                EXTENSIONES_CERTIFICADO.contains(&extension.to_lowercase().as_str())
+            })
+            .collect::<Vec<PathBuf>>();
+        archivos.sort();
+        for archivo in archivos {
+            almacen.extend(leer_certificados(&archivo)?);
+        }
+    }
+    Ok(almacen)
+}
+
+fn leer_certificados(ruta: &Path) -> Result<Vec<Certificate>, String> {
+    let contenido = match fs::read(ruta) {
+        Err(error) => {
+            let mensaje_error = format!(
+                "no se ha podido leer el certificado de confianza {} {}",
+                ruta.display(),
+                error
+            );
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    let certificados = match contenido.starts_with(b"-----BEGIN") {
+        true => Certificate::load_pem_chain(&contenido),
+        false => Certificate::from_der(&contenido).map(|c| vec![c]),
+    };
+    match certificados {
+        Err(error) => {
+            let mensaje_error = format!(
+                "el certificado de confianza {} no es válido {}",
+                ruta.display(),
+                error
+            );
+            Err(mensaje_error)
+        }
+        Ok(ok) => Ok(ok),
+    }
+}
+
+// Comprueba las firmas del PDF tal y como se ha descargado, antes de que la unión las elimine.
+// Cada firma se comprueba sobre la revisión que firmó. Si tiene varias se devuelve la peor
+pub fn verificar_documento(bytes: &[u8], almacen: &[Certificate]) -> VerificacionFirma {
+    let documento = match Document::load_mem(bytes) {
+        Err(error) => {
+            let detalle = format!("no se ha podido leer el PDF {}", error);
+            return VerificacionFirma::nueva(EstadoFirma::Invalida, &detalle);
+        }
+        Ok(ok) => ok,
+    };
+    let mut resultado: Option<VerificacionFirma> = None;
+    // Alguna firma llega hasta el final del archivo
+    let mut firmado_completo = false;
+    for firma in diccionarios_firma(&documento) {
+        let verificacion = match comprobar_firma(bytes, firma, almacen) {
+            Err(detalle) => VerificacionFirma::nueva(EstadoFirma::Invalida, &detalle),
+            Ok((verificacion, fin_revision)) => {
+                firmado_completo |= bytes[fin_revision..].iter().all(u8::is_ascii_whitespace);
+                verificacion
+            }
+        };
+        if resultado
+            .as_ref()
+            .is_none_or(|r| verificacion.estado > r.estado)
+        {
+            resultado = Some(verificacion);
+        }
+    }
+    let Some(mut resultado) = resultado else {
+        return VerificacionFirma::nueva(EstadoFirma::SinFirma, "");
+    };
+    // Las actualizaciones incrementales sin firmar no invalidan las firmas anteriores, pero lo
+    // que añaden no está garantizado por ninguna
+    if !firmado_completo && resultado.estado != EstadoFirma::Invalida {
+        resultado.cambios_posteriores = true;
+        let aviso = "el documento tiene cambios posteriores a la última firma";
+        resultado.detalle = match resultado.detalle.is_empty() {
+            true => aviso.to_string(),
+            false => format!("{}; {}", resultado.detalle, aviso),
+        };
+    }
+    resultado
+}
+
+// Modo verificar, para un PDF ya guardado como el boletín unido y firmado con --firmar
+pub fn verificar_archivo(ruta: &Path, configuracion: &Configuracion) -> Result<(), String> {
+    let contenido = match fs::read(ruta) {
+        Err(error) => {
+            let mensaje_error = format!("no se ha podido leer {} {}", ruta.display(), error);
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    let almacen = cargar_almacen(&configuracion.almacen_confianza)?;
+    let verificacion = verificar_documento(&contenido, &almacen);
+    println!("{}: {}", ruta.display(), verificacion.resumen());
+    if let Some(firmante) = &verificacion.firmante {
+        println!("firmante: {}", firmante);
+    }
+    if let Some(fecha) = &verificacion.fecha {
+        println!("fecha: {}", fecha);
+    }
+    match verificacion.estado {
+        // El boletín firmado no debería cambiar después, aunque la firma siga siendo válida
+        EstadoFirma::Valida if verificacion.cambios_posteriores => Err(format!(
+            "{} se ha modificado después de firmarse",
+            ruta.display()
+        )),
+        EstadoFirma::Valida => Ok(()),
+        EstadoFirma::SinFirma => Err(format!("{} no está firmado", ruta.display())),
+        _ => Err(format!("la firma de {} no es válida", ruta.display())),
+    }
+}
+
+// Diccionarios de firma, como objetos propios o dentro del campo de formulario que los usa
+fn diccionarios_firma(documento: &Document) -> Vec<&Dictionary> {
+    let es_firma = |d: &Dictionary| d.has(b"ByteRange") && d.has(b"Contents");
+    let mut firmas = Vec::new();
+    for objeto in documento.objects.values() {
+        let diccionario = match objeto {
+            Object::Dictionary(diccionario) => diccionario,
+            _ => continue,
+        };
+        if es_firma(diccionario) {
+            firmas.push(diccionario);
+        } else if let Ok(Object::Dictionary(valor)) = diccionario.get(b"V") {
+            if es_firma(valor) {
+                firmas.push(valor);
+            }
+        }
+    }
+    firmas
+}
+
+// Un error indica que la firma no es válida, el resto de resultados se devuelven como Ok junto
+// con el final de la revisión firmada
+fn comprobar_firma(
+    bytes: &[u8],
+    firma: &Dictionary,
+    almacen: &[Certificate],
+) -> Result<(VerificacionFirma, usize), String> {
+    let contenido = firma
+        .get(b"Contents")
+        .and_then(Object::as_str)
+        .map_err(|_| String::from("la firma no tiene contenido"))?;
+    let (datos, fin_revision) = comprobar_rangos(bytes, firma, contenido)?;
+    // El hueco reservado para la firma suele tener ceros de relleno tras el DER
+    let info = SliceReader::new(contenido)
+        .and_then(|mut lector| ContentInfo::decode(&mut lector))
+        .map_err(|error| format!("la firma no es un CMS válido {}", error))?;
+    if info.content_type != rfc5911::ID_SIGNED_DATA {
+        return Err(format!(
+            "la firma contiene {} en lugar de SignedData",
+            info.content_type
+        ));
+    }
+    let firmado = info
+        .content
+        .decode_as::<SignedData>()
+        .map_err(|error| format!("la firma no es un SignedData válido {}", error))?;
+    let certificados = firmado
+        .certificates
+        .iter()
+        .flat_map(|c| c.0.iter())
+        .filter_map(|c| match c {
+            CertificateChoices::Certificate(certificado) => Some(certificado.clone()),
+            _ => None,
+        })
+        .collect::<Vec<Certificate>>();
+    if firmado.signer_infos.0.is_empty() {
+        return Err(String::from("la firma no tiene ningún firmante"));
+    }
+    // Todos los firmantes tienen que corresponder al documento, y la firma vale lo que el peor
+    let mut estado = EstadoFirma::Valida;
+    let mut detalles = Vec::new();
+    let mut firmantes = Vec::new();
+    let mut fecha = None;
+    for firmante in firmado.signer_infos.0.iter() {
+        let (certificado, fecha_firmante) = comprobar_firmante(firmante, &certificados, &datos)?;
+        let momento = fecha_firmante
+            .map(|f| f.to_system_time())
+            .unwrap_or_else(SystemTime::now);
+        if let Err(detalle) = comprobar_cadena(certificado, &certificados, almacen, momento) {
+            estado = EstadoFirma::NoConfiable;
+            detalles.push(detalle);
+        }
+        let nombre = nombre_comun(&certificado.tbs_certificate.subject);
+        if !firmantes.contains(&nombre) {
+            firmantes.push(nombre);
+        }
+        fecha = fecha.or(fecha_firmante);
+    }
+    if estado == EstadoFirma::Valida {
+        detalles.push(String::from(SIN_REVOCACION));
+    }
+    let verificacion = VerificacionFirma {
+        estado,
+        firmante: Some(firmantes.join(", ")),
+        fecha: match fecha {
+            Some(fecha) => Some(
+                DateTime::<Utc>::from(fecha.to_system_time())
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+            ),
+            None => firma
+                .get(b"M")
+                .and_then(Object::as_str)
+                .ok()
+                .map(|m| String::from_utf8_lossy(m).to_string()),
+        },
+        detalle: detalles.join("; "),
+        cambios_posteriores: false,
+    };
+    Ok((verificacion, fin_revision))
+}
+
+// Comprueba que un firmante firmó los datos con un certificado incluido en la firma que permite
+// firmar. Devuelve ese certificado y la fecha de la firma si la indica
+fn comprobar_firmante<'a>(
+    firmante: &SignerInfo,
+    certificados: &'a [Certificate],
+    datos: &[u8],
+) -> Result<(&'a Certificate, Option<Time>), String> {
+    let certificado = certificados
+        .iter()
+        .find(|c| identifica(&firmante.sid, c))
+        .ok_or_else(|| String::from("la firma no incluye el certificado del firmante"))?;
+    comprobar_uso_firma(certificado)?;
+
+    let (resumen_datos, _) = resumir(firmante.digest_alg.oid, datos)?;
+    let mut fecha = None;
+    let datos_firmados = match &firmante.signed_attrs {
+        None => datos.to_vec(),
+        Some(atributos) => {
+            let valor = |oid: ObjectIdentifier| {
+                atributos
+                    .iter()
+                    .find(|a| a.oid == oid)
+                    .and_then(|a| a.values.iter().next())
+            };
+            let resumen_mensaje = valor(rfc5911::ID_MESSAGE_DIGEST)
+                .and_then(|v| v.decode_as::<OctetString>().ok())
+                .ok_or_else(|| String::from("la firma no incluye el resumen del documento"))?;
+            if resumen_mensaje.as_bytes() != resumen_datos.as_slice() {
+                return Err(String::from(
+                    "el documento se ha modificado después de firmarse",
+                ));
+            }
+            fecha = valor(rfc5911::ID_SIGNING_TIME)
+                .and_then(|v| v.to_der().ok())
+                .and_then(|v| Time::from_der(&v).ok());
+            atributos
+                .to_der()
+                .map_err(|error| format!("no se han podido codificar los atributos {}", error))?
+        }
+    };
+    verificar_rsa(
+        certificado,
+        firmante.signature_algorithm.oid,
+        firmante.digest_alg.oid,
+        &datos_firmados,
+        firmante.signature.as_bytes(),
+    )
+    .map_err(|error| {
+        format!(
+            "la firma no corresponde al certificado del firmante {}",
+            error
+        )
+    })?;
+    Ok((certificado, fecha))
+}
+
+// Si el certificado limita el uso de la clave, tiene que permitir la firma digital o el no repudio
+fn comprobar_uso_firma(certificado: &Certificate) -> Result<(), String> {
+    match certificado.tbs_certificate.get::<KeyUsage>() {
+        Ok(Some((_, uso))) if !uso.digital_signature() && !uso.non_repudiation() => Err(format!(
+            "el certificado de {} no permite firmar documentos",
+            nombre_comun(&certificado.tbs_certificate.subject)
+        )),
+        Err(error) => Err(format!(
+            "el uso de la clave del certificado no es válido {}",
+            error
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Los rangos firmados deben empezar en 0, terminar en el %%EOF de una revisión del archivo y
+// dejar fuera solo la cadena hexadecimal con la firma. Devuelve los bytes cubiertos y el final
+// de la revisión, que no llega al del archivo si después se han añadido actualizaciones
+fn comprobar_rangos(
+    bytes: &[u8],
+    firma: &Dictionary,
+    contenido: &[u8],
+) -> Result<(Vec<u8>, usize), String> {
+    let rangos = firma
+        .get(b"ByteRange")
+        .and_then(Object::as_array)
+        .map_err(|_| String::from("la firma no indica los rangos firmados"))?
+        .iter()
+        .filter_map(|v| v.as_i64().ok())
+        .filter_map(|v| usize::try_from(v).ok())
+        .collect::<Vec<usize>>();
+    let [inicio, longitud, inicio_final, longitud_final] = rangos[..] else {
+        return Err(String::from("los rangos firmados no son válidos"));
+    };
+    if inicio != 0 || longitud > inicio_final || inicio_final > bytes.len() {
+        return Err(String::from("los rangos firmados no son válidos"));
+    }
+    let fin_revision = match inicio_final.checked_add(longitud_final) {
+        Some(fin) if fin <= bytes.len() => fin,
+        _ => return Err(String::from("los rangos firmados no son válidos")),
+    };
+    if !bytes[..fin_revision].trim_ascii_end().ends_with(b"%%EOF") {
+        return Err(String::from(
+            "la firma no cubre una revisión completa del documento",
+        ));
+    }
+    let hueco = &bytes[longitud..inicio_final];
+    let hexadecimal = match hueco {
+        [b'<', hexadecimal @ .., b'>'] => hexadecimal,
+        _ => {
+            return Err(String::from(
+                "los rangos firmados no dejan fuera solo la firma",
+            ))
+        }
+    };
+    if decodificar_hexadecimal(hexadecimal).as_deref() != Some(contenido) {
+        return Err(String::from(
+            "los rangos firmados no dejan fuera solo la firma",
+        ));
+    }
+    let mut datos = bytes[..longitud].to_vec();
+    datos.extend_from_slice(&bytes[inicio_final..fin_revision]);
+    Ok((datos, fin_revision))
+}
+
+fn decodificar_hexadecimal(texto: &[u8]) -> Option<Vec<u8>> {
+    let digitos = texto
+        .iter()
+        .filter(|c| !c.is_ascii_whitespace())
+        .map(|c| (*c as char).to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<u8>>>()?;
+    Some(
+        digitos
+            .chunks(2)
+            .map(|par| (par[0] << 4) | par.get(1).copied().unwrap_or(0))
+            .collect(),
+    )
+}
+
+fn identifica(sid: &SignerIdentifier, certificado: &Certificate) -> bool {
+    let tbs = &certificado.tbs_certificate;
+    match sid {
+        SignerIdentifier::IssuerAndSerialNumber(emisor) => {
+            emisor.issuer == tbs.issuer && emisor.serial_number == tbs.serial_number
+        }
+        SignerIdentifier::SubjectKeyIdentifier(clave) => {
+            matches!(tbs.get::<SubjectKeyIdentifier>(), Ok(Some((_, propia))) if propia == *clave)
+        }
+    }
+}
+
+// Sigue los emisores desde el certificado del firmante, entre los incluidos en la firma, hasta
+// llegar a uno del almacén. Todos deben estar vigentes en el momento de la firma
+fn comprobar_cadena(
+    certificado: &Certificate,
+    intermedios: &[Certificate],
+    almacen: &[Certificate],
+    momento: SystemTime,
+) -> Result<(), String> {
+    if almacen.is_empty() {
+        return Err(String::from(
+            "no se ha indicado ningún almacén de confianza",
+        ));
+    }
+    let mut actual = certificado;
+    for _ in 0..MAXIMO_CADENA {
+        comprobar_vigencia(actual, momento)?;
+        if almacen.contains(actual) {
+            return Ok(());
+        }
+        // Un certificado del almacén solo sirve de ancla si puede emitir certificados, salvo
+        // las raíces v1 antiguas que no tienen extensiones
+        let ancla = almacen
+            .iter()
+            .filter(|a| a.tbs_certificate.extensions.is_none() || es_autoridad(a))
+            .find(|a| emitido_por(actual, a));
+        if let Some(ancla) = ancla {
+            return comprobar_vigencia(ancla, momento);
+        }
+        let emisor = intermedios
+            .iter()
+            .filter(|c| *c != actual && es_autoridad(c))
+            .find(|c| emitido_por(actual, c));
+        actual = match emisor {
+            None => {
+                return Err(format!(
+                    "{} no está emitido por ningún certificado del almacén de confianza",
+                    nombre_comun(&actual.tbs_certificate.subject)
+                ));
+            }
+            Some(emisor) => emisor,
+        };
+    }
+    Err(String::from("la cadena de certificados es demasiado larga"))
+}
+
+// Además del nombre y la firma, si los dos certificados identifican la clave tienen que coincidir
+fn emitido_por(certificado: &Certificate, emisor: &Certificate) -> bool {
+    if certificado.tbs_certificate.issuer != emisor.tbs_certificate.subject {
+        return false;
+    }
+    let clave_autoridad = match certificado.tbs_certificate.get::<AuthorityKeyIdentifier>() {
+        Ok(Some((_, autoridad))) => autoridad.key_identifier,
+        _ => None,
+    };
+    if let (Some(clave_autoridad), Ok(Some((_, clave_emisor)))) = (
+        clave_autoridad,
+        emisor.tbs_certificate.get::<SubjectKeyIdentifier>(),
+    ) {
+        if clave_autoridad != clave_emisor.0 {
+            return false;
+        }
+    }
+    let tbs = match certificado.tbs_certificate.to_der() {
+        Err(_) => return false,
+        Ok(ok) => ok,
+    };
+    let algoritmo = certificado.signature_algorithm.oid;
+    let firma = certificado.signature.raw_bytes();
+    verificar_rsa(emisor, algoritmo, algoritmo, &tbs, firma).is_ok()
+}
+
+// Marcado como autoridad y, si limita el uso de la clave, con permiso para firmar certificados
+fn es_autoridad(certificado: &Certificate) -> bool {
+    let tbs = &certificado.tbs_certificate;
+    matches!(tbs.get::<BasicConstraints>(), Ok(Some((_, restricciones))) if restricciones.ca)
+        && match tbs.get::<KeyUsage>() {
+            Ok(Some((_, uso))) => uso.key_cert_sign(),
+            Ok(None) => true,
+            Err(_) => false,
+        }
+}
+
+fn comprobar_vigencia(certificado: &Certificate, momento: SystemTime) -> Result<(), String> {
+    let validez = &certificado.tbs_certificate.validity;
+    if momento < validez.not_before.to_system_time() || momento > validez.not_after.to_system_time()
+    {
+        return Err(format!(
+            "el certificado de {} no estaba vigente en la fecha de la firma",
+            nombre_comun(&certificado.tbs_certificate.subject)
+        ));
+    }
+    Ok(())
+}
+
+// Solo RSA con PKCS#1 v1.5, que es lo que usa el BOE. resumen es el algoritmo de resumen a
+// usar si el de firma no lo fija, como ocurre con rsaEncryption en CMS
+fn verificar_rsa(
+    certificado: &Certificate,
+    algoritmo_firma: ObjectIdentifier,
+    resumen: ObjectIdentifier,
+    datos: &[u8],
+    firma: &[u8],
+) -> Result<(), String> {
+    let resumen = match algoritmo_firma {
+        rfc5912::RSA_ENCRYPTION => resumen,
+        rfc5912::SHA_1_WITH_RSA_ENCRYPTION => rfc5912::ID_SHA_1,
+        rfc5912::SHA_256_WITH_RSA_ENCRYPTION => rfc5912::ID_SHA_256,
+        rfc5912::SHA_384_WITH_RSA_ENCRYPTION => rfc5912::ID_SHA_384,
+        rfc5912::SHA_512_WITH_RSA_ENCRYPTION => rfc5912::ID_SHA_512,
+        otro => return Err(format!("el algoritmo de firma {} no está admitido", otro)),
+    };
+    let (resumen, esquema) = resumir(resumen, datos)?;
+    let clave = certificado
+        .tbs_certificate
+        .subject_public_key_info
+        .subject_public_key
+        .raw_bytes();
+    let clave = RsaPublicKey::from_pkcs1_der(clave)
+        .map_err(|error| format!("la clave del certificado no es RSA {}", error))?;
+    clave
+        .verify(esquema, &resumen, firma)
+        .map_err(|error| error.to_string())
+}
+
+fn resumir(algoritmo: ObjectIdentifier, datos: &[u8]) -> Result<(Vec<u8>, Pkcs1v15Sign), String> {
+    match algoritmo {
+        rfc5912::ID_SHA_1 => Ok((Sha1::digest(datos).to_vec(), Pkcs1v15Sign::new::<Sha1>())),
+        rfc5912::ID_SHA_256 => Ok((
+            Sha256::digest(datos).to_vec(),
+            Pkcs1v15Sign::new::<Sha256>(),
+        )),
+        rfc5912::ID_SHA_384 => Ok((
+            Sha384::digest(datos).to_vec(),
+            Pkcs1v15Sign::new::<Sha384>(),
+        )),
+        rfc5912::ID_SHA_512 => Ok((
+            Sha512::digest(datos).to_vec(),
+            Pkcs1v15Sign::new::<Sha512>(),
+        )),
+        otro => Err(format!("el algoritmo de resumen {} no está admitido", otro)),
+    }
+}
+
+// CN del certificado, o el nombre completo si no lo tiene
+pub fn nombre_comun(nombre: &Name) -> String {
+    for atributo in nombre.0.iter().flat_map(|rdn| rdn.0.iter()) {
+        if atributo.oid != rfc4519::CN {
+            continue;
+        }
+        if let Ok(valor) = Utf8StringRef::try_from(&atributo.value) {
+            return valor.to_string();
+        }
+        if let Ok(valor) = PrintableStringRef::try_from(&atributo.value) {
+            return valor.to_string();
+        }
+    }
+    nombre.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::Range, time::Duration};
+
+    use der::{oid::AssociatedOid, Any};
+    use lopdf::dictionary;
+
+    use super::*;
+    use crate::controlador_boe::firmar;
+
+    const FIRMADO: &[u8] =
+        include_bytes!("../../tests/fixtures/boe.es/boe/dias/2024/12/11/pdfs/BOE-A-2024-25800.pdf");
+    const ALTERADO: &[u8] =
+        include_bytes!("../../tests/fixtures/boe.es/boe/dias/2024/12/11/pdfs/BOE-A-2024-25801.pdf");
+    const SIN_FIRMA: &[u8] =
+        include_bytes!("../../tests/fixtures/boe.es/boe/dias/2024/12/11/pdfs/BOE-A-2024-25802.pdf");
+
+    fn ruta_fixture(nombre: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/firma")
+            .join(nombre)
+    }
+
+    fn almacen(nombre: &str) -> Vec<Certificate> {
+        cargar_almacen(&[ruta_fixture(nombre)]).unwrap()
+    }
+
+    // El SignedData de la primera firma del documento y el hueco de su cadena hexadecimal
+    fn firma_incluida(pdf: &[u8]) -> (SignedData, Range<usize>) {
+        let documento = Document::load_mem(pdf).unwrap();
+        let firma = diccionarios_firma(&documento)[0];
+        let rangos = firma.get(b"ByteRange").and_then(Object::as_array).unwrap();
+        let hueco =
+            rangos[1].as_i64().unwrap() as usize + 1..rangos[2].as_i64().unwrap() as usize - 1;
+        let contenido = firma.get(b"Contents").and_then(Object::as_str).unwrap();
+        let info = ContentInfo::decode(&mut SliceReader::new(contenido).unwrap()).unwrap();
+        (info.content.decode_as().unwrap(), hueco)
+    }
+
+    fn certificado_firmante(firmado: &SignedData) -> Certificate {
+        let firmante = firmado.signer_infos.0.get(0).unwrap();
+        firmado
+            .certificates
+            .iter()
+            .flat_map(|c| c.0.iter())
+            .find_map(|c| match c {
+                CertificateChoices::Certificate(c) if identifica(&firmante.sid, c) => {
+                    Some(c.clone())
+                }
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    fn cambiar_extension<T: AssociatedOid + Encode>(certificado: &mut Certificate, valor: T) {
+        let extension = certificado
+            .tbs_certificate
+            .extensions
+            .as_mut()
+            .unwrap()
+            .iter_mut()
+            .find(|e| e.extn_id == T::OID)
+            .unwrap();
+        extension.extn_value = OctetString::new(valor.to_der().unwrap()).unwrap();
+    }
+
+    // Añade al PDF una actualización incremental sin firmar con un objeto nuevo
+    fn añadir_revision(pdf: &[u8]) -> Vec<u8> {
+        let documento = Document::load_mem(pdf).unwrap();
+        let raiz = documento
+            .trailer
+            .get(b"Root")
+            .unwrap()
+            .as_reference()
+            .unwrap();
+        let texto = String::from_utf8_lossy(pdf);
+        let anterior = texto
+            .rsplit("startxref")
+            .next()
+            .unwrap()
+            .split_whitespace()
+            .next()
+            .unwrap()
+            .to_string();
+        let numero = documento.max_id + 1;
+        let mut salida = pdf.to_vec();
+        let posicion = salida.len();
+        salida.extend_from_slice(format!("{} 0 obj\n(nota)\nendobj\n", numero).as_bytes());
+        let tabla = salida.len();
+        salida.extend_from_slice(
+            format!("xref\n{} 1\n{:010} 00000 n\r\n", numero, posicion).as_bytes(),
+        );
+        salida.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root {} 0 R /Prev {} >>\nstartxref\n{}\n%%EOF\n",
+                numero + 1,
+                raiz.0,
+                anterior,
+                tabla
+            )
+            .as_bytes(),
+        );
+        salida
+    }
+
+    #[test]
+    fn acepta_la_firma_de_un_certificado_de_confianza() {
+        let verificacion = verificar_documento(FIRMADO, &almacen("ca.pem"));
+        assert_eq!(
+            verificacion.estado,
+            EstadoFirma::Valida,
+            "{}",
+            verificacion.detalle
+        );
+        assert_eq!(
+            verificacion.firmante.as_deref(),
+            Some("Agencia Estatal BOE de pruebas")
+        );
+        assert!(verificacion.fecha.as_deref().unwrap().ends_with('Z'));
+        assert_eq!(
+            verificacion.resumen(),
+            format!("valida: {}", SIN_REVOCACION)
+        );
+    }
+
+    #[test]
+    fn distingue_la_firma_correcta_sin_certificado_de_confianza() {
+        let verificacion = verificar_documento(FIRMADO, &almacen("otra_ca.pem"));
+        assert_eq!(verificacion.estado, EstadoFirma::NoConfiable);
+        assert!(verificacion
+            .detalle
+            .ends_with("ningún certificado del almacén de confianza"));
+
+        let verificacion = verificar_documento(FIRMADO, &[]);
+        assert_eq!(verificacion.estado, EstadoFirma::NoConfiable);
+    }
+
+    #[test]
+    fn rechaza_los_documentos_modificados() {
+        let verificacion = verificar_documento(ALTERADO, &almacen("ca.pem"));
+        assert_eq!(verificacion.estado, EstadoFirma::Invalida);
+        assert_eq!(
+            verificacion.detalle,
+            "el documento se ha modificado después de firmarse"
+        );
+    }
+
+    #[test]
+    fn comprueba_cada_firma_sobre_su_revision() {
+        // Una actualización incremental añadida tras la firma no la invalida, pero se avisa
+        let ampliado = añadir_revision(FIRMADO);
+        let verificacion = verificar_documento(&ampliado, &almacen("ca.pem"));
+        assert_eq!(
+            verificacion.estado,
+            EstadoFirma::Valida,
+            "{}",
+            verificacion.detalle
+        );
+        assert!(verificacion.cambios_posteriores);
+        assert_eq!(
+            verificacion.detalle,
+            format!(
+                "{}; el documento tiene cambios posteriores a la última firma",
+                SIN_REVOCACION
+            )
+        );
+        let verificacion = verificar_documento(&ampliado, &[]);
+        assert_eq!(verificacion.estado, EstadoFirma::NoConfiable);
+        assert!(verificacion
+            .detalle
+            .ends_with("; el documento tiene cambios posteriores a la última firma"));
+
+        // Con una segunda firma que cubre la actualización las dos siguen siendo válidas
+        let firmante = firmar::cargar_firmante(&ruta_fixture("firmante.p12"), "boe").unwrap();
+        let refirmado = firmar::firmar_pdf(&ampliado, &firmante).unwrap();
+        let verificacion = verificar_documento(&refirmado, &almacen("ca.pem"));
+        assert_eq!(
+            verificacion.estado,
+            EstadoFirma::Valida,
+            "{}",
+            verificacion.detalle
+        );
+        assert_eq!(verificacion.detalle, SIN_REVOCACION);
+        assert!(!verificacion.cambios_posteriores);
+
+        // Los rangos tienen que terminar donde termina una revisión
+        let bytes = b"%PDF-1.7\n<3082>\ntrailer\n%%EOF\nmas";
+        let firma = |fin: i64| {
+            dictionary! { "ByteRange" => vec![0.into(), 9.into(), 15.into(), (fin - 15).into()] }
+        };
+        let (datos, fin_revision) = comprobar_rangos(bytes, &firma(30), &[0x30, 0x82]).unwrap();
+        assert_eq!(fin_revision, 30);
+        assert_eq!(datos, b"%PDF-1.7\n\ntrailer\n%%EOF\n");
+        assert_eq!(
+            comprobar_rangos(bytes, &firma(25), &[0x30, 0x82]).unwrap_err(),
+            "la firma no cubre una revisión completa del documento"
+        );
+        assert!(comprobar_rangos(bytes, &firma(40), &[0x30, 0x82]).is_err());
+    }
+
+    #[test]
+    fn comprueba_todos_los_firmantes() {
+        // Un segundo firmante con la firma alterada invalida la firma aunque el primero valga
+        let (mut firmado, hueco) = firma_incluida(FIRMADO);
+        let mut otro = firmado.signer_infos.0.get(0).unwrap().clone();
+        let mut valor = otro.signature.as_bytes().to_vec();
+        valor[0] ^= 0xFF;
+        otro.signature = OctetString::new(valor).unwrap();
+        firmado.signer_infos.0.insert(otro).unwrap();
+        let info = ContentInfo {
+            content_type: rfc5911::ID_SIGNED_DATA,
+            content: Any::encode_from(&firmado).unwrap(),
+        };
+        let hexadecimal: String = info
+            .to_der()
+            .unwrap()
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        let mut pdf = FIRMADO.to_vec();
+        pdf[hueco.clone()].fill(b'0');
+        pdf[hueco.start..hueco.start + hexadecimal.len()].copy_from_slice(hexadecimal.as_bytes());
+
+        let verificacion = verificar_documento(&pdf, &almacen("ca.pem"));
+        assert_eq!(verificacion.estado, EstadoFirma::Invalida);
+        assert!(verificacion
+            .detalle
+            .starts_with("la firma no corresponde al certificado del firmante"));
+    }
+
+    #[test]
+    fn exige_un_certificado_que_permita_firmar() {
+        let (firmado, _) = firma_incluida(FIRMADO);
+        assert!(comprobar_uso_firma(&certificado_firmante(&firmado)).is_ok());
+        let error = comprobar_uso_firma(&almacen("ca.pem")[0]).unwrap_err();
+        assert_eq!(
+            error,
+            "el certificado de CA de pruebas no permite firmar documentos"
+        );
+    }
+
+    #[test]
+    fn solo_acepta_como_ancla_una_autoridad_con_la_clave_del_emisor() {
+        let (firmado, _) = firma_incluida(FIRMADO);
+        let hoja = certificado_firmante(&firmado);
+        let momento =
+            hoja.tbs_certificate.validity.not_before.to_system_time() + Duration::from_secs(60);
+        let ca = almacen("ca.pem");
+        assert!(comprobar_cadena(&hoja, &[], &ca, momento).is_ok());
+
+        let mut sin_autoridad = ca.clone();
+        cambiar_extension(
+            &mut sin_autoridad[0],
+            BasicConstraints {
+                ca: false,
+                path_len_constraint: None,
+            },
+        );
+        assert!(comprobar_cadena(&hoja, &[], &sin_autoridad, momento).is_err());
+
+        let mut otra_clave = ca.clone();
+        let identificador = SubjectKeyIdentifier(OctetString::new(vec![0; 20]).unwrap());
+        cambiar_extension(&mut otra_clave[0], identificador);
+        assert!(comprobar_cadena(&hoja, &[], &otra_clave, momento).is_err());
+    }
+
+    #[test]
+    fn indica_los_documentos_sin_firma() {
+        let verificacion = verificar_documento(SIN_FIRMA, &almacen("ca.pem"));
+        assert_eq!(verificacion.estado, EstadoFirma::SinFirma);
+        assert_eq!(verificacion.resumen(), "sin_firma");
+    }
+
+    #[test]
+    fn decodifica_la_cadena_hexadecimal_con_relleno() {
+        assert_eq!(
+            decodificar_hexadecimal(b"30 0a\nf"),
+            Some(vec![0x30, 0x0a, 0xf0])
+        );
+        assert_eq!(decodificar_hexadecimal(b"3g"), None);
+    }
+}
@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+
+use super::{descargar::EnlacePDF, dividir};
+
+// Los títulos del BOE pueden superar los 500 caracteres, se recortan para no exceder el límite
+// de longitud de nombre de los sistemas de archivos
+const LONGITUD_MAXIMA_TITULO: usize = 80;
+
+// "https://boe.es/boe/dias/2024/12/07/pdfs/BOE-A-2024-25512.pdf" -> "BOE-A-2024-25512"
+pub fn identificador_boe(url: &str) -> String {
+    let archivo = url
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    let archivo = archivo
+        .strip_suffix(".pdf")
+        .or_else(|| archivo.strip_suffix(".PDF"))
+        .unwrap_or(archivo);
+    archivo
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect()
+}
+
+// Carpeta de la sección y nombre del archivo, relativos a la carpeta de documentos del día
+pub fn ruta_relativa(enlace: &EnlacePDF) -> PathBuf {
+    let mut titulo = dividir::nombre_grupo(&enlace.titulo);
+    if titulo.len() > LONGITUD_MAXIMA_TITULO {
+        // nombre_grupo deja solo caracteres ASCII, así que se puede cortar por bytes
+        titulo.truncate(LONGITUD_MAXIMA_TITULO);
+        titulo = titulo.trim_end_matches('_').to_string();
+    }
+    let identificador = identificador_boe(&enlace.url);
+    let nombre = match identificador.is_empty() {
+        true => format!("{}.pdf", titulo),
+        false => format!("{}_{}.pdf", identificador, titulo),
+    };
+    let seccion = match enlace.seccion.is_empty() {
+        true => &enlace.apartado,
+        false => &enlace.seccion,
+    };
+    PathBuf::from(dividir::nombre_grupo(seccion)).join(nombre)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enlace(url: &str, titulo: &str, seccion: &str) -> EnlacePDF {
+        EnlacePDF {
+            apartado: String::from("Notificaciones"),
+            url: url.to_string(),
+            titulo: titulo.to_string(),
+            seccion: seccion.to_string(),
+            departamento: String::from("MINISTERIO DE HACIENDA"),
+            ..EnlacePDF::default()
+        }
+    }
+
+    #[test]
+    fn extrae_el_identificador_de_la_url() {
+        assert_eq!(
+            identificador_boe("https://boe.es/boe/dias/2024/12/07/pdfs/BOE-A-2024-25512.pdf"),
+            "BOE-A-2024-25512"
+        );
+        assert_eq!(
+            identificador_boe("https://boe.es/ver.pdf?id=../../x"),
+            "ver"
+        );
+    }
+
+    #[test]
+    fn genera_rutas_seguras_por_seccion() {
+        let disposicion = enlace(
+            "https://boe.es/boe/dias/2024/12/07/pdfs/BOE-A-2024-25512.pdf",
+            "Real Decreto 1234/2024, de 5 de diciembre, por el que se regula el año fiscal.",
+            "I. Disposiciones generales",
+        );
+        assert_eq!(
+            ruta_relativa(&disposicion),
+            PathBuf::from("i_disposiciones_generales").join(
+                "BOE-A-2024-25512_real_decreto_1234_2024_de_5_de_diciembre_por_el_que_se_regula_el_ano_fiscal.pdf"
+            )
+        );
+
+        let largo = enlace(
+            "https://boe.es/BOE-N-2024-1.pdf",
+            &"Anuncio ".repeat(40),
+            "",
+        );
+        let ruta = ruta_relativa(&largo);
+        assert!(ruta.starts_with("notificaciones"));
+        let nombre = ruta.file_name().unwrap().to_str().unwrap();
+        assert!(nombre.len() <= "BOE-N-2024-1_".len() + LONGITUD_MAXIMA_TITULO + ".pdf".len());
+        assert!(!nombre.contains("_.pdf"));
+    }
+}
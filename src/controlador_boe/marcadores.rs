@@ -0,0 +1,495 @@
+use std::collections::{HashMap, HashSet};
+
+use lopdf::{decode_text_string, Bookmark, Dictionary, Document, Object, ObjectId};
+
+// Evita bucles infinitos con árboles de marcadores o de nombres mal formados
+const PROFUNDIDAD_MAXIMA: usize = 32;
+
+pub struct MarcadorOrigen {
+    pub titulo: String,
+    pub pagina: ObjectId,
+    pub hijos: Vec<MarcadorOrigen>,
+}
+
+// Lee el índice del documento resolviendo los destinos con nombre a la página a la que apuntan
+pub fn leer_marcadores(documento: &Document) -> Vec<MarcadorOrigen> {
+    let catalogo = match documento.catalog() {
+        Err(_) => return Vec::new(),
+        Ok(ok) => ok,
+    };
+    let raiz = match catalogo
+        .get(b"Outlines")
+        .and_then(|o| resolver(documento, o).as_dict())
+    {
+        Err(_) => return Vec::new(),
+        Ok(ok) => ok,
+    };
+    let destinos = destinos_nombrados(documento);
+    let mut visitados = HashSet::new();
+    match raiz.get(b"First") {
+        Err(_) => Vec::new(),
+        Ok(primero) => leer_hermanos(documento, primero, &destinos, &mut visitados, 0),
+    }
+}
+
+fn leer_hermanos(
+    documento: &Document,
+    primero: &Object,
+    destinos: &HashMap<Vec<u8>, Object>,
+    visitados: &mut HashSet<ObjectId>,
+    profundidad: usize,
+) -> Vec<MarcadorOrigen> {
+    let mut marcadores = Vec::new();
+    if profundidad > PROFUNDIDAD_MAXIMA {
+        return marcadores;
+    }
+    let mut siguiente = Some(primero);
+    while let Some(objeto) = siguiente {
+        if let Object::Reference(id) = objeto {
+            if !visitados.insert(*id) {
+                break;
+            }
+        }
+        let nodo = match resolver(documento, objeto).as_dict() {
+            Err(_) => break,
+            Ok(ok) => ok,
+        };
+        let hijos = match nodo.get(b"First") {
+            Err(_) => Vec::new(),
+            Ok(hijo) => leer_hermanos(documento, hijo, destinos, visitados, profundidad + 1),
+        };
+        let pagina = destino_elemento(documento, nodo)
+            .and_then(|d| pagina_destino(documento, d, destinos))
+            .or_else(|| hijos.first().map(|h| h.pagina));
+        // Sin página propia ni hijos no hay nada a lo que saltar
+        if let Some(pagina) = pagina {
+            let titulo = match nodo.get(b"Title").map(|t| resolver(documento, t)) {
+                Err(_) => String::new(),
+                Ok(titulo) => decode_text_string(titulo).unwrap_or_default(),
+            };
+            marcadores.push(MarcadorOrigen {
+                titulo,
+                pagina,
+                hijos,
+            });
+        }
+        siguiente = nodo.get(b"Next").ok();
+    }
+    marcadores
+}
+
+pub fn añadir_marcadores(documento: &mut Document, marcadores: &[MarcadorOrigen], padre: u32) {
+    for marcador in marcadores {
+        let marcador_pdf = Bookmark::new(
+            marcador.titulo.to_owned(),
+            [0.0, 0.0, 0.0],
+            0,
+            marcador.pagina,
+        );
+        let id = documento.add_bookmark(marcador_pdf, Some(padre));
+        añadir_marcadores(documento, &marcador.hijos, id);
+    }
+}
+
+// Los enlaces internos que usan destinos con nombre dejarían de funcionar al unir los
+// documentos, porque el árbol de nombres de cada origen se pierde, así que se sustituyen
+// por el destino explícito
+pub fn resolver_enlaces_nombrados(documento: &mut Document) {
+    let destinos = destinos_nombrados(documento);
+    if destinos.is_empty() {
+        return;
+    }
+    let mut anotaciones = Vec::new();
+    for id_pagina in documento.get_pages().into_values() {
+        let anotaciones_pagina = match documento
+            .get_dictionary(id_pagina)
+            .and_then(|p| p.get(b"Annots"))
+        {
+            Err(_) => continue,
+            Ok(ok) => resolver(documento, ok),
+        };
+        if let Ok(lista) = anotaciones_pagina.as_array() {
+            anotaciones.extend(lista.iter().filter_map(|a| a.as_reference().ok()));
+        }
+    }
+    let mut acciones = Vec::new();
+    for id in anotaciones {
+        let anotacion = match documento.get_dictionary_mut(id) {
+            Err(_) => continue,
+            Ok(ok) => ok,
+        };
+        if anotacion.has(b"Dest") {
+            sustituir_destino(anotacion, b"Dest", &destinos);
+            continue;
+        }
+        match anotacion.get_mut(b"A") {
+            Ok(Object::Reference(id_accion)) => acciones.push(*id_accion),
+            Ok(Object::Dictionary(accion)) => sustituir_destino(accion, b"D", &destinos),
+            _ => (),
+        }
+    }
+    for id in acciones {
+        if let Ok(accion) = documento.get_dictionary_mut(id) {
+            sustituir_destino(accion, b"D", &destinos);
+        }
+    }
+}
+
+fn sustituir_destino(
+    diccionario: &mut Dictionary,
+    clave: &[u8],
+    destinos: &HashMap<Vec<u8>, Object>,
+) {
+    // En las acciones solo GoTo apunta a este documento, GoToR o URI no se tocan
+    if clave == b"D"
+        && diccionario.get(b"S").and_then(Object::as_name).ok() != Some(b"GoTo".as_slice())
+    {
+        return;
+    }
+    let destino = match diccionario.get(clave).and_then(nombre_destino) {
+        Err(_) => return,
+        Ok(nombre) => destinos.get(nombre),
+    };
+    if let Some(destino) = destino {
+        diccionario.set(clave.to_vec(), destino.clone());
+    }
+}
+
+// Une el árbol /Names /Dests y el diccionario /Dests antiguo en un único mapa
+// nombre -> destino explícito
+fn destinos_nombrados(documento: &Document) -> HashMap<Vec<u8>, Object> {
+    let mut destinos = HashMap::new();
+    let catalogo = match documento.catalog() {
+        Err(_) => return destinos,
+        Ok(ok) => ok,
+    };
+    if let Ok(dests) = catalogo
+        .get(b"Dests")
+        .and_then(|d| resolver(documento, d).as_dict())
+    {
+        for (nombre, valor) in dests.iter() {
+            if let Some(destino) = destino_explicito(documento, valor) {
+                destinos.insert(nombre.to_owned(), destino);
+            }
+        }
+    }
+    let arbol = catalogo
+        .get(b"Names")
+        .and_then(|n| resolver(documento, n).as_dict())
+        .and_then(|n| n.get(b"Dests"))
+        .and_then(|d| resolver(documento, d).as_dict());
+    if let Ok(arbol) = arbol {
+        leer_arbol_nombres(documento, arbol, &mut destinos, 0);
+    }
+    destinos
+}
+
+fn leer_arbol_nombres(
+    documento: &Document,
+    nodo: &Dictionary,
+    destinos: &mut HashMap<Vec<u8>, Object>,
+    profundidad: usize,
+) {
+    if profundidad > PROFUNDIDAD_MAXIMA {
+        return;
+    }
+    if let Ok(hijos) = nodo
+        .get(b"Kids")
+        .and_then(|k| resolver(documento, k).as_array())
+    {
+        for hijo in hijos {
+            if let Ok(hijo) = resolver(documento, hijo).as_dict() {
+                leer_arbol_nombres(documento, hijo, destinos, profundidad + 1);
+            }
+        }
+    }
+    if let Ok(nombres) = nodo
+        .get(b"Names")
+        .and_then(|n| resolver(documento, n).as_array())
+    {
+        for par in nombres.chunks(2) {
+            if let [nombre, valor] = par {
+                if let (Ok(nombre), Some(destino)) =
+                    (nombre.as_str(), destino_explicito(documento, valor))
+                {
+                    destinos.insert(nombre.to_owned(), destino);
+                }
+            }
+        }
+    }
+}
+
+// Un destino puede ser directamente el array [página /XYZ ...] o un diccionario con la clave D
+fn destino_explicito(documento: &Document, valor: &Object) -> Option<Object> {
+    match resolver(documento, valor) {
+        Object::Array(array) => Some(Object::Array(array.clone())),
+        Object::Dictionary(diccionario) => {
+            match diccionario.get(b"D").map(|d| resolver(documento, d)) {
+                Ok(Object::Array(array)) => Some(Object::Array(array.clone())),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn destino_elemento<'a>(documento: &'a Document, nodo: &'a Dictionary) -> Option<&'a Object> {
+    if let Ok(destino) = nodo.get(b"Dest") {
+        return Some(destino);
+    }
+    let accion = resolver(documento, nodo.get(b"A").ok()?).as_dict().ok()?;
+    if accion.get(b"S").and_then(Object::as_name).ok()? != b"GoTo" {
+        return None;
+    }
+    accion.get(b"D").ok()
+}
+
+fn pagina_destino(
+    documento: &Document,
+    destino: &Object,
+    destinos: &HashMap<Vec<u8>, Object>,
+) -> Option<ObjectId> {
+    let destino = match nombre_destino(destino) {
+        Ok(nombre) => destinos.get(nombre)?.to_owned(),
+        Err(_) => destino_explicito(documento, destino)?,
+    };
+    destino.as_array().ok()?.first()?.as_reference().ok()
+}
+
+fn nombre_destino(destino: &Object) -> lopdf::Result<&[u8]> {
+    match destino {
+        Object::Name(nombre) => Ok(nombre),
+        _ => destino.as_str(),
+    }
+}
+
+fn resolver<'a>(documento: &'a Document, objeto: &'a Object) -> &'a Object {
+    match objeto {
+        Object::Reference(id) => documento.get_object(*id).unwrap_or(objeto),
+        _ => objeto,
+    }
+}
+
+// add_bookmark escribe los títulos tal cual, lo que estropea las tildes al no ser PDFDocEncoding
+pub fn codificar_titulos(documento: &mut Document, raiz: ObjectId) {
+    let mut pendientes = Vec::new();
+    if let Ok(Object::Reference(primero)) =
+        documento.get_dictionary(raiz).and_then(|r| r.get(b"First"))
+    {
+        pendientes.push(*primero);
+    }
+    let mut visitados = HashSet::new();
+    while let Some(id) = pendientes.pop() {
+        if !visitados.insert(id) {
+            continue;
+        }
+        let elemento = match documento.get_dictionary_mut(id) {
+            Err(_) => continue,
+            Ok(ok) => ok,
+        };
+        for clave in [b"First".as_slice(), b"Next".as_slice()] {
+            if let Ok(Object::Reference(siguiente)) = elemento.get(clave) {
+                pendientes.push(*siguiente);
+            }
+        }
+        if let Ok(titulo) = elemento.get(b"Title").and_then(Object::as_str) {
+            if let Ok(texto) = String::from_utf8(titulo.to_vec()) {
+                elemento.set("Title", lopdf::text_string(&texto));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lopdf::{dictionary, Stream, StringFormat};
+
+    use super::*;
+    use crate::controlador_boe::{
+        descargar::EnlacePDF,
+        unir_pdf::{self, OpcionesUnion},
+    };
+
+    // Dos páginas con un índice que solo usa destinos con nombre: el capítulo por el árbol
+    // /Names /Dests, el primer artículo por el mismo árbol y el segundo por /Dests y una acción
+    fn documento_con_indice() -> Document {
+        let mut documento = Document::with_version("1.5");
+        let id_paginas = documento.new_object_id();
+        let mut paginas = Vec::new();
+        for _ in 0..2 {
+            let contenido = documento.add_object(Stream::new(dictionary! {}, b"BT ET".to_vec()));
+            paginas.push(documento.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => id_paginas,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+                "Contents" => contenido,
+            }));
+        }
+        documento.objects.insert(
+            id_paginas,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => paginas.iter().map(|p| Object::Reference(*p)).collect::<Vec<_>>(),
+                "Count" => 2,
+            }),
+        );
+
+        let id_raiz = documento.new_object_id();
+        let id_capitulo = documento.new_object_id();
+        let id_primero = documento.new_object_id();
+        let id_segundo = documento.new_object_id();
+        documento.objects.insert(
+            id_raiz,
+            Object::Dictionary(dictionary! {
+                "Type" => "Outlines",
+                "First" => id_capitulo,
+                "Last" => id_capitulo,
+                "Count" => 3,
+            }),
+        );
+        documento.objects.insert(
+            id_capitulo,
+            Object::Dictionary(dictionary! {
+                "Title" => lopdf::text_string("Capítulo 1"),
+                "Parent" => id_raiz,
+                "Dest" => Object::string_literal("capitulo"),
+                "First" => id_primero,
+                "Last" => id_segundo,
+                "Count" => 2,
+            }),
+        );
+        documento.objects.insert(
+            id_primero,
+            Object::Dictionary(dictionary! {
+                "Title" => lopdf::text_string("Artículo 1"),
+                "Parent" => id_capitulo,
+                "Dest" => Object::string_literal("articulo"),
+                "Next" => id_segundo,
+            }),
+        );
+        documento.objects.insert(
+            id_segundo,
+            Object::Dictionary(dictionary! {
+                "Title" => lopdf::text_string("Статья 2"),
+                "Parent" => id_capitulo,
+                "Prev" => id_primero,
+                "A" => dictionary! { "S" => "GoTo", "D" => "anexo" },
+            }),
+        );
+
+        let arbol = documento.add_object(dictionary! {
+            "Names" => vec![
+                Object::string_literal("articulo"),
+                Object::Dictionary(dictionary! {
+                    "D" => vec![paginas[0].into(), "Fit".into()],
+                }),
+                Object::string_literal("capitulo"),
+                vec![paginas[1].into(), "XYZ".into(), Object::Null, Object::Null, Object::Null]
+                    .into(),
+            ],
+        });
+        let catalogo = documento.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => id_paginas,
+            "Outlines" => id_raiz,
+            "Names" => dictionary! { "Dests" => arbol },
+            "Dests" => dictionary! { "anexo" => vec![paginas[1].into(), "Fit".into()] },
+        });
+        documento.trailer.set("Root", catalogo);
+        documento
+    }
+
+    fn unir(titulo: &str) -> Document {
+        let enlace = EnlacePDF {
+            titulo: titulo.to_string(),
+            url: String::from("https://boe.es/BOE-A-2024-1.pdf"),
+            ..EnlacePDF::default()
+        };
+        let opciones = OpcionesUnion {
+            optimizar_recursos: false,
+            pdfa: false,
+            estampar: false,
+            fecha: String::from("07-12-2024"),
+            cifrado: None,
+        };
+        unir_pdf::unir_documentos(vec![(enlace, documento_con_indice())], &opciones)
+            .unwrap()
+            .documento
+    }
+
+    fn referencia(diccionario: &Dictionary, clave: &[u8]) -> ObjectId {
+        diccionario
+            .get(clave)
+            .and_then(Object::as_reference)
+            .unwrap()
+    }
+
+    fn pagina(documento: &Document, marcador: &Dictionary) -> ObjectId {
+        let accion = documento
+            .get_dictionary(referencia(marcador, b"A"))
+            .unwrap();
+        let destino = accion.get(b"D").and_then(Object::as_array).unwrap();
+        destino[0].as_reference().unwrap()
+    }
+
+    fn titulo(marcador: &Dictionary) -> String {
+        decode_text_string(marcador.get(b"Title").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn conserva_el_indice_anidado_con_destinos_con_nombre() {
+        let documento = unir("Real Decreto 1/2024");
+        let paginas: Vec<ObjectId> = documento.get_pages().into_values().collect();
+        let raiz = referencia(documento.catalog().unwrap(), b"Outlines");
+
+        let id_disposicion = referencia(documento.get_dictionary(raiz).unwrap(), b"First");
+        let disposicion = documento.get_dictionary(id_disposicion).unwrap();
+        assert_eq!(titulo(disposicion), "Real Decreto 1/2024");
+        assert_eq!(referencia(disposicion, b"Parent"), raiz);
+        assert_eq!(pagina(&documento, disposicion), paginas[0]);
+
+        let id_capitulo = referencia(disposicion, b"First");
+        let capitulo = documento.get_dictionary(id_capitulo).unwrap();
+        assert_eq!(titulo(capitulo), "Capítulo 1");
+        assert_eq!(referencia(capitulo, b"Parent"), id_disposicion);
+        assert_eq!(pagina(&documento, capitulo), paginas[1]);
+        assert!(!capitulo.has(b"Next"));
+
+        let primero = documento
+            .get_dictionary(referencia(capitulo, b"First"))
+            .unwrap();
+        assert_eq!(titulo(primero), "Artículo 1");
+        assert_eq!(referencia(primero, b"Parent"), id_capitulo);
+        assert_eq!(pagina(&documento, primero), paginas[0]);
+
+        let segundo = documento
+            .get_dictionary(referencia(primero, b"Next"))
+            .unwrap();
+        assert_eq!(titulo(segundo), "Статья 2");
+        assert_eq!(referencia(segundo, b"Parent"), id_capitulo);
+        assert_eq!(pagina(&documento, segundo), paginas[1]);
+        assert!(!segundo.has(b"First"));
+    }
+
+    #[test]
+    fn codifica_en_utf16_los_titulos_fuera_de_latin1() {
+        let documento = unir("Orden «ΑΒΓ» 中文");
+        let raiz = referencia(documento.catalog().unwrap(), b"Outlines");
+        let disposicion = documento
+            .get_dictionary(referencia(
+                documento.get_dictionary(raiz).unwrap(),
+                b"First",
+            ))
+            .unwrap();
+
+        let esperado: Vec<u8> = [0xFE, 0xFF]
+            .into_iter()
+            .chain("Orden «ΑΒΓ» 中文".encode_utf16().flat_map(u16::to_be_bytes))
+            .collect();
+        match disposicion.get(b"Title").unwrap() {
+            Object::String(bytes, StringFormat::Hexadecimal) => assert_eq!(bytes, &esperado),
+            otro => panic!("título sin codificar {:?}", otro),
+        }
+        assert_eq!(titulo(disposicion), "Orden «ΑΒΓ» 中文");
+    }
+}
@@ -0,0 +1,324 @@
+use chrono::Utc;
+use lopdf::{decode_text_string, dictionary, Dictionary, Document, Object, Stream};
+
+use super::descargar::EnlacePDF;
+
+pub const PRODUCTOR: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+// Entrada propia del diccionario Info con las url de los documentos unidos. Van separadas por
+// espacios porque PDFDocEncoding no admite saltos de línea
+pub const CLAVE_FUENTES: &str = "BOEFuentes";
+
+// Rellena el diccionario Info del PDF unido para que los gestores documentales puedan indexarlo.
+// fecha es la del boletín, con el formato 07-12-2024
+pub fn rellenar_info(documento: &mut Document, fecha: &str, enlaces: &[EnlacePDF]) {
+    let mut secciones: Vec<&str> = Vec::new();
+    for enlace in enlaces {
+        let seccion = match enlace.seccion.is_empty() {
+            true => enlace.apartado.as_str(),
+            false => enlace.seccion.as_str(),
+        };
+        if !seccion.is_empty() && !secciones.contains(&seccion) {
+            secciones.push(seccion);
+        }
+    }
+    let palabras_clave = ["BOE", fecha]
+        .into_iter()
+        .chain(secciones)
+        .collect::<Vec<&str>>()
+        .join("; ");
+    let fuentes = enlaces
+        .iter()
+        .map(|e| e.url.as_str())
+        .collect::<Vec<&str>>()
+        .join(" ");
+    let asunto = format!(
+        "Boletín Oficial del Estado del {}, {} documentos",
+        fecha,
+        enlaces.len()
+    );
+    let ahora = Object::string_literal(fecha_pdf(&Utc::now()));
+    let info = diccionario_info(documento);
+    info.set(
+        "Title",
+        lopdf::text_string(&format!("BOE completo {}", fecha)),
+    );
+    // El archivo lo genera esta herramienta, el BOE figura solo como origen en el asunto y las
+    // fuentes
+    info.remove(b"Author");
+    info.set("Subject", lopdf::text_string(&asunto));
+    info.set("Keywords", lopdf::text_string(&palabras_clave));
+    info.set("Producer", lopdf::text_string(PRODUCTOR));
+    info.set("CreationDate", ahora.clone());
+    info.set("ModDate", ahora);
+    info.set(CLAVE_FUENTES, lopdf::text_string(&fuentes));
+}
+
+// Diccionario Info del documento, que se crea si no existe
+pub fn diccionario_info(documento: &mut Document) -> &mut Dictionary {
+    let id = match documento.trailer.get(b"Info") {
+        Ok(Object::Reference(id)) if documento.get_dictionary(*id).is_ok() => *id,
+        _ => {
+            let id = documento.add_object(Dictionary::new());
+            documento.trailer.set("Info", id);
+            id
+        }
+    };
+    documento.get_dictionary_mut(id).unwrap()
+}
+
+pub fn completar_info(documento: &mut Document) {
+    let ahora = fecha_pdf(&Utc::now());
+    let info = diccionario_info(documento);
+    if !info.has(b"Producer") {
+        info.set("Producer", lopdf::text_string(PRODUCTOR));
+    }
+    if !info.has(b"CreationDate") {
+        info.set("CreationDate", Object::string_literal(ahora.as_str()));
+    }
+    info.set("ModDate", Object::string_literal(ahora));
+}
+
+pub fn fecha_pdf(fecha: &chrono::DateTime<Utc>) -> String {
+    fecha.format("D:%Y%m%d%H%M%SZ").to_string()
+}
+
+// Genera los metadatos XMP a partir del diccionario Info, que en PDF/A deben coincidir
+pub fn escribir_xmp(documento: &mut Document, pdfa: bool) -> Result<(), String> {
+    let info = diccionario_info(documento).clone();
+    let texto = |clave: &[u8]| {
+        info.get(clave)
+            .ok()
+            .and_then(|valor| decode_text_string(valor).ok())
+            .filter(|valor| !valor.is_empty())
+            .map(|valor| escapar_xml(&valor))
+    };
+    let fecha = |clave: &[u8]| {
+        info.get(clave)
+            .and_then(Object::as_str)
+            .ok()
+            .and_then(|valor| fecha_xmp(&String::from_utf8_lossy(valor)))
+    };
+    let mut propiedades = String::new();
+    if pdfa {
+        propiedades
+            .push_str("<pdfaid:part>2</pdfaid:part><pdfaid:conformance>B</pdfaid:conformance>");
+    }
+    if let Some(titulo) = texto(b"Title") {
+        propiedades.push_str(&format!(
+            "<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>",
+            titulo
+        ));
+    }
+    if let Some(autor) = texto(b"Author") {
+        propiedades.push_str(&format!(
+            "<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>",
+            autor
+        ));
+    }
+    if let Some(asunto) = texto(b"Subject") {
+        propiedades.push_str(&format!(
+            "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>",
+            asunto
+        ));
+    }
+    // Palabras clave y fuentes también como listas de Dublin Core, que es lo que indexan
+    // los gestores
+    let lista = |clave: &[u8], separador: &str| {
+        texto(clave)
+            .map(|valor| {
+                valor
+                    .split(separador)
+                    .filter(|elemento| !elemento.trim().is_empty())
+                    .map(|elemento| format!("<rdf:li>{}</rdf:li>", elemento.trim()))
+                    .collect::<String>()
+            })
+            .filter(|elementos| !elementos.is_empty())
+    };
+    if let Some(palabras) = lista(b"Keywords", ";") {
+        propiedades.push_str(&format!(
+            "<dc:subject><rdf:Bag>{}</rdf:Bag></dc:subject>",
+            palabras
+        ));
+    }
+    if let Some(fuentes) = lista(CLAVE_FUENTES.as_bytes(), " ") {
+        propiedades.push_str(&format!(
+            "<dc:relation><rdf:Bag>{}</rdf:Bag></dc:relation>",
+            fuentes
+        ));
+    }
+    let simples = [
+        ("pdf:Keywords", texto(b"Keywords")),
+        ("pdf:Producer", texto(b"Producer")),
+        ("xmp:CreatorTool", texto(b"Creator")),
+        ("xmp:CreateDate", fecha(b"CreationDate")),
+        ("xmp:ModifyDate", fecha(b"ModDate")),
+    ];
+    for (propiedad, valor) in simples {
+        if let Some(valor) = valor {
+            propiedades.push_str(&format!("<{0}>{1}</{0}>", propiedad, valor));
+        }
+    }
+    let paquete = format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">",
+            "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">",
+            "<rdf:Description rdf:about=\"\"",
+            " xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\"",
+            " xmlns:dc=\"http://purl.org/dc/elements/1.1/\"",
+            " xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"",
+            " xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\">",
+            "{}",
+            "</rdf:Description></rdf:RDF></x:xmpmeta>\n",
+            "<?xpacket end=\"w\"?>"
+        ),
+        propiedades
+    );
+    // PDF/A no admite filtros en el flujo de metadatos, así que no se comprime al guardar
+    let metadatos = Stream::new(
+        dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+        paquete.into_bytes(),
+    )
+    .with_compression(false);
+    // Se sustituyen los metadatos que hubiera, incluidos los heredados del primer documento unido
+    let anterior = documento
+        .catalog()
+        .and_then(|c| c.get(b"Metadata"))
+        .and_then(Object::as_reference);
+    if let Ok(id) = anterior {
+        if let Some(objeto) = documento.objects.get_mut(&id) {
+            *objeto = Object::Stream(metadatos);
+            return Ok(());
+        }
+    }
+    let id_metadatos = documento.add_object(metadatos);
+    match documento.catalog_mut() {
+        Err(error) => {
+            let mensaje_error = format!("no se han podido añadir los metadatos XMP {}", error);
+            Err(mensaje_error)
+        }
+        Ok(catalogo) => {
+            catalogo.set("Metadata", id_metadatos);
+            Ok(())
+        }
+    }
+}
+
+// "D:20241207103000+01'00'" -> "2024-12-07T10:30:00+01:00"
+pub fn fecha_xmp(fecha: &str) -> Option<String> {
+    let fecha = fecha.strip_prefix("D:").unwrap_or(fecha);
+    let cifras = fecha
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect::<String>();
+    if cifras.len() < 4 || cifras.len() % 2 != 0 || cifras.len() > 14 {
+        return None;
+    }
+    let parte = |inicio: usize| &cifras[inicio..inicio + 2];
+    let mut resultado = cifras[..4].to_string();
+    if cifras.len() >= 6 {
+        resultado.push_str(&format!("-{}", parte(4)));
+    }
+    if cifras.len() >= 8 {
+        resultado.push_str(&format!("-{}", parte(6)));
+    }
+    // XMP necesita al menos horas y minutos para indicar la hora
+    if cifras.len() >= 12 {
+        resultado.push_str(&format!("T{}:{}", parte(8), parte(10)));
+        if cifras.len() == 14 {
+            resultado.push_str(&format!(":{}", parte(12)));
+        }
+        let zona = &fecha[cifras.len()..];
+        match zona.chars().next() {
+            Some('Z') => resultado.push('Z'),
+            Some(signo @ ('+' | '-')) => {
+                let zona = zona[1..].replace('\'', "");
+                if zona.len() >= 4 && zona.chars().all(|c| c.is_ascii_digit()) {
+                    resultado.push_str(&format!("{}{}:{}", signo, &zona[..2], &zona[2..4]));
+                }
+            }
+            _ => (),
+        }
+    }
+    Some(resultado)
+}
+
+fn escapar_xml(texto: &str) -> String {
+    texto
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enlace(url: &str, seccion: &str) -> EnlacePDF {
+        EnlacePDF {
+            apartado: String::from("Disposiciones y anuncios"),
+            url: url.to_string(),
+            titulo: String::from("Orden de prueba"),
+            seccion: seccion.to_string(),
+            ..EnlacePDF::default()
+        }
+    }
+
+    #[test]
+    fn rellena_info_y_xmp_con_los_mismos_datos() {
+        let mut documento = Document::with_version("1.5");
+        let catalogo = documento.add_object(dictionary! { "Type" => "Catalog" });
+        documento.trailer.set("Root", catalogo);
+        let enlaces = [
+            enlace("https://boe.es/a.pdf?x=1&y=2", "I. Disposiciones generales"),
+            enlace("https://boe.es/b.pdf", "III. Otras disposiciones"),
+            enlace("https://boe.es/c.pdf", "I. Disposiciones generales"),
+        ];
+        rellenar_info(&mut documento, "07-12-2024", &enlaces);
+        escribir_xmp(&mut documento, false).unwrap();
+        // Una segunda llamada sustituye los metadatos en lugar de añadir otros
+        escribir_xmp(&mut documento, false).unwrap();
+
+        let info = diccionario_info(&mut documento).clone();
+        let texto = |clave: &[u8]| decode_text_string(info.get(clave).unwrap()).unwrap();
+        assert_eq!(texto(b"Title"), "BOE completo 07-12-2024");
+        assert_eq!(
+            texto(b"Keywords"),
+            "BOE; 07-12-2024; I. Disposiciones generales; III. Otras disposiciones"
+        );
+        assert_eq!(
+            texto(CLAVE_FUENTES.as_bytes()).split_whitespace().count(),
+            3
+        );
+        assert!(!info.has(b"Author"));
+
+        let metadatos = documento
+            .objects
+            .values()
+            .filter_map(|o| o.as_stream().ok())
+            .filter(|s| s.dict.get(b"Type").and_then(Object::as_name).ok() == Some(b"Metadata"))
+            .collect::<Vec<_>>();
+        assert_eq!(metadatos.len(), 1);
+        let xmp = String::from_utf8(metadatos[0].content.clone()).unwrap();
+        assert!(xmp.contains("<rdf:li xml:lang=\"x-default\">BOE completo 07-12-2024</rdf:li>"));
+        assert!(xmp.contains("<rdf:li>https://boe.es/a.pdf?x=1&amp;y=2</rdf:li>"));
+        assert!(xmp.contains("<rdf:li>III. Otras disposiciones</rdf:li>"));
+        assert!(!xmp.contains("<pdfaid:part>"));
+        assert!(!xmp.contains("<dc:creator>"));
+    }
+
+    #[test]
+    fn convierte_las_fechas_a_xmp() {
+        assert_eq!(
+            fecha_xmp("D:20241207103000+01'00'").unwrap(),
+            "2024-12-07T10:30:00+01:00"
+        );
+        assert_eq!(
+            fecha_xmp("D:20241207103000Z").unwrap(),
+            "2024-12-07T10:30:00Z"
+        );
+        assert_eq!(fecha_xmp("D:20241207").unwrap(), "2024-12-07");
+        assert_eq!(fecha_xmp("D:2024120"), None);
+    }
+}
@@ -1,3 +1,28 @@
+pub mod argumentos;
+pub mod cifrar;
 pub mod cliente_http;
+pub mod comparar;
+pub mod configuracion;
+pub mod correcciones;
 pub mod descargar;
+pub mod dividir;
+pub mod documento_xml;
+pub mod duplicados;
+pub mod estampar;
+pub mod filtros;
+pub mod firmar;
+pub mod firmas;
+pub mod individuales;
+pub mod marcadores;
+pub mod metadatos;
+pub mod optimizar;
+pub mod paquete;
+pub mod pdfa;
+pub mod progreso;
+pub mod registro;
+pub mod servir;
+pub mod unir_pdf;
 pub mod validar_fecha;
+pub mod validar_pdf;
+pub mod vigilar;
+pub mod volumenes;
@@ -0,0 +1,218 @@
+use std::collections::{BTreeMap, HashMap};
+
+use lopdf::{Document, Object, ObjectId};
+use sha2::{Digest, Sha256};
+
+// Objetos que forman la estructura del documento y no pueden compartirse aunque sean iguales
+const TIPOS_ESTRUCTURA: [&[u8]; 8] = [
+    b"Catalog",
+    b"Pages",
+    b"Page",
+    b"Annot",
+    b"Outlines",
+    b"Sig",
+    b"ObjStm",
+    b"XRef",
+];
+const CLAVES_ESTRUCTURA: [&[u8]; 6] = [b"Parent", b"P", b"Kids", b"First", b"Next", b"Prev"];
+
+pub struct ResultadoOptimizacion {
+    pub objetos_eliminados: usize,
+    // Tamaño de los objetos eliminados antes de comprimir, orientativo
+    pub bytes_ahorrados: usize,
+}
+
+// Cada PDF del BOE incrusta las mismas fuentes e imágenes. Se agrupan los objetos idénticos,
+// se deja uno y se redirigen las referencias. Se repite hasta que no cambia nada porque al
+// unificar los archivos de fuente pasan a ser iguales sus descriptores y después las fuentes
+pub fn compartir_recursos(documento: &mut Document) -> ResultadoOptimizacion {
+    let mut resultado = ResultadoOptimizacion {
+        objetos_eliminados: 0,
+        bytes_ahorrados: 0,
+    };
+    loop {
+        let mut conservados: HashMap<[u8; 32], ObjectId> = HashMap::new();
+        let mut sustituciones: BTreeMap<ObjectId, ObjectId> = BTreeMap::new();
+        for (id, objeto) in &documento.objects {
+            if !se_puede_compartir(objeto) {
+                continue;
+            }
+            let mut huella = Huella::default();
+            huella.añadir_objeto(objeto);
+            let tamaño = huella.bytes;
+            let clave = huella.finalizar();
+            match conservados.get(&clave) {
+                Some(conservado) => {
+                    sustituciones.insert(*id, *conservado);
+                    resultado.bytes_ahorrados += tamaño;
+                }
+                // Se recorren en orden, así que se conserva siempre el de menor id
+                None => {
+                    conservados.insert(clave, *id);
+                }
+            }
+        }
+        if sustituciones.is_empty() {
+            return resultado;
+        }
+        resultado.objetos_eliminados += sustituciones.len();
+        for id in sustituciones.keys() {
+            documento.objects.remove(id);
+        }
+        for objeto in documento.objects.values_mut() {
+            redirigir_referencias(objeto, &sustituciones);
+        }
+        for (_, objeto) in documento.trailer.iter_mut() {
+            redirigir_referencias(objeto, &sustituciones);
+        }
+    }
+}
+
+fn se_puede_compartir(objeto: &Object) -> bool {
+    let diccionario = match objeto {
+        Object::Stream(flujo) => &flujo.dict,
+        Object::Dictionary(diccionario) => diccionario,
+        Object::Array(_) => return true,
+        _ => return false,
+    };
+    if let Ok(tipo) = diccionario.get(b"Type").and_then(Object::as_name) {
+        if TIPOS_ESTRUCTURA.contains(&tipo) {
+            return false;
+        }
+    }
+    !CLAVES_ESTRUCTURA.iter().any(|clave| diccionario.has(clave))
+}
+
+fn redirigir_referencias(objeto: &mut Object, sustituciones: &BTreeMap<ObjectId, ObjectId>) {
+    match objeto {
+        Object::Reference(id) => {
+            if let Some(nuevo) = sustituciones.get(id) {
+                *id = *nuevo;
+            }
+        }
+        Object::Array(elementos) => {
+            for elemento in elementos {
+                redirigir_referencias(elemento, sustituciones);
+            }
+        }
+        Object::Dictionary(diccionario) => {
+            for (_, valor) in diccionario.iter_mut() {
+                redirigir_referencias(valor, sustituciones);
+            }
+        }
+        Object::Stream(flujo) => {
+            for (_, valor) in flujo.dict.iter_mut() {
+                redirigir_referencias(valor, sustituciones);
+            }
+        }
+        _ => (),
+    }
+}
+
+// Serialización canónica del objeto (claves ordenadas) que se resume con SHA-256 sobre la marcha
+#[derive(Default)]
+struct Huella {
+    resumen: Sha256,
+    bytes: usize,
+}
+
+impl Huella {
+    fn añadir(&mut self, datos: &[u8]) {
+        self.resumen.update(datos);
+        self.bytes += datos.len();
+    }
+    fn añadir_objeto(&mut self, objeto: &Object) {
+        match objeto {
+            Object::Null => self.añadir(b"n"),
+            Object::Boolean(valor) => self.añadir(if *valor { b"t" } else { b"f" }),
+            Object::Integer(valor) => self.añadir(format!("i{};", valor).as_bytes()),
+            Object::Real(valor) => self.añadir(format!("r{};", valor).as_bytes()),
+            Object::Name(nombre) => {
+                self.añadir(format!("/{};", nombre.len()).as_bytes());
+                self.añadir(nombre);
+            }
+            Object::String(texto, _) => {
+                self.añadir(format!("({};", texto.len()).as_bytes());
+                self.añadir(texto);
+            }
+            Object::Reference((numero, generacion)) => {
+                self.añadir(format!("R{} {};", numero, generacion).as_bytes())
+            }
+            Object::Array(elementos) => {
+                self.añadir(format!("[{};", elementos.len()).as_bytes());
+                for elemento in elementos {
+                    self.añadir_objeto(elemento);
+                }
+            }
+            Object::Dictionary(diccionario) => self.añadir_diccionario(diccionario),
+            Object::Stream(flujo) => {
+                self.añadir(b"S");
+                self.añadir_diccionario(&flujo.dict);
+                self.añadir(format!("{};", flujo.content.len()).as_bytes());
+                self.añadir(&flujo.content);
+            }
+        }
+    }
+    fn añadir_diccionario(&mut self, diccionario: &lopdf::Dictionary) {
+        let mut entradas = diccionario.iter().collect::<Vec<_>>();
+        entradas.sort_by(|a, b| a.0.cmp(b.0));
+        self.añadir(format!("<{};", entradas.len()).as_bytes());
+        for (clave, valor) in entradas {
+            self.añadir(format!("/{};", clave.len()).as_bytes());
+            self.añadir(clave);
+            self.añadir_objeto(valor);
+        }
+    }
+    fn finalizar(self) -> [u8; 32] {
+        self.resumen.finalize().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lopdf::{dictionary, Stream};
+
+    use super::*;
+
+    #[test]
+    fn unifica_fuentes_identicas_en_cascada() {
+        let mut documento = Document::with_version("1.5");
+        let mut fuentes = Vec::new();
+        for _ in 0..3 {
+            let archivo = documento.add_object(Stream::new(dictionary! {}, vec![7; 1000]));
+            let descriptor = documento.add_object(dictionary! {
+                "Type" => "FontDescriptor",
+                "FontName" => "BOEFont",
+                "FontFile2" => archivo,
+            });
+            let fuente = documento.add_object(dictionary! {
+                "Type" => "Font",
+                "Subtype" => "TrueType",
+                "BaseFont" => "BOEFont",
+                "FontDescriptor" => descriptor,
+            });
+            let pagina = documento.add_object(dictionary! {
+                "Type" => "Page",
+                "Resources" => dictionary! { "Font" => dictionary! { "F1" => fuente } },
+            });
+            fuentes.push((fuente, pagina));
+        }
+
+        let resultado = compartir_recursos(&mut documento);
+        assert_eq!(resultado.objetos_eliminados, 6);
+        assert!(resultado.bytes_ahorrados >= 2000);
+        // Las páginas se mantienen y todas usan la primera fuente
+        for (_, pagina) in &fuentes {
+            let fuente = documento
+                .get_dictionary(*pagina)
+                .and_then(|p| p.get_deref(b"Resources", &documento))
+                .and_then(Object::as_dict)
+                .and_then(|r| r.get(b"Font"))
+                .and_then(Object::as_dict)
+                .and_then(|f| f.get(b"F1"))
+                .and_then(Object::as_reference)
+                .unwrap();
+            assert_eq!(fuente, fuentes[0].0);
+        }
+    }
+}
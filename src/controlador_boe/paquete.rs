@@ -0,0 +1,253 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use super::documento_xml::MetadatosXml;
+
+pub const NOMBRE_MANIFIESTO: &str = "manifiesto.sha256";
+pub const NOMBRE_MANIFIESTO_JSON: &str = "manifiesto.json";
+
+pub enum OrigenEntrada<'a> {
+    Archivo(PathBuf),
+    Memoria(&'a [u8]),
+}
+
+pub struct EntradaPaquete<'a> {
+    // Ruta dentro del ZIP, con / como separador
+    pub nombre: String,
+    pub origen: OrigenEntrada<'a>,
+    // Metadatos del XML del documento, si se han leído
+    pub metadatos_xml: Option<&'a MetadatosXml>,
+}
+
+// Entrada del manifiesto JSON, que solo se escribe si algún documento lleva metadatos
+#[derive(Serialize)]
+struct EntradaManifiesto<'a> {
+    nombre: &'a str,
+    sha256: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadatos_xml: Option<&'a MetadatosXml>,
+}
+
+// Escribe el ZIP copiando cada entrada por bloques, sin cargar los archivos en memoria, y termina
+// con un manifiesto en el formato de sha256sum para poder comprobarlo con sha256sum -c
+pub fn crear_paquete(ruta: &Path, entradas: &[EntradaPaquete]) -> Result<(), String> {
+    // Se escribe con otro nombre y se renombra al final para no dejar nunca un ZIP a medias
+    let ruta_parcial = ruta.with_extension("zip.parcial");
+    let archivo = match File::create(&ruta_parcial) {
+        Err(error) => {
+            let mensaje_error = format!(
+                "no se ha podido crear el paquete {} {}",
+                ruta_parcial.display(),
+                error
+            );
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    let mut zip = ZipWriter::new(BufWriter::new(archivo));
+    let mut manifiesto = String::new();
+    let mut manifiesto_json = Vec::new();
+    for entrada in entradas {
+        match escribir_entrada(&mut zip, entrada) {
+            Err(error) => {
+                let _ = fs::remove_file(&ruta_parcial);
+                let mensaje_error = format!(
+                    "ha fallado la copia de {} al paquete {}",
+                    entrada.nombre, error
+                );
+                return Err(mensaje_error);
+            }
+            Ok(resumen) => {
+                manifiesto.push_str(&format!("{}  {}\n", resumen, entrada.nombre));
+                manifiesto_json.push(EntradaManifiesto {
+                    nombre: &entrada.nombre,
+                    sha256: resumen,
+                    metadatos_xml: entrada.metadatos_xml,
+                });
+            }
+        }
+    }
+    let mut manifiestos = vec![(NOMBRE_MANIFIESTO, manifiesto.into_bytes())];
+    if manifiesto_json.iter().any(|e| e.metadatos_xml.is_some()) {
+        match serde_json::to_vec_pretty(&manifiesto_json) {
+            Err(error) => {
+                let _ = fs::remove_file(&ruta_parcial);
+                let mensaje_error = format!("ha fallado la creación del manifiesto JSON {}", error);
+                return Err(mensaje_error);
+            }
+            Ok(ok) => manifiestos.push((NOMBRE_MANIFIESTO_JSON, ok)),
+        }
+    }
+    let resultado = manifiestos
+        .iter()
+        .try_for_each(|(nombre, contenido)| {
+            let entrada = EntradaPaquete {
+                nombre: nombre.to_string(),
+                origen: OrigenEntrada::Memoria(contenido),
+                metadatos_xml: None,
+            };
+            escribir_entrada(&mut zip, &entrada).map(|_| ())
+        })
+        .and_then(|_| zip.finish().map_err(io::Error::from))
+        .and_then(|mut escritor| escritor.flush());
+    if let Err(error) = resultado {
+        let _ = fs::remove_file(&ruta_parcial);
+        let mensaje_error = format!(
+            "ha fallado la escritura del paquete {} {}",
+            ruta.display(),
+            error
+        );
+        return Err(mensaje_error);
+    }
+    match fs::rename(&ruta_parcial, ruta) {
+        Err(error) => {
+            let mensaje_error = format!(
+                "no se ha podido mover el paquete a {} {}",
+                ruta.display(),
+                error
+            );
+            Err(mensaje_error)
+        }
+        Ok(_) => Ok(()),
+    }
+}
+
+// Devuelve el resumen SHA-256 en hexadecimal del contenido copiado
+fn escribir_entrada<W: Write + io::Seek>(
+    zip: &mut ZipWriter<W>,
+    entrada: &EntradaPaquete,
+) -> io::Result<String> {
+    // Los PDF ya van comprimidos por dentro, volver a comprimirlos solo gasta tiempo
+    let metodo = match entrada.nombre.to_lowercase().ends_with(".pdf") {
+        true => CompressionMethod::Stored,
+        false => CompressionMethod::Deflated,
+    };
+    let opciones = SimpleFileOptions::default()
+        .compression_method(metodo)
+        .large_file(true);
+    zip.start_file(entrada.nombre.as_str(), opciones)?;
+    let mut destino = EscritorResumen {
+        interno: zip,
+        resumen: Sha256::new(),
+    };
+    match &entrada.origen {
+        OrigenEntrada::Archivo(ruta) => {
+            io::copy(&mut File::open(ruta)?, &mut destino)?;
+        }
+        OrigenEntrada::Memoria(bytes) => destino.write_all(bytes)?,
+    }
+    Ok(destino
+        .resumen
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+// Calcula el resumen de lo que se escribe en el ZIP sin tener que leer dos veces cada archivo
+struct EscritorResumen<'a, W: Write> {
+    interno: &'a mut W,
+    resumen: Sha256,
+}
+
+impl<W: Write> Write for EscritorResumen<'_, W> {
+    fn write(&mut self, datos: &[u8]) -> io::Result<usize> {
+        let escritos = self.interno.write(datos)?;
+        self.resumen.update(&datos[..escritos]);
+        Ok(escritos)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.interno.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use tempfile::TempDir;
+    use zip::ZipArchive;
+
+    use super::{super::duplicados::resumen_sha256, *};
+
+    #[test]
+    fn empaqueta_archivos_y_memoria_con_manifiesto() {
+        let directorio = TempDir::new().unwrap();
+        let ruta_informe = directorio.path().join("informe.tsv");
+        fs::write(&ruta_informe, "Apartado\tTítulo\n").unwrap();
+        let pdf = b"%PDF-1.5 contenido".to_vec();
+        let entradas = vec![
+            EntradaPaquete {
+                nombre: String::from("informe.tsv"),
+                origen: OrigenEntrada::Archivo(ruta_informe),
+                metadatos_xml: None,
+            },
+            EntradaPaquete {
+                nombre: String::from("documentos/notificaciones/BOE-N-1.pdf"),
+                origen: OrigenEntrada::Memoria(&pdf),
+                metadatos_xml: None,
+            },
+        ];
+        let ruta_zip = directorio.path().join("boe.zip");
+        crear_paquete(&ruta_zip, &entradas).unwrap();
+        assert!(!ruta_zip.with_extension("zip.parcial").exists());
+
+        let mut paquete = ZipArchive::new(File::open(&ruta_zip).unwrap()).unwrap();
+        assert_eq!(paquete.len(), 3);
+        let mut manifiesto = String::new();
+        paquete
+            .by_name(NOMBRE_MANIFIESTO)
+            .unwrap()
+            .read_to_string(&mut manifiesto)
+            .unwrap();
+        assert_eq!(
+            manifiesto,
+            format!(
+                "{}  informe.tsv\n{}  documentos/notificaciones/BOE-N-1.pdf\n",
+                resumen_sha256("Apartado\tTítulo\n".as_bytes()),
+                resumen_sha256(&pdf)
+            )
+        );
+        let documento = paquete
+            .by_name("documentos/notificaciones/BOE-N-1.pdf")
+            .unwrap();
+        assert_eq!(documento.compression(), CompressionMethod::Stored);
+    }
+
+    #[test]
+    fn añade_el_manifiesto_json_con_los_metadatos() {
+        let directorio = TempDir::new().unwrap();
+        let pdf = b"%PDF-1.5 contenido".to_vec();
+        let metadatos = MetadatosXml {
+            identificador: String::from("BOE-A-2024-25900"),
+            rango: Some(String::from("Orden")),
+            ..MetadatosXml::default()
+        };
+        let entradas = vec![EntradaPaquete {
+            nombre: String::from("documentos/i_disposiciones_generales/BOE-A-2024-25900.pdf"),
+            origen: OrigenEntrada::Memoria(&pdf),
+            metadatos_xml: Some(&metadatos),
+        }];
+        let ruta_zip = directorio.path().join("boe.zip");
+        crear_paquete(&ruta_zip, &entradas).unwrap();
+
+        let mut paquete = ZipArchive::new(File::open(&ruta_zip).unwrap()).unwrap();
+        assert_eq!(paquete.len(), 3);
+        let mut manifiesto = String::new();
+        paquete
+            .by_name(NOMBRE_MANIFIESTO_JSON)
+            .unwrap()
+            .read_to_string(&mut manifiesto)
+            .unwrap();
+        let manifiesto: serde_json::Value = serde_json::from_str(&manifiesto).unwrap();
+        assert_eq!(manifiesto[0]["sha256"], resumen_sha256(&pdf));
+        assert_eq!(manifiesto[0]["metadatos_xml"]["rango"], "Orden");
+    }
+}
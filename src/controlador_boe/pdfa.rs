@@ -0,0 +1,566 @@
+use std::collections::{BTreeSet, HashSet};
+
+use chrono::Utc;
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use sha2::{Digest, Sha256};
+
+use super::metadatos;
+
+const CONDICION_SALIDA: &str = "sRGB IEC61966-2.1";
+
+// Acciones que PDF/A-2 no admite (ISO 19005-2, 6.5.1)
+const ACCIONES_PROHIBIDAS: [&[u8]; 11] = [
+    b"Launch",
+    b"Sound",
+    b"Movie",
+    b"ResetForm",
+    b"ImportData",
+    b"JavaScript",
+    b"Hide",
+    b"SetOCGState",
+    b"Rendition",
+    b"Trans",
+    b"GoTo3DView",
+];
+const ANOTACIONES_PROHIBIDAS: [&[u8]; 6] = [
+    b"Sound",
+    b"Movie",
+    b"Screen",
+    b"3D",
+    b"RichMedia",
+    b"FileAttachment",
+];
+// Banderas de anotación: Invisible, Hidden, Print y NoView
+const ANOTACION_OCULTA: i64 = 1 | 2 | 32;
+const ANOTACION_IMPRIMIR: i64 = 4;
+
+// Adapta el documento unido a PDF/A-2b y comprueba que cumple los requisitos que no se pueden
+// arreglar, como las fuentes sin incrustar. Debe llamarse antes de comprimir y guardar
+pub fn convertir_pdfa(documento: &mut Document) -> Result<(), String> {
+    documento.trailer.remove(b"Encrypt");
+    eliminar_funciones_prohibidas(documento);
+    ajustar_anotaciones(documento);
+    // Quedan sin referencias el JavaScript, los adjuntos y las anotaciones eliminadas
+    documento.prune_objects();
+    descomprimir_lzw(documento);
+    añadir_intencion_salida(documento)?;
+    metadatos::completar_info(documento);
+    metadatos::escribir_xmp(documento, true)?;
+    añadir_identificador(documento);
+    documento.version = String::from("1.7");
+
+    let problemas = validar_pdfa(documento);
+    if !problemas.is_empty() {
+        let mensaje_error = format!(
+            "el PDF unido no cumple PDF/A-2b: {}",
+            problemas.into_iter().collect::<Vec<String>>().join("; ")
+        );
+        return Err(mensaje_error);
+    }
+    Ok(())
+}
+
+// Requisitos estructurales de PDF/A-2b que se pueden comprobar sin interpretar el contenido
+pub fn validar_pdfa(documento: &Document) -> BTreeSet<String> {
+    let mut problemas = BTreeSet::new();
+    if documento.trailer.has(b"Encrypt") {
+        problemas.insert(String::from("el documento está cifrado"));
+    }
+    if !documento.trailer.has(b"ID") {
+        problemas.insert(String::from(
+            "falta el identificador del documento en el trailer",
+        ));
+    }
+    match documento.catalog() {
+        Err(_) => {
+            problemas.insert(String::from("no se encuentra el catálogo"));
+        }
+        Ok(catalogo) => {
+            if !catalogo.has(b"Metadata") {
+                problemas.insert(String::from("faltan los metadatos XMP"));
+            }
+            if !catalogo.has(b"OutputIntents") {
+                problemas.insert(String::from("falta el perfil de color de salida"));
+            }
+        }
+    }
+    for objeto in documento.objects.values() {
+        let diccionario = match objeto {
+            Object::Dictionary(diccionario) => diccionario,
+            Object::Stream(flujo) => {
+                if flujo
+                    .filters()
+                    .unwrap_or_default()
+                    .iter()
+                    .any(|f| f == "LZWDecode")
+                {
+                    problemas.insert(String::from("hay flujos comprimidos con LZW"));
+                }
+                if [b"F".as_slice(), b"FFilter", b"FDecodeParms"]
+                    .iter()
+                    .any(|c| flujo.dict.has(c))
+                {
+                    problemas.insert(String::from("hay flujos con datos en archivos externos"));
+                }
+                if nombre(&flujo.dict, b"Subtype") == Some(b"PS".as_slice()) {
+                    problemas.insert(String::from("hay objetos PostScript"));
+                }
+                &flujo.dict
+            }
+            _ => continue,
+        };
+        if let Some(accion) = nombre(diccionario, b"S") {
+            if ACCIONES_PROHIBIDAS.contains(&accion) {
+                problemas.insert(format!(
+                    "hay acciones {} no permitidas",
+                    String::from_utf8_lossy(accion)
+                ));
+            }
+        }
+        if nombre(diccionario, b"Type") == Some(b"Font".as_slice()) {
+            if let Some(fuente) = fuente_sin_incrustar(documento, diccionario) {
+                problemas.insert(format!("la fuente {} no está incrustada", fuente));
+            }
+        }
+    }
+    problemas
+}
+
+fn fuente_sin_incrustar(documento: &Document, fuente: &Dictionary) -> Option<String> {
+    let nombre_fuente = nombre(fuente, b"BaseFont")
+        .map(|n| String::from_utf8_lossy(n).to_string())
+        .unwrap_or_default();
+    let fuente = match nombre(fuente, b"Subtype") {
+        // Las fuentes Type3 se dibujan con operadores del propio PDF
+        Some(b"Type3") => return None,
+        Some(b"Type0") => fuente
+            .get(b"DescendantFonts")
+            .and_then(|d| resolver(documento, d).as_array())
+            .ok()
+            .and_then(|d| d.first())
+            .and_then(|d| resolver(documento, d).as_dict().ok())?,
+        _ => fuente,
+    };
+    let incrustada = fuente
+        .get(b"FontDescriptor")
+        .and_then(|d| resolver(documento, d).as_dict())
+        .map(|d| {
+            [b"FontFile".as_slice(), b"FontFile2", b"FontFile3"]
+                .iter()
+                .any(|c| d.has(c))
+        })
+        .unwrap_or(false);
+    match incrustada {
+        true => None,
+        false => Some(nombre_fuente),
+    }
+}
+
+fn eliminar_funciones_prohibidas(documento: &mut Document) {
+    let acciones_prohibidas = documento
+        .objects
+        .iter()
+        .filter(|(_, objeto)| match objeto.as_dict() {
+            Err(_) => false,
+            Ok(diccionario) => es_accion_prohibida(diccionario),
+        })
+        .map(|(id, _)| *id)
+        .collect::<HashSet<ObjectId>>();
+    for objeto in documento.objects.values_mut() {
+        limpiar_objeto(objeto, &acciones_prohibidas);
+    }
+    if let Ok(catalogo) = documento.catalog_mut() {
+        catalogo.remove(b"Requirements");
+        catalogo.remove(b"NeedsRendering");
+    }
+    // Los adjuntos tendrían que ser a su vez PDF/A, así que se quitan todos
+    if let Some(nombres) = diccionario_catalogo(documento, b"Names") {
+        nombres.remove(b"JavaScript");
+        nombres.remove(b"EmbeddedFiles");
+    }
+    if let Some(formulario) = diccionario_catalogo(documento, b"AcroForm") {
+        formulario.remove(b"NeedAppearances");
+        formulario.remove(b"XFA");
+    }
+}
+
+// Entrada del catálogo que puede estar directamente en él o ser una referencia
+fn diccionario_catalogo<'a>(
+    documento: &'a mut Document,
+    clave: &[u8],
+) -> Option<&'a mut Dictionary> {
+    let id = match documento.catalog().ok()?.get(clave).ok()? {
+        Object::Reference(id) => *id,
+        Object::Dictionary(_) => {
+            return documento
+                .catalog_mut()
+                .ok()?
+                .get_mut(clave)
+                .ok()?
+                .as_dict_mut()
+                .ok();
+        }
+        _ => return None,
+    };
+    documento.get_dictionary_mut(id).ok()
+}
+
+fn es_accion_prohibida(diccionario: &Dictionary) -> bool {
+    nombre(diccionario, b"S").is_some_and(|accion| ACCIONES_PROHIBIDAS.contains(&accion))
+}
+
+// Quita acciones automáticas y prohibidas, funciones de transferencia e interpolación de imágenes
+fn limpiar_objeto(objeto: &mut Object, acciones_prohibidas: &HashSet<ObjectId>) {
+    let diccionario = match objeto {
+        Object::Dictionary(diccionario) => diccionario,
+        Object::Stream(flujo) => {
+            for clave in [b"Interpolate".as_slice(), b"Alternates", b"OPI"] {
+                flujo.dict.remove(clave);
+            }
+            &mut flujo.dict
+        }
+        Object::Array(elementos) => {
+            for elemento in elementos {
+                limpiar_objeto(elemento, acciones_prohibidas);
+            }
+            return;
+        }
+        _ => return,
+    };
+    diccionario.remove(b"AA");
+    if nombre(diccionario, b"Type") == Some(b"ExtGState".as_slice()) {
+        diccionario.remove(b"TR");
+    }
+    for clave in [b"A".as_slice(), b"OpenAction", b"Next"] {
+        let prohibida = match diccionario.get(clave) {
+            Ok(Object::Reference(id)) => acciones_prohibidas.contains(id),
+            Ok(Object::Dictionary(accion)) => es_accion_prohibida(accion),
+            _ => false,
+        };
+        if prohibida {
+            diccionario.remove(clave);
+        }
+    }
+    for (_, valor) in diccionario.iter_mut() {
+        limpiar_objeto(valor, acciones_prohibidas);
+    }
+}
+
+// Todas las anotaciones tienen que imprimirse y ninguna puede ser multimedia
+fn ajustar_anotaciones(documento: &mut Document) {
+    for id_pagina in documento.get_pages().into_values() {
+        let anotaciones = match documento
+            .get_dictionary(id_pagina)
+            .and_then(|p| p.get(b"Annots"))
+            .map(|a| resolver(documento, a))
+        {
+            Ok(Object::Array(anotaciones)) => anotaciones.clone(),
+            _ => continue,
+        };
+        let mut conservadas = Vec::new();
+        for mut anotacion in anotaciones {
+            let diccionario = match &mut anotacion {
+                Object::Reference(id) => documento.get_dictionary_mut(*id).ok(),
+                Object::Dictionary(diccionario) => Some(diccionario),
+                _ => None,
+            };
+            let diccionario = match diccionario {
+                None => continue,
+                Some(diccionario) => diccionario,
+            };
+            if nombre(diccionario, b"Subtype").is_some_and(|s| ANOTACIONES_PROHIBIDAS.contains(&s))
+            {
+                continue;
+            }
+            let banderas = diccionario.get(b"F").and_then(Object::as_i64).unwrap_or(0);
+            diccionario.set("F", (banderas & !ANOTACION_OCULTA) | ANOTACION_IMPRIMIR);
+            conservadas.push(anotacion);
+        }
+        if let Ok(pagina) = documento.get_dictionary_mut(id_pagina) {
+            pagina.set("Annots", conservadas);
+        }
+    }
+}
+
+// LZW no está permitido, se descomprime y después compress vuelve a comprimirlo con Flate
+fn descomprimir_lzw(documento: &mut Document) {
+    for objeto in documento.objects.values_mut() {
+        if let Object::Stream(flujo) = objeto {
+            if flujo
+                .filters()
+                .unwrap_or_default()
+                .iter()
+                .any(|f| f == "LZWDecode")
+            {
+                flujo.decompress();
+            }
+        }
+    }
+}
+
+fn añadir_intencion_salida(documento: &mut Document) -> Result<(), String> {
+    let perfil = Stream::new(dictionary! { "N" => 3 }, perfil_srgb());
+    let id_perfil = documento.add_object(perfil);
+    let intencion = dictionary! {
+        "Type" => "OutputIntent",
+        "S" => "GTS_PDFA1",
+        "OutputConditionIdentifier" => Object::string_literal(CONDICION_SALIDA),
+        "Info" => Object::string_literal(CONDICION_SALIDA),
+        "RegistryName" => Object::string_literal("http://www.color.org"),
+        "DestOutputProfile" => id_perfil,
+    };
+    let id_intencion = documento.add_object(intencion);
+    match documento.catalog_mut() {
+        Err(error) => {
+            let mensaje_error = format!("no se ha podido añadir el perfil de color {}", error);
+            Err(mensaje_error)
+        }
+        Ok(catalogo) => {
+            catalogo.set("OutputIntents", vec![Object::Reference(id_intencion)]);
+            Ok(())
+        }
+    }
+}
+
+pub fn añadir_identificador(documento: &mut Document) {
+    let mut resumen = Sha256::new();
+    resumen.update(Utc::now().to_rfc3339().as_bytes());
+    resumen.update(documento.max_id.to_le_bytes());
+    let identificador = resumen.finalize()[..16].to_vec();
+    documento.trailer.set(
+        "ID",
+        vec![
+            Object::String(identificador.clone(), lopdf::StringFormat::Hexadecimal),
+            Object::String(identificador, lopdf::StringFormat::Hexadecimal),
+        ],
+    );
+}
+
+// Perfil ICC v2 de sRGB: primarios adaptados a D50 y la curva de transferencia de sRGB en tabla
+pub fn perfil_srgb() -> Vec<u8> {
+    let xyz = |valores: [f64; 3]| {
+        let mut datos = b"XYZ \0\0\0\0".to_vec();
+        for valor in valores {
+            datos.extend(((valor * 65536.0).round() as i32).to_be_bytes());
+        }
+        datos
+    };
+    let mut curva = b"curv\0\0\0\0".to_vec();
+    curva.extend(1024u32.to_be_bytes());
+    for indice in 0..1024 {
+        let entrada = indice as f64 / 1023.0;
+        let salida = match entrada <= 0.04045 {
+            true => entrada / 12.92,
+            false => ((entrada + 0.055) / 1.055).powf(2.4),
+        };
+        curva.extend(((salida * 65535.0).round() as u16).to_be_bytes());
+    }
+    let mut descripcion = b"desc\0\0\0\0".to_vec();
+    descripcion.extend((CONDICION_SALIDA.len() as u32 + 1).to_be_bytes());
+    descripcion.extend(CONDICION_SALIDA.as_bytes());
+    // Fin de la cadena ASCII, partes Unicode y ScriptCode vacías
+    descripcion.extend([0u8; 1 + 4 + 4 + 2 + 1 + 67]);
+    let mut derechos = b"text\0\0\0\0".to_vec();
+    derechos.extend(b"Dominio publico\0");
+
+    let etiquetas: [(&[u8; 4], Vec<u8>); 9] = [
+        (b"desc", descripcion),
+        (b"cprt", derechos),
+        (b"wtpt", xyz([0.9642, 1.0, 0.8249])),
+        (b"rXYZ", xyz([0.4361, 0.2225, 0.0139])),
+        (b"gXYZ", xyz([0.3851, 0.7169, 0.0971])),
+        (b"bXYZ", xyz([0.1431, 0.0606, 0.7141])),
+        (b"rTRC", curva.clone()),
+        (b"gTRC", curva.clone()),
+        (b"bTRC", curva),
+    ];
+    let mut tabla = (etiquetas.len() as u32).to_be_bytes().to_vec();
+    let mut datos = Vec::new();
+    let inicio_datos = 128 + 4 + 12 * etiquetas.len();
+    for (firma, contenido) in etiquetas {
+        tabla.extend(firma);
+        tabla.extend(((inicio_datos + datos.len()) as u32).to_be_bytes());
+        tabla.extend((contenido.len() as u32).to_be_bytes());
+        datos.extend(&contenido);
+        // Cada etiqueta empieza alineada a 4 bytes
+        datos.resize(datos.len().div_ceil(4) * 4, 0);
+    }
+    let tamaño = inicio_datos + datos.len();
+    let mut perfil = Vec::with_capacity(tamaño);
+    perfil.extend((tamaño as u32).to_be_bytes());
+    perfil.extend([0u8; 4]);
+    perfil.extend([2, 0x10, 0, 0]);
+    perfil.extend(b"mntrRGB XYZ ");
+    for parte in [2024u16, 1, 1, 0, 0, 0] {
+        perfil.extend(parte.to_be_bytes());
+    }
+    perfil.extend(b"acsp");
+    perfil.extend([0u8; 24]);
+    // Intención perceptual e iluminante D50
+    perfil.extend([0u8; 4]);
+    for valor in [0.9642, 1.0, 0.8249] {
+        perfil.extend(((valor * 65536.0f64).round() as i32).to_be_bytes());
+    }
+    perfil.resize(128, 0);
+    perfil.extend(tabla);
+    perfil.extend(datos);
+    perfil
+}
+
+fn nombre<'a>(diccionario: &'a Dictionary, clave: &[u8]) -> Option<&'a [u8]> {
+    diccionario.get(clave).and_then(Object::as_name).ok()
+}
+
+fn resolver<'a>(documento: &'a Document, objeto: &'a Object) -> &'a Object {
+    match objeto {
+        Object::Reference(id) => documento.get_object(*id).unwrap_or(objeto),
+        _ => objeto,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lopdf::xref::{XrefEntry, XrefType};
+
+    use super::*;
+    use crate::controlador_boe::unir_pdf;
+
+    // Página con una fuente, un enlace oculto y JavaScript al abrir el documento
+    fn documento_prueba(incrustar_fuente: bool) -> Document {
+        let mut documento = Document::with_version("1.5");
+        let mut descriptor = dictionary! { "Type" => "FontDescriptor", "FontName" => "BOEFont" };
+        if incrustar_fuente {
+            let archivo = documento.add_object(Stream::new(dictionary! {}, vec![0; 64]));
+            descriptor.set("FontFile2", archivo);
+        }
+        let descriptor = documento.add_object(descriptor);
+        let fuente = documento.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "TrueType",
+            "BaseFont" => "BOEFont",
+            "FontDescriptor" => descriptor,
+        });
+        let enlace = documento.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![0.into(), 0.into(), 10.into(), 10.into()],
+            "F" => 2,
+            "A" => dictionary! { "S" => "URI", "URI" => Object::string_literal("https://boe.es") },
+        });
+        let contenido = documento.add_object(Stream::new(dictionary! {}, b"BT ET".to_vec()));
+        let id_paginas = documento.new_object_id();
+        let pagina = documento.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => id_paginas,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            "Contents" => contenido,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => fuente } },
+            "Annots" => vec![Object::Reference(enlace)],
+        });
+        documento.objects.insert(
+            id_paginas,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![Object::Reference(pagina)],
+                "Count" => 1,
+            }),
+        );
+        let javascript = documento.add_object(dictionary! {
+            "S" => "JavaScript",
+            "JS" => Object::string_literal("app.alert('hola')"),
+        });
+        let catalogo = documento.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => id_paginas,
+            "OpenAction" => javascript,
+        });
+        documento.trailer.set("Root", catalogo);
+        documento
+    }
+
+    #[test]
+    fn convierte_a_pdfa_2b() {
+        let mut documento = documento_prueba(true);
+        convertir_pdfa(&mut documento).unwrap();
+        documento.compress();
+
+        let catalogo = documento.catalog().unwrap();
+        assert!(!catalogo.has(b"OpenAction"));
+        let metadatos = catalogo
+            .get(b"Metadata")
+            .and_then(Object::as_reference)
+            .and_then(|id| documento.get_object(id))
+            .and_then(Object::as_stream)
+            .unwrap();
+        assert!(!metadatos.dict.has(b"Filter"));
+        let xmp = String::from_utf8(metadatos.content.clone()).unwrap();
+        assert!(xmp.contains("<pdfaid:part>2</pdfaid:part>"));
+        assert!(xmp.contains(&format!(
+            "<pdf:Producer>{}</pdf:Producer>",
+            metadatos::PRODUCTOR
+        )));
+        assert!(documento.trailer.has(b"ID"));
+        assert!(!documento.objects.values().any(|o| o
+            .as_dict()
+            .is_ok_and(|d| nombre(d, b"S") == Some(b"JavaScript"))));
+
+        let pagina = *documento.get_pages().values().next().unwrap();
+        let enlace = documento
+            .get_dictionary(pagina)
+            .and_then(|p| p.get(b"Annots"))
+            .and_then(Object::as_array)
+            .and_then(|a| documento.get_dictionary(a[0].as_reference().unwrap()))
+            .unwrap();
+        assert_eq!(enlace.get(b"F").and_then(Object::as_i64).unwrap(), 4);
+
+        assert_eq!(documento.version, "1.7");
+        let contenido = unir_pdf::serializar(&mut documento).unwrap();
+        assert!(contenido.starts_with(b"%PDF-1.7\n%"));
+        let segunda_linea = contenido.split(|b| *b == b'\n').nth(1).unwrap();
+        assert!(segunda_linea[1..].len() >= 4 && segunda_linea[1..].iter().all(|b| *b > 127));
+        let leido = Document::load_mem(&contenido).unwrap();
+        assert_eq!(leido.version, "1.7");
+        assert_eq!(leido.get_pages().len(), 1);
+    }
+
+    #[test]
+    fn desplaza_las_referencias_tras_el_comentario_binario() {
+        for tipo in [
+            XrefType::CrossReferenceTable,
+            XrefType::CrossReferenceStream,
+        ] {
+            let mut documento = documento_prueba(true);
+            documento.reference_table.cross_reference_type = tipo;
+            let contenido = unir_pdf::serializar(&mut documento).unwrap();
+            assert!(contenido.starts_with(b"%PDF-1.5\n%\xE2\xE3\xCF\xD3\n"));
+
+            let leido = Document::load_mem(&contenido).unwrap();
+            assert_eq!(leido.get_pages().len(), 1);
+            for (id, entrada) in &leido.reference_table.entries {
+                if let XrefEntry::Normal { offset, generation } = entrada {
+                    let cabecera = format!("{} {} obj", id, generation);
+                    assert!(contenido[*offset as usize..].starts_with(cabecera.as_bytes()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rechaza_las_fuentes_sin_incrustar() {
+        let mut documento = documento_prueba(false);
+        let error = convertir_pdfa(&mut documento).unwrap_err();
+        assert!(error.contains("la fuente BOEFont no está incrustada"));
+    }
+
+    #[test]
+    fn genera_un_perfil_icc_valido() {
+        let perfil = perfil_srgb();
+        assert_eq!(
+            u32::from_be_bytes(perfil[..4].try_into().unwrap()) as usize,
+            perfil.len()
+        );
+        assert_eq!(&perfil[12..24], b"mntrRGB XYZ ");
+        assert_eq!(&perfil[36..40], b"acsp");
+        assert_eq!(perfil.len() % 4, 0);
+    }
+}
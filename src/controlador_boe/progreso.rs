@@ -0,0 +1,59 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::Serialize;
+
+use super::descargar::EnlacePDF;
+
+static PROGRESO_JSON: AtomicBool = AtomicBool::new(false);
+
+// Un objeto JSON por línea en la salida estándar, pensado para que otros procesos sigan
+// la ejecución
+#[derive(Serialize)]
+#[serde(tag = "evento", rename_all = "snake_case")]
+pub enum EventoProgreso<'a> {
+    EnlaceEncontrado {
+        enlace: &'a EnlacePDF,
+    },
+    DescargaCompletada {
+        indice: usize,
+        total: usize,
+        url: &'a str,
+        bytes: usize,
+    },
+    DescargaFallida {
+        indice: usize,
+        total: usize,
+        url: &'a str,
+        error: &'a str,
+    },
+    DocumentoDescartado {
+        enlace: &'a EnlacePDF,
+        error: &'a str,
+    },
+    UnionIniciada {
+        documentos: usize,
+    },
+    UnionCompletada {
+        paginas: usize,
+        ruta: String,
+        bytes_ahorrados: usize,
+    },
+    Finalizado {
+        correcto: bool,
+        error: Option<&'a str>,
+    },
+}
+
+pub fn activar_progreso_json() {
+    PROGRESO_JSON.store(true, Ordering::Relaxed);
+}
+
+pub fn emitir(evento: EventoProgreso) {
+    if !PROGRESO_JSON.load(Ordering::Relaxed) {
+        return;
+    }
+    match serde_json::to_string(&evento) {
+        Err(error) => tracing::warn!("no se ha podido serializar el evento de progreso {}", error),
+        Ok(linea) => println!("{}", linea),
+    }
+}
@@ -0,0 +1,31 @@
+use std::io::{self, IsTerminal};
+
+use tracing::Level;
+use tracing_subscriber::{filter::Targets, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+
+#[derive(Clone, Copy)]
+pub enum NivelRegistro {
+    Silencioso,
+    Normal,
+    Detallado,
+}
+
+pub fn iniciar_registro(nivel: NivelRegistro) {
+    let nivel = match nivel {
+        NivelRegistro::Silencioso => Level::ERROR,
+        NivelRegistro::Normal => Level::INFO,
+        NivelRegistro::Detallado => Level::DEBUG,
+    };
+    // Solo los eventos propios, el modo detallado de reqwest o hyper inundaría la salida
+    let filtro = Targets::new().with_target(env!("CARGO_CRATE_NAME"), nivel);
+    // La salida estándar queda libre para los eventos de progreso en JSON y los colores
+    // solo se usan en una terminal para no ensuciar los registros de CI
+    let capa = fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .with_target(false);
+    tracing_subscriber::registry()
+        .with(filtro)
+        .with(capa)
+        .init();
+}
@@ -0,0 +1,309 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use tokio::{fs, net::TcpListener, sync::Mutex};
+use tracing::{info, warn};
+
+use super::{
+    configuracion::Configuracion,
+    descargar::{self, InformeDescargas},
+    dividir::Division,
+    validar_fecha::{self, FechaBoe},
+};
+
+// Una fecha que no se ha podido generar (domingo, fecha futura, boe.es caído) no se vuelve a
+// pedir hasta que pase este tiempo, para que las peticiones no se conviertan en descargas
+const ESPERA_TRAS_FALLO: Duration = Duration::from_secs(600);
+
+struct EstadoServidor {
+    configuracion: Configuracion,
+    // Un cerrojo por fecha para que dos peticiones simultáneas no generen dos veces el mismo
+    // boletín. Se quita al terminar la generación
+    generaciones: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    // Último fallo de cada fecha, con su error
+    fallos: Mutex<HashMap<String, (Instant, String)>>,
+}
+
+pub async fn servir_boletines(configuracion: &Configuracion) -> Result<(), String> {
+    let direccion = &configuracion.direccion_servidor;
+    let rutas = crear_rutas(configuracion);
+    let escucha = match TcpListener::bind(direccion).await {
+        Err(error) => {
+            let mensaje_error = format!("no se ha podido escuchar en {} {}", direccion, error);
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    info!("servidor escuchando en http://{}", direccion);
+    match axum::serve(escucha, rutas).await {
+        Err(error) => Err(format!("el servidor se ha detenido por un error {}", error)),
+        Ok(_) => Ok(()),
+    }
+}
+
+pub fn crear_rutas(configuracion: &Configuracion) -> Router {
+    Router::new()
+        .route("/boe/{archivo}", get(servir_pdf))
+        .route("/boe/{fecha}/informe.json", get(servir_informe))
+        .route("/boe/{fecha}/documentos", get(servir_documentos))
+        .with_state(nuevo_estado(configuracion))
+}
+
+fn nuevo_estado(configuracion: &Configuracion) -> Arc<EstadoServidor> {
+    // Las rutas sirven siempre el boletín completo, aunque la configuración pida dividirlo
+    let mut configuracion = configuracion.clone();
+    configuracion.division = Division::Ninguna;
+    configuracion.volumen_maximo_mb = None;
+    configuracion.volumen_maximo_paginas = None;
+    Arc::new(EstadoServidor {
+        configuracion,
+        generaciones: Mutex::new(HashMap::new()),
+        fallos: Mutex::new(HashMap::new()),
+    })
+}
+
+async fn servir_pdf(
+    State(estado): State<Arc<EstadoServidor>>,
+    Path(archivo): Path<String>,
+) -> Response {
+    let fecha = match archivo.strip_suffix(".pdf") {
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                "se esperaba una ruta /boe/AÑO-MM-DD.pdf",
+            )
+                .into_response();
+        }
+        Some(fecha) => fecha,
+    };
+    match asegurar_boletin(&estado, fecha).await {
+        Err(respuesta) => respuesta,
+        Ok(fecha_boe) => {
+            let ruta_pdf = descargar::ruta_salida(&estado.configuracion, &fecha_boe, ".pdf");
+            servir_archivo(ruta_pdf, "application/pdf").await
+        }
+    }
+}
+
+async fn servir_informe(
+    State(estado): State<Arc<EstadoServidor>>,
+    Path(fecha): Path<String>,
+) -> Response {
+    match asegurar_boletin(&estado, &fecha).await {
+        Err(respuesta) => respuesta,
+        Ok(fecha_boe) => {
+            let ruta_informe =
+                descargar::ruta_salida(&estado.configuracion, &fecha_boe, "_informe.json");
+            servir_archivo(ruta_informe, "application/json").await
+        }
+    }
+}
+
+async fn servir_documentos(
+    State(estado): State<Arc<EstadoServidor>>,
+    Path(fecha): Path<String>,
+) -> Response {
+    let fecha_boe = match asegurar_boletin(&estado, &fecha).await {
+        Err(respuesta) => return respuesta,
+        Ok(ok) => ok,
+    };
+    let ruta_informe = descargar::ruta_salida(&estado.configuracion, &fecha_boe, "_informe.json");
+    let contenido = match fs::read(&ruta_informe).await {
+        Err(error) => {
+            let mensaje_error = format!("no se ha podido leer el informe {}", error);
+            return (StatusCode::INTERNAL_SERVER_ERROR, mensaje_error).into_response();
+        }
+        Ok(ok) => ok,
+    };
+    match serde_json::from_slice::<InformeDescargas>(&contenido) {
+        Err(error) => {
+            let mensaje_error = format!("el informe guardado no es válido {}", error);
+            (StatusCode::INTERNAL_SERVER_ERROR, mensaje_error).into_response()
+        }
+        Ok(informe) => Json(informe.documentos).into_response(),
+    }
+}
+
+// Devuelve la fecha validada una vez que el boletín está disponible en el directorio de salida,
+// generándolo si todavía no existe
+async fn asegurar_boletin(estado: &EstadoServidor, fecha: &str) -> Result<FechaBoe, Response> {
+    // En las rutas se usa AÑO-MM-DD, el resto del programa trabaja con DD-MM-AÑO
+    let partes_fecha = fecha.split('-').collect::<Vec<&str>>();
+    if partes_fecha.len() != 3 {
+        let mensaje_error = format!("la fecha {} no es válida, se esperaba AÑO-MM-DD", fecha);
+        return Err((StatusCode::BAD_REQUEST, mensaje_error).into_response());
+    }
+    let fecha_boe_texto = format!(
+        "{}-{}-{}",
+        partes_fecha[2], partes_fecha[1], partes_fecha[0]
+    );
+    let fecha_boe = match validar_fecha::comprobar_formato(&fecha_boe_texto) {
+        Err(error) => return Err((StatusCode::BAD_REQUEST, error).into_response()),
+        Ok(ok) => ok,
+    };
+    if boletin_generado(&estado.configuracion, &fecha_boe) {
+        return Ok(fecha_boe);
+    }
+    if let Some(respuesta) = fallo_reciente(estado, &fecha_boe_texto).await {
+        return Err(respuesta);
+    }
+    let cerrojo = estado
+        .generaciones
+        .lock()
+        .await
+        .entry(fecha_boe_texto.to_owned())
+        .or_default()
+        .clone();
+    let _generacion = cerrojo.lock().await;
+    // Otra petición ha podido generarlo, o fallar, mientras se esperaba el cerrojo
+    if boletin_generado(&estado.configuracion, &fecha_boe) {
+        return Ok(fecha_boe);
+    }
+    if let Some(respuesta) = fallo_reciente(estado, &fecha_boe_texto).await {
+        return Err(respuesta);
+    }
+    info!("generando bajo demanda el boletín del {}", fecha_boe_texto);
+    // En una tarea propia para que la generación termine aunque el cliente corte la conexión
+    let configuracion = estado.configuracion.clone();
+    let fecha_generacion = fecha_boe_texto.to_owned();
+    let resultado = tokio::spawn(async move {
+        descargar::crear_gestor_descargas(&fecha_generacion, &configuracion).await
+    })
+    .await
+    .unwrap_or_else(|error| {
+        Err(format!(
+            "la generación del boletín se ha interrumpido {}",
+            error
+        ))
+    });
+    {
+        let mut generaciones = estado.generaciones.lock().await;
+        if generaciones
+            .get(&fecha_boe_texto)
+            .is_some_and(|c| Arc::ptr_eq(c, &cerrojo))
+        {
+            generaciones.remove(&fecha_boe_texto);
+        }
+    }
+    match resultado {
+        Err(error) => {
+            warn!(
+                "no se ha podido generar el boletín del {} {}",
+                fecha_boe_texto, error
+            );
+            let mut fallos = estado.fallos.lock().await;
+            fallos.retain(|_, (momento, _)| momento.elapsed() < ESPERA_TRAS_FALLO);
+            fallos.insert(fecha_boe_texto, (Instant::now(), error.to_owned()));
+            Err((StatusCode::BAD_GATEWAY, error).into_response())
+        }
+        Ok(_) => Ok(fecha_boe),
+    }
+}
+
+// Respuesta con el error guardado si la fecha ha fallado hace menos de ESPERA_TRAS_FALLO
+async fn fallo_reciente(estado: &EstadoServidor, fecha: &str) -> Option<Response> {
+    let mut fallos = estado.fallos.lock().await;
+    let (momento, error) = fallos.get(fecha)?;
+    let transcurrido = momento.elapsed();
+    if transcurrido >= ESPERA_TRAS_FALLO {
+        fallos.remove(fecha);
+        return None;
+    }
+    let mensaje_error = format!(
+        "{} (no se volverá a intentar hasta dentro de {} s)",
+        error,
+        (ESPERA_TRAS_FALLO - transcurrido).as_secs()
+    );
+    Some((StatusCode::BAD_GATEWAY, mensaje_error).into_response())
+}
+
+// El informe se escribe antes de descargar los documentos, así que solo el PDF, que se guarda de
+// una vez al final, indica que el boletín está completo
+fn boletin_generado(configuracion: &Configuracion, fecha: &FechaBoe) -> bool {
+    descargar::ruta_salida(configuracion, fecha, ".pdf").exists()
+}
+
+async fn servir_archivo(ruta: PathBuf, tipo_contenido: &'static str) -> Response {
+    match fs::read(&ruta).await {
+        Err(error) => {
+            let mensaje_error = format!("no se ha podido leer {} {}", ruta.display(), error);
+            (StatusCode::INTERNAL_SERVER_ERROR, mensaje_error).into_response()
+        }
+        Ok(contenido) => ([(header::CONTENT_TYPE, tipo_contenido)], contenido).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn estado_pruebas(salida: &TempDir) -> Arc<EstadoServidor> {
+        // Ningún servidor escucha aquí, así que cualquier generación fallaría
+        let configuracion = Configuracion {
+            url_base: String::from("http://127.0.0.1:9"),
+            directorio_salida: salida.path().to_path_buf(),
+            ..Configuracion::default()
+        };
+        nuevo_estado(&configuracion)
+    }
+
+    #[tokio::test]
+    async fn rechaza_las_rutas_y_fechas_no_validas() {
+        let salida = TempDir::new().unwrap();
+        let estado = estado_pruebas(&salida);
+        let respuesta =
+            servir_pdf(State(estado.clone()), Path(String::from("2024-12-07.txt"))).await;
+        assert_eq!(respuesta.status(), StatusCode::NOT_FOUND);
+        let respuesta = servir_pdf(State(estado.clone()), Path(String::from("07-12.pdf"))).await;
+        assert_eq!(respuesta.status(), StatusCode::BAD_REQUEST);
+        let respuesta =
+            servir_informe(State(estado.clone()), Path(String::from("2024-13-45"))).await;
+        assert_eq!(respuesta.status(), StatusCode::BAD_REQUEST);
+        // Ninguna ha intentado generar nada
+        assert!(estado.fallos.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sirve_el_pdf_ya_generado_sin_esperar_al_informe() {
+        let salida = TempDir::new().unwrap();
+        let estado = estado_pruebas(&salida);
+        let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+        let ruta_pdf = descargar::ruta_salida(&estado.configuracion, &fecha, ".pdf");
+        std::fs::write(&ruta_pdf, b"%PDF-1.7").unwrap();
+        let respuesta = servir_pdf(State(estado), Path(String::from("2024-12-07.pdf"))).await;
+        assert_eq!(respuesta.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn olvida_los_fallos_pasada_la_espera() {
+        let salida = TempDir::new().unwrap();
+        let estado = estado_pruebas(&salida);
+        let antiguo = Instant::now().checked_sub(ESPERA_TRAS_FALLO).unwrap();
+        estado
+            .fallos
+            .lock()
+            .await
+            .insert(String::from("07-12-2024"), (antiguo, String::from("caído")));
+        estado.fallos.lock().await.insert(
+            String::from("09-12-2024"),
+            (Instant::now(), String::from("caído")),
+        );
+
+        assert!(fallo_reciente(&estado, "07-12-2024").await.is_none());
+        assert!(!estado.fallos.lock().await.contains_key("07-12-2024"));
+        let respuesta = fallo_reciente(&estado, "09-12-2024").await.unwrap();
+        assert_eq!(respuesta.status(), StatusCode::BAD_GATEWAY);
+    }
+}
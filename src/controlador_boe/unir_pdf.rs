@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+
+use lopdf::{Bookmark, Document, Object, ObjectId};
+
+use super::{
+    cifrar::{self, Cifrado},
+    configuracion::Configuracion,
+    correcciones::{self, MarcadorDocumento},
+    descargar::EnlacePDF,
+    estampar, firmar, marcadores, metadatos,
+    optimizar::{self, ResultadoOptimizacion},
+    pdfa,
+    validar_fecha::FechaBoe,
+};
+
+// Comentario tras la cabecera con cuatro bytes mayores de 127 para marcar el PDF como binario
+const COMENTARIO_BINARIO: &[u8] = b"%\xE2\xE3\xCF\xD3\n";
+
+// Documento ya leído junto a su enlace y el tamaño con el que se descargó
+pub struct PdfCargado {
+    pub enlace: EnlacePDF,
+    pub documento: Document,
+    pub tamaño: usize,
+}
+
+pub struct OpcionesUnion {
+    pub optimizar_recursos: bool,
+    pub pdfa: bool,
+    // Pie con el identificador y la página en cada página, y etiquetas de página por documento
+    pub estampar: bool,
+    // Fecha del boletín para los metadatos, con el formato 07-12-2024
+    pub fecha: String,
+    pub cifrado: Option<Cifrado>,
+}
+
+impl OpcionesUnion {
+    pub fn desde_configuracion(configuracion: &Configuracion, fecha: &FechaBoe) -> OpcionesUnion {
+        OpcionesUnion {
+            optimizar_recursos: configuracion.optimizar_recursos,
+            pdfa: configuracion.pdfa,
+            estampar: configuracion.estampar_paginas,
+            fecha: format!("{:02}-{:02}-{}", fecha.dia, fecha.mes, fecha.año),
+            cifrado: Cifrado::desde_configuracion(configuracion),
+        }
+    }
+}
+
+pub struct PdfUnido {
+    pub documento: Document,
+    pub optimizacion: Option<ResultadoOptimizacion>,
+}
+
+// Une los documentos en el orden recibido, con un marcador por documento del que cuelga su
+// índice original. Se usa tanto para el boletín completo como para cada parte al dividirlo
+pub fn unir_documentos(
+    documents: Vec<(EnlacePDF, Document)>,
+    opciones: &OpcionesUnion,
+) -> Result<PdfUnido, String> {
+    // Define a starting max_id (will be used as start index for object_ids)
+    let mut max_id = 1;
+    // Collect all Documents Objects grouped by a map
+    let mut documents_pages = BTreeMap::new();
+    let mut documents_objects = BTreeMap::new();
+    let mut document = Document::with_version("1.5");
+    let mut enlaces = Vec::new();
+    let total_paginas = documents.iter().map(|(_, d)| d.get_pages().len()).sum();
+    let mut etiquetas = Vec::new();
+    let mut marcadores_documentos = Vec::new();
+
+    for (enlace, mut doc) in documents {
+        firmar::quitar_firmas(&mut doc);
+        if opciones.estampar {
+            let paginas_previas = documents_pages.len();
+            estampar::estampar_paginas(&mut doc, &enlace, paginas_previas + 1, total_paginas);
+            etiquetas.push((paginas_previas, estampar::identificador(&enlace)));
+        }
+        doc.renumber_objects_with(max_id);
+        // Tras renumerar, los destinos de marcadores y enlaces ya son los del documento unido
+        marcadores::resolver_enlaces_nombrados(&mut doc);
+        let marcadores_origen = marcadores::leer_marcadores(&doc);
+
+        max_id = doc.max_id + 1;
+
+        let pages = doc.get_pages();
+        // Un marcador por disposición con el índice del documento original colgando de él
+        if let Some(first_page) = pages.values().next() {
+            let bookmark = Bookmark::new(enlace.titulo.to_owned(), [0.0, 0.0, 1.0], 0, *first_page);
+            let id = document.add_bookmark(bookmark, None);
+            marcadores::añadir_marcadores(&mut document, &marcadores_origen, id);
+            marcadores_documentos.push(MarcadorDocumento {
+                identificador: estampar::identificador(&enlace),
+                titulo: enlace.titulo.to_owned(),
+                corrige: enlace.corrige.to_owned(),
+                marcador: id,
+                pagina: *first_page,
+            });
+        }
+        documents_pages.extend(
+            pages
+                .into_values()
+                .map(|object_id| (object_id, doc.get_object(object_id).unwrap().to_owned()))
+                .collect::<BTreeMap<ObjectId, Object>>(),
+        );
+        documents_objects.extend(doc.objects);
+        enlaces.push(enlace);
+    }
+    correcciones::añadir_referencias_cruzadas(&mut document, &marcadores_documentos);
+
+    // Catalog and Pages are mandatory
+    let mut catalog_object: Option<(ObjectId, Object)> = None;
+    let mut pages_object: Option<(ObjectId, Object)> = None;
+
+    // Process all objects except "Page" type
+    for (object_id, object) in documents_objects.iter() {
+        // We have to ignore "Page" (as are processed later), "Outlines" and "Outline" objects
+        // All other objects should be collected and inserted into the main Document
+        match object.type_name().unwrap_or("") {
+            "Catalog" => {
+                // Collect a first "Catalog" object and use it for the future "Pages"
+                catalog_object = Some((
+                    if let Some((id, _)) = catalog_object {
+                        id
+                    } else {
+                        *object_id
+                    },
+                    object.clone(),
+                ));
+            }
+            "Pages" => {
+                // Collect and update a first "Pages" object and use it for the future "Catalog"
+                // We have also to merge all dictionaries of the old and the new "Pages" object
+                if let Ok(dictionary) = object.as_dict() {
+                    let mut dictionary = dictionary.clone();
+                    if let Some((_, ref object)) = pages_object {
+                        if let Ok(old_dictionary) = object.as_dict() {
+                            dictionary.extend(old_dictionary);
+                        }
+                    }
+
+                    pages_object = Some((
+                        if let Some((id, _)) = pages_object {
+                            id
+                        } else {
+                            *object_id
+                        },
+                        Object::Dictionary(dictionary),
+                    ));
+                }
+            }
+            "Page" => {}     // Ignored, processed later and separately
+            "Outlines" => {} // Ignored, rebuilt from the bookmarks
+            "Outline" => {}  // Ignored, rebuilt from the bookmarks
+            _ => {
+                document.objects.insert(*object_id, object.clone());
+            }
+        }
+    }
+
+    // If no "Pages" found abort
+    if pages_object.is_none() {
+        return Err(String::from(
+            "ha fallado el proceso de unión de los PDF. Pages root not found.",
+        ));
+    }
+
+    // Iter over all "Page" and collect with the parent "Pages" created before
+    for (object_id, object) in documents_pages.iter() {
+        if let Ok(dictionary) = object.as_dict() {
+            let mut dictionary = dictionary.clone();
+            dictionary.set("Parent", pages_object.as_ref().unwrap().0);
+
+            document
+                .objects
+                .insert(*object_id, Object::Dictionary(dictionary));
+        }
+    }
+
+    // If no "Catalog" found abort
+    if catalog_object.is_none() {
+        return Err(String::from(
+            "ha fallado el proceso de unión de los PDF. Catalog root not found.",
+        ));
+    }
+
+    let catalog_object = catalog_object.unwrap();
+    let pages_object = pages_object.unwrap();
+
+    // Build a new "Pages" with updated fields
+    if let Ok(dictionary) = pages_object.1.as_dict() {
+        let mut dictionary = dictionary.clone();
+
+        // Set new pages count
+        dictionary.set("Count", documents_pages.len() as u32);
+
+        // Set new "Kids" list (collected from documents pages) for "Pages"
+        dictionary.set(
+            "Kids",
+            documents_pages
+                .into_keys()
+                .map(Object::Reference)
+                .collect::<Vec<_>>(),
+        );
+
+        document
+            .objects
+            .insert(pages_object.0, Object::Dictionary(dictionary));
+    }
+
+    // Build a new "Catalog" with updated fields
+    if let Ok(dictionary) = catalog_object.1.as_dict() {
+        let mut dictionary = dictionary.clone();
+        dictionary.set("Pages", pages_object.0);
+        dictionary.remove(b"Outlines"); // Rebuilt from the bookmarks below
+
+        document
+            .objects
+            .insert(catalog_object.0, Object::Dictionary(dictionary));
+    }
+
+    document.trailer.set("Root", catalog_object.0);
+
+    let mut optimizacion = None;
+    if opciones.optimizar_recursos {
+        optimizacion = Some(optimizar::compartir_recursos(&mut document));
+        // Quedan sin referencias los objetos que solo usaban los duplicados
+        document.prune_objects();
+    }
+
+    // Update the max internal ID as wasn't updated before due to direct objects insertion
+    document.max_id = document.objects.len() as u32;
+
+    // Reorder all new Document objects
+    document.renumber_objects();
+
+    //Set any Bookmarks to the First child if they are not set to a page
+    document.adjust_zero_pages();
+
+    //Set all bookmarks to the PDF Object tree then set the Outlines to the Bookmark content map.
+    if let Some(n) = document.build_outline() {
+        marcadores::codificar_titulos(&mut document, n);
+        // Renumbering may have moved the catalog, the trailer always points to the current one
+        if let Ok(dict) = document.catalog_mut() {
+            dict.set("Outlines", Object::Reference(n));
+        }
+    }
+
+    if opciones.estampar {
+        estampar::añadir_etiquetas(&mut document, &etiquetas);
+    }
+    metadatos::rellenar_info(&mut document, &opciones.fecha, &enlaces);
+    if opciones.pdfa {
+        pdfa::convertir_pdfa(&mut document)?;
+    } else {
+        metadatos::escribir_xmp(&mut document, false)?;
+    }
+
+    document.compress();
+    // Después de comprimir, los datos cifrados ya no se pueden comprimir
+    if let Some(cifrado) = &opciones.cifrado {
+        cifrar::cifrar_documento(&mut document, cifrado);
+    }
+    Ok(PdfUnido {
+        documento: document,
+        optimizacion,
+    })
+}
+
+pub fn serializar(documento: &mut Document) -> Result<Vec<u8>, String> {
+    let mut contenido = Vec::new();
+    if let Err(error) = documento.save_to(&mut contenido) {
+        let mensaje_error = format!("no se ha podido generar el PDF unido {}", error);
+        return Err(mensaje_error);
+    }
+    match añadir_comentario_binario(&contenido) {
+        None => Err(String::from(
+            "no se ha podido añadir el comentario binario a la cabecera del PDF unido",
+        )),
+        Some(contenido) => Ok(contenido),
+    }
+}
+
+// lopdf no escribe tras la cabecera el comentario con bytes mayores de 127 que pide PDF/A
+// (ISO 19005-2, 6.1.2), así que se inserta después de guardar y se desplazan los
+// desplazamientos de la tabla o del flujo de referencias que lopdf ya había calculado
+fn añadir_comentario_binario(contenido: &[u8]) -> Option<Vec<u8>> {
+    let fin_cabecera = contenido.iter().position(|byte| *byte == b'\n')? + 1;
+    let posicion_startxref = contenido.windows(9).rposition(|v| v == b"startxref")?;
+    let inicio_referencias = firmar::ultima_referencia(contenido)?;
+    let desplazamiento = COMENTARIO_BINARIO.len();
+
+    let mut resultado = Vec::with_capacity(contenido.len() + desplazamiento);
+    resultado.extend_from_slice(&contenido[..fin_cabecera]);
+    resultado.extend_from_slice(COMENTARIO_BINARIO);
+    resultado.extend_from_slice(&contenido[fin_cabecera..posicion_startxref]);
+    let referencias = &mut resultado[inicio_referencias + desplazamiento..];
+    if referencias.starts_with(b"xref") {
+        desplazar_tabla(referencias, desplazamiento)?;
+    } else {
+        desplazar_flujo(referencias, desplazamiento)?;
+    }
+    resultado.extend_from_slice(
+        format!("startxref\n{}\n%%EOF", inicio_referencias + desplazamiento).as_bytes(),
+    );
+    Some(resultado)
+}
+
+// Entradas de 20 bytes con el desplazamiento en los diez primeros y una n si están en uso
+fn desplazar_tabla(tabla: &mut [u8], desplazamiento: usize) -> Option<()> {
+    let mut posicion = tabla.iter().position(|byte| *byte == b'\n')? + 1;
+    while !tabla[posicion..].starts_with(b"trailer") {
+        let fin_linea = posicion + tabla[posicion..].iter().position(|byte| *byte == b'\n')?;
+        let subseccion = std::str::from_utf8(&tabla[posicion..fin_linea]).ok()?;
+        let cantidad: usize = subseccion.split_whitespace().nth(1)?.parse().ok()?;
+        posicion = fin_linea + 1;
+        for _ in 0..cantidad {
+            let entrada = tabla.get_mut(posicion..posicion + 20)?;
+            if entrada[17] == b'n' {
+                let valor: usize = std::str::from_utf8(&entrada[..10]).ok()?.parse().ok()?;
+                let nuevo = format!("{:010}", valor + desplazamiento);
+                entrada[..10].copy_from_slice(nuevo.as_bytes());
+            }
+            posicion += 20;
+        }
+    }
+    Some(())
+}
+
+// lopdf escribe el flujo sin comprimir y con anchos [1 4 2], así que las entradas de tipo 1
+// llevan el desplazamiento en los cuatro bytes siguientes al tipo
+fn desplazar_flujo(objeto: &mut [u8], desplazamiento: usize) -> Option<()> {
+    let inicio = objeto.windows(7).position(|v| v == b"stream\n")? + 7;
+    let fin = objeto.windows(10).rposition(|v| v == b"\nendstream")?;
+    let entradas = objeto.get_mut(inicio..fin)?;
+    if entradas.len() % 7 != 0 {
+        return None;
+    }
+    for entrada in entradas.chunks_exact_mut(7) {
+        if entrada[0] == 1 {
+            let valor = u32::from_be_bytes(entrada[1..5].try_into().ok()?);
+            let nuevo = valor.checked_add(u32::try_from(desplazamiento).ok()?)?;
+            entrada[1..5].copy_from_slice(&nuevo.to_be_bytes());
+        }
+    }
+    Some(())
+}
@@ -1,17 +1,16 @@
-use std::process::exit;
-
 pub struct FechaBoe {
     pub dia: usize,
     pub mes: usize,
     pub año: usize,
 }
 
-pub fn comprobar_formato(fecha: &str) -> FechaBoe {
+pub fn comprobar_formato(fecha: &str) -> Result<FechaBoe, String> {
     // DD-MM-AÑO
     let partes_fecha = fecha.trim().split("-").collect::<Vec<&str>>();
     if partes_fecha.len() != 3 {
-        eprintln!("el formato de la fecha no es válido, se esperaba DD-MM-AÑO");
-        exit(1);
+        return Err(String::from(
+            "el formato de la fecha no es válido, se esperaba DD-MM-AÑO",
+        ));
     }
     let mut fecha_boe = FechaBoe {
         dia: 0,
@@ -21,33 +20,29 @@ pub fn comprobar_formato(fecha: &str) -> FechaBoe {
     for (indice, valor) in partes_fecha.iter().enumerate() {
         let valor_parseado = match valor.parse::<usize>() {
             Err(_) => {
-                eprintln!(
+                return Err(format!(
                     "el formato de la fecha no es válido, la parte {} no corresponde a una cifra",
                     valor
-                );
-                exit(1);
+                ));
             }
             Ok(ok) => ok,
         };
         match indice {
             0 => {
                 if valor_parseado > 31 {
-                    eprintln!("el formato de la fecha no es válido, la cifra que corresponde al día {} no puede ser mayor que 31", valor_parseado);
-                    exit(1);
+                    return Err(format!("el formato de la fecha no es válido, la cifra que corresponde al día {} no puede ser mayor que 31", valor_parseado));
                 }
                 fecha_boe.dia = valor_parseado;
             }
             1 => {
                 if valor_parseado > 12 {
-                    eprintln!("el formato de la fecha no es válido, la cifra que corresponde al mes {} no puede ser mayor que 12", valor_parseado);
-                    exit(1);
+                    return Err(format!("el formato de la fecha no es válido, la cifra que corresponde al mes {} no puede ser mayor que 12", valor_parseado));
                 }
                 fecha_boe.mes = valor_parseado;
             }
             2 => {
-                if valor_parseado > 2050 || valor_parseado < 1950 {
-                    eprintln!("el formato de la fecha no es válido, la cifra que corresponde al año {} debe ser un valor entre 1950 y 2050", valor_parseado);
-                    exit(1);
+                if !(1950..=2050).contains(&valor_parseado) {
+                    return Err(format!("el formato de la fecha no es válido, la cifra que corresponde al año {} debe ser un valor entre 1950 y 2050", valor_parseado));
                 }
                 fecha_boe.año = valor_parseado;
             }
@@ -55,5 +50,31 @@ pub fn comprobar_formato(fecha: &str) -> FechaBoe {
         }
     }
 
-    fecha_boe
+    Ok(fecha_boe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acepta_fecha_valida() {
+        let fecha = comprobar_formato(" 07-12-2024 ").unwrap();
+        assert_eq!((fecha.dia, fecha.mes, fecha.año), (7, 12, 2024));
+    }
+
+    #[test]
+    fn rechaza_formato_incorrecto() {
+        assert!(comprobar_formato("2024/12/07").is_err());
+        assert!(comprobar_formato("07-12").is_err());
+        assert!(comprobar_formato("07-dic-2024").is_err());
+    }
+
+    #[test]
+    fn rechaza_valores_fuera_de_rango() {
+        assert!(comprobar_formato("32-12-2024").is_err());
+        assert!(comprobar_formato("07-13-2024").is_err());
+        assert!(comprobar_formato("07-12-1949").is_err());
+        assert!(comprobar_formato("07-12-2051").is_err());
+    }
 }
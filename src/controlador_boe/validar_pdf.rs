@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+
+use lopdf::Document;
+
+// La especificación permite que la cabecera no esté exactamente al principio del archivo
+const LIMITE_CABECERA: usize = 1024;
+// Número de objeto más alto que admite la especificación (y lopdf)
+const NUMERO_OBJETO_MAXIMO: u32 = 8_388_607;
+
+// Un servidor puede responder 200 con una página de error, así que no basta con el status code
+pub fn comprobar_pdf(tipo_contenido: Option<&str>, bytes: &[u8]) -> Result<(), String> {
+    if let Some(tipo) = tipo_contenido {
+        let tipo = tipo.to_ascii_lowercase();
+        if tipo.starts_with("text/") {
+            return Err(format!("se ha recibido {} en lugar de un PDF", tipo));
+        }
+    }
+    let inicio = &bytes[..bytes.len().min(LIMITE_CABECERA)];
+    if !inicio.windows(5).any(|v| v == b"%PDF-") {
+        return Err(String::from(
+            "el contenido recibido no empieza por la cabecera %PDF-",
+        ));
+    }
+    Ok(())
+}
+
+pub fn cargar_documento(bytes: &[u8], reparar: bool) -> Result<Document, String> {
+    let error = match Document::load_mem(bytes) {
+        Err(error) => error,
+        Ok(ok) => return Ok(ok),
+    };
+    if !reparar {
+        return Err(format!("no se ha podido leer el PDF {}", error));
+    }
+    let reparado = match reconstruir_referencias(bytes) {
+        None => {
+            let mensaje_error = format!(
+                "no se ha podido leer el PDF {} y no se han encontrado objetos para repararlo",
+                error
+            );
+            return Err(mensaje_error);
+        }
+        Some(ok) => ok,
+    };
+    match Document::load_mem(&reparado) {
+        Err(error_reparado) => Err(format!(
+            "no se ha podido leer el PDF {} ni tras intentar repararlo {}",
+            error, error_reparado
+        )),
+        Ok(ok) => Ok(ok),
+    }
+}
+
+// Recorre el archivo buscando las cabeceras "N G obj" y añade al final una tabla de referencias
+// y un trailer nuevos. Sirve para descargas cortadas o tablas con desplazamientos incorrectos,
+// no para objetos guardados dentro de flujos de objetos
+pub fn reconstruir_referencias(bytes: &[u8]) -> Option<Vec<u8>> {
+    // Si un objeto aparece varias veces vale la última definición, como en las actualizaciones
+    let mut objetos: BTreeMap<u32, (u16, usize)> = BTreeMap::new();
+    let mut raiz = None;
+    let mut posicion = 0;
+    while posicion < bytes.len() {
+        let inicio_linea =
+            posicion == 0 || bytes[posicion - 1] == b'\n' || bytes[posicion - 1] == b'\r';
+        if inicio_linea {
+            if let Some((numero, generacion, fin_cabecera)) = leer_cabecera_objeto(bytes, posicion)
+            {
+                // Un objeto cortado a medias no se puede recuperar
+                let fin_objeto = match buscar(bytes, fin_cabecera, b"endobj") {
+                    None => break,
+                    Some(ok) => ok,
+                };
+                if es_catalogo(&bytes[fin_cabecera..fin_objeto]) {
+                    raiz = Some((numero, generacion));
+                }
+                objetos.insert(numero, (generacion, posicion));
+                posicion = fin_objeto;
+                continue;
+            }
+        }
+        posicion += 1;
+    }
+    let (numero_raiz, generacion_raiz) = raiz?;
+    let tamaño = objetos.keys().last()?.checked_add(1)?;
+
+    let mut reparado = bytes.to_vec();
+    if !reparado.ends_with(b"\n") {
+        reparado.push(b'\n');
+    }
+    let inicio_tabla = reparado.len();
+    // Solo una subsección por cada tramo de objetos seguidos, un número suelto muy alto no
+    // obliga a escribir una entrada por cada uno de los que faltan
+    reparado.extend_from_slice(b"xref\n0 1\n0000000000 65535 f\r\n");
+    let numeros = objetos
+        .keys()
+        .copied()
+        .filter(|n| *n > 0)
+        .collect::<Vec<u32>>();
+    for tramo in numeros.chunk_by(|a, b| a.checked_add(1) == Some(*b)) {
+        reparado.extend_from_slice(format!("{} {}\n", tramo[0], tramo.len()).as_bytes());
+        for numero in tramo {
+            // Cada entrada de la tabla ocupa exactamente 20 bytes
+            let (generacion, desplazamiento) = objetos[numero];
+            let entrada = format!("{:010} {:05} n\r\n", desplazamiento, generacion);
+            reparado.extend_from_slice(entrada.as_bytes());
+        }
+    }
+    let trailer = format!(
+        "trailer\n<< /Size {} /Root {} {} R >>\nstartxref\n{}\n%%EOF\n",
+        tamaño, numero_raiz, generacion_raiz, inicio_tabla
+    );
+    reparado.extend_from_slice(trailer.as_bytes());
+    Some(reparado)
+}
+
+// Devuelve el número, la generación y la posición siguiente a "obj"
+fn leer_cabecera_objeto(bytes: &[u8], posicion: usize) -> Option<(u32, u16, usize)> {
+    let (numero, posicion) = leer_numero(bytes, posicion)?;
+    let posicion = saltar_espacios(bytes, posicion)?;
+    let (generacion, posicion) = leer_numero(bytes, posicion)?;
+    let posicion = saltar_espacios(bytes, posicion)?;
+    if !bytes[posicion..].starts_with(b"obj") {
+        return None;
+    }
+    // Un número fuera de rango no es una cabecera válida, se ignora
+    let numero = u32::try_from(numero)
+        .ok()
+        .filter(|n| *n <= NUMERO_OBJETO_MAXIMO)?;
+    let generacion = u16::try_from(generacion).ok()?;
+    Some((numero, generacion, posicion + 3))
+}
+
+fn leer_numero(bytes: &[u8], posicion: usize) -> Option<(u64, usize)> {
+    let mut fin = posicion;
+    while fin < bytes.len() && bytes[fin].is_ascii_digit() {
+        fin += 1;
+    }
+    if fin == posicion || fin - posicion > 10 {
+        return None;
+    }
+    let numero = std::str::from_utf8(&bytes[posicion..fin])
+        .ok()?
+        .parse()
+        .ok()?;
+    Some((numero, fin))
+}
+
+fn saltar_espacios(bytes: &[u8], posicion: usize) -> Option<usize> {
+    let mut fin = posicion;
+    while fin < bytes.len() && bytes[fin].is_ascii_whitespace() {
+        fin += 1;
+    }
+    if fin == posicion {
+        return None;
+    }
+    Some(fin)
+}
+
+fn buscar(bytes: &[u8], desde: usize, patron: &[u8]) -> Option<usize> {
+    bytes[desde..]
+        .windows(patron.len())
+        .position(|v| v == patron)
+        .map(|p| desde + p)
+}
+
+fn es_catalogo(contenido: &[u8]) -> bool {
+    let texto = String::from_utf8_lossy(contenido);
+    let compacto = texto.split_whitespace().collect::<String>();
+    compacto.contains("/Type/Catalog")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PDF_VALIDO: &[u8] =
+        include_bytes!("../../tests/fixtures/boe.es/boe/dias/2024/12/09/pdfs/BOE-A-2024-25600.pdf");
+    const PDF_CORTADO: &[u8] =
+        include_bytes!("../../tests/fixtures/boe.es/boe/dias/2024/12/09/pdfs/BOE-A-2024-25602.pdf");
+    const PAGINA_ERROR: &[u8] =
+        include_bytes!("../../tests/fixtures/boe.es/boe/dias/2024/12/09/pdfs/BOE-A-2024-25601.pdf");
+
+    #[test]
+    fn rechaza_paginas_html_servidas_como_pdf() {
+        assert!(comprobar_pdf(Some("application/pdf"), PDF_VALIDO).is_ok());
+        assert!(comprobar_pdf(None, PDF_VALIDO).is_ok());
+        assert!(comprobar_pdf(Some("text/html; charset=utf-8"), PDF_VALIDO).is_err());
+        assert!(comprobar_pdf(Some("application/pdf"), PAGINA_ERROR).is_err());
+    }
+
+    #[test]
+    fn repara_un_pdf_sin_tabla_de_referencias() {
+        assert!(cargar_documento(PDF_CORTADO, false).is_err());
+        let documento = cargar_documento(PDF_CORTADO, true).unwrap();
+        assert_eq!(documento.get_pages().len(), 2);
+    }
+
+    #[test]
+    fn ignora_los_numeros_de_objeto_desmesurados() {
+        let mut dañado = PDF_CORTADO.to_vec();
+        dañado.extend_from_slice(b"\n4000000000 0 obj\n<< >>\nendobj\n");
+        dañado.extend_from_slice(b"8000000 70000 obj\n<< >>\nendobj\n");
+        dañado.extend_from_slice(b"8000000 0 obj\n<< >>\nendobj\n");
+        let reparado = reconstruir_referencias(&dañado).unwrap();
+        // Solo se añaden las entradas de los objetos que existen
+        assert!(reparado.len() < dañado.len() + 2048);
+        let texto = String::from_utf8_lossy(&reparado[dañado.len()..]).to_string();
+        assert!(texto.contains("\n8000000 1\n"));
+        assert!(texto.contains("/Size 8000001"));
+        let documento = cargar_documento(&dañado, true).unwrap();
+        assert_eq!(documento.get_pages().len(), 2);
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+    time::Duration,
+};
+
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, Weekday};
+use reqwest::Client;
+use scraper::Selector;
+use tokio::time::sleep;
+use tracing::{error, info};
+
+use super::{cliente_http, configuracion::Configuracion, descargar};
+
+const NOMBRE_ARCHIVO_ESTADO: &str = "boe_completo_estado.txt";
+const INTENTOS_POR_FECHA: usize = 3;
+
+pub async fn vigilar_publicaciones(configuracion: &Configuracion) -> Result<(), String> {
+    let ruta_estado = configuracion.directorio_salida.join(NOMBRE_ARCHIVO_ESTADO);
+    let mut procesadas = leer_fechas_procesadas(&ruta_estado)?;
+    let cliente = cliente_http::nuevo_cliente_http(configuracion)?;
+    let sondeo = Duration::from_secs(configuracion.sondeo);
+    info!(
+        "modo vigilancia iniciado, {} fechas procesadas previamente en {}",
+        procesadas.len(),
+        ruta_estado.display()
+    );
+    loop {
+        let hoy = Local::now().date_naive();
+        let fecha = formatear_fecha(hoy);
+        if pendiente_de_procesar(hoy, &procesadas) {
+            let mut intentos = 0;
+            while intentos < INTENTOS_POR_FECHA && Local::now().date_naive() == hoy {
+                if !sumario_publicado(&cliente, &configuracion.url_base, hoy).await {
+                    info!("el sumario del {} todavía no está publicado", fecha);
+                    sleep(sondeo).await;
+                    continue;
+                }
+                intentos += 1;
+                match descargar::crear_gestor_descargas(&fecha, configuracion).await {
+                    Err(error) => {
+                        error!(
+                            "ha fallado el procesamiento del {} (intento {} de {}) {}",
+                            fecha, intentos, INTENTOS_POR_FECHA, error
+                        );
+                        sleep(sondeo).await;
+                    }
+                    Ok(_) => {
+                        registrar_fecha_procesada(&ruta_estado, &fecha)?;
+                        procesadas.insert(fecha);
+                        break;
+                    }
+                }
+            }
+        }
+        let espera = tiempo_hasta_siguiente_publicacion(Local::now(), sondeo);
+        info!(
+            "esperando {} minutos hasta el siguiente día de publicación",
+            espera.as_secs() / 60
+        );
+        sleep(espera).await;
+    }
+}
+
+async fn sumario_publicado(cliente: &Client, url_base: &str, fecha: NaiveDate) -> bool {
+    let ruta = format!(
+        "boe/dias/{}/{:02}/{:02}/",
+        fecha.year(),
+        fecha.month(),
+        fecha.day()
+    );
+    let endpoint = match descargar::unir_url_base(url_base, &ruta) {
+        Err(_) => return false,
+        Ok(ok) => ok,
+    };
+    let html = descargar::realizar_get_devolver_html_parseado(endpoint.as_str(), cliente).await;
+    let html = match html {
+        Err(_) => return false,
+        Ok(ok) => ok,
+    };
+    let selector = Selector::parse("#indiceSumario .sumario .puntoPDF a")
+        .expect("ha fallado el selector para comprobar la publicación del sumario");
+    html.select(&selector).next().is_some()
+}
+
+fn formatear_fecha(fecha: NaiveDate) -> String {
+    fecha.format("%d-%m-%Y").to_string()
+}
+
+// El BOE no se publica en domingo y las fechas del archivo de estado ya se han generado
+fn pendiente_de_procesar(fecha: NaiveDate, procesadas: &HashSet<String>) -> bool {
+    fecha.weekday() != Weekday::Sun && !procesadas.contains(&formatear_fecha(fecha))
+}
+
+// Hasta las 00:05 del siguiente día con boletín, o el sondeo si no se puede calcular
+fn tiempo_hasta_siguiente_publicacion(ahora: DateTime<Local>, sondeo: Duration) -> Duration {
+    let mut siguiente = ahora.date_naive() + Days::new(1);
+    if siguiente.weekday() == Weekday::Sun {
+        siguiente = siguiente + Days::new(1);
+    }
+    let inicio_siguiente = siguiente
+        .and_hms_opt(0, 5, 0)
+        .and_then(|f| f.and_local_timezone(Local).earliest());
+    match inicio_siguiente {
+        None => sondeo,
+        Some(inicio) => (inicio - ahora).to_std().unwrap_or(sondeo),
+    }
+}
+
+fn leer_fechas_procesadas(ruta: &Path) -> Result<HashSet<String>, String> {
+    if !ruta.exists() {
+        return Ok(HashSet::new());
+    }
+    match fs::read_to_string(ruta) {
+        Err(error) => Err(format!(
+            "no se ha podido leer el archivo de estado {} {}",
+            ruta.display(),
+            error
+        )),
+        Ok(contenido) => Ok(contenido
+            .lines()
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect()),
+    }
+}
+
+fn registrar_fecha_procesada(ruta: &Path, fecha: &str) -> Result<(), String> {
+    let mut archivo = match OpenOptions::new().create(true).append(true).open(ruta) {
+        Err(error) => {
+            let mensaje_error = format!(
+                "no se ha podido abrir el archivo de estado {} {}",
+                ruta.display(),
+                error
+            );
+            return Err(mensaje_error);
+        }
+        Ok(ok) => ok,
+    };
+    match writeln!(archivo, "{}", fecha) {
+        Err(error) => Err(format!(
+            "ha fallado la escritura del archivo de estado {} {}",
+            ruta.display(),
+            error
+        )),
+        Ok(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    fn fecha(dia: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 12, dia).unwrap()
+    }
+
+    #[test]
+    fn guarda_y_lee_las_fechas_procesadas() {
+        let directorio = TempDir::new().unwrap();
+        let ruta = directorio.path().join(NOMBRE_ARCHIVO_ESTADO);
+        assert!(leer_fechas_procesadas(&ruta).unwrap().is_empty());
+
+        registrar_fecha_procesada(&ruta, "07-12-2024").unwrap();
+        registrar_fecha_procesada(&ruta, "09-12-2024").unwrap();
+        fs::write(&ruta, fs::read_to_string(&ruta).unwrap() + "\n  \n").unwrap();
+        registrar_fecha_procesada(&ruta, "10-12-2024").unwrap();
+
+        let procesadas = leer_fechas_procesadas(&ruta).unwrap();
+        assert_eq!(procesadas.len(), 3);
+        assert!(!pendiente_de_procesar(fecha(7), &procesadas));
+        assert!(!pendiente_de_procesar(fecha(10), &procesadas));
+        assert!(pendiente_de_procesar(fecha(11), &procesadas));
+    }
+
+    #[test]
+    fn no_procesa_los_domingos() {
+        // El 8 de diciembre de 2024 fue domingo
+        assert!(!pendiente_de_procesar(fecha(8), &HashSet::new()));
+        assert!(pendiente_de_procesar(fecha(9), &HashSet::new()));
+    }
+
+    #[test]
+    fn espera_hasta_el_siguiente_dia_con_boletin() {
+        let sondeo = Duration::from_secs(600);
+        // Jueves a las 20:00, el viernes a las 00:05
+        let jueves = Local.with_ymd_and_hms(2024, 12, 5, 20, 0, 0).unwrap();
+        assert_eq!(
+            tiempo_hasta_siguiente_publicacion(jueves, sondeo),
+            Duration::from_secs(4 * 3600 + 5 * 60)
+        );
+        // Sábado a las 23:00, se salta el domingo
+        let sabado = Local.with_ymd_and_hms(2024, 12, 7, 23, 0, 0).unwrap();
+        assert_eq!(
+            tiempo_hasta_siguiente_publicacion(sabado, sondeo),
+            Duration::from_secs(25 * 3600 + 5 * 60)
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::VecDeque;
+
+use tracing::warn;
+
+use super::{
+    configuracion::Configuracion,
+    descargar::EnlacePDF,
+    firmar::{self, Firmante},
+    optimizar::ResultadoOptimizacion,
+    unir_pdf::{self, OpcionesUnion, PdfCargado},
+};
+
+pub struct LimitesVolumen {
+    pub bytes: Option<usize>,
+    pub paginas: Option<usize>,
+}
+
+pub struct Volumen {
+    pub enlaces: Vec<EnlacePDF>,
+    pub contenido: Vec<u8>,
+    pub paginas: usize,
+    pub optimizacion: Option<ResultadoOptimizacion>,
+}
+
+impl LimitesVolumen {
+    // None si no se ha pedido dividir en volúmenes
+    pub fn desde_configuracion(configuracion: &Configuracion) -> Option<LimitesVolumen> {
+        if configuracion.volumen_maximo_mb.is_none()
+            && configuracion.volumen_maximo_paginas.is_none()
+        {
+            return None;
+        }
+        Some(LimitesVolumen {
+            bytes: configuracion
+                .volumen_maximo_mb
+                .map(|mb| mb as usize * 1_000_000),
+            paginas: configuracion.volumen_maximo_paginas,
+        })
+    }
+    fn admite(&self, bytes: usize, paginas: usize) -> bool {
+        self.bytes.is_none_or(|maximo| bytes <= maximo)
+            && self.paginas.is_none_or(|maximo| paginas <= maximo)
+    }
+}
+
+// Reparte los documentos en volúmenes consecutivos sin partir ninguno. El tamaño descargado
+// sirve para hacer una primera estimación, pero el que cuenta es el del PDF unido, así que si
+// un volumen se pasa se busca por bisección cuántos de sus documentos caben. Con firmante cada
+// volumen se devuelve ya firmado, porque la firma también cuenta para el límite: se firma solo
+// el volumen elegido y lo que ocupa la firma se descuenta al probar los siguientes
+pub fn repartir_en_volumenes(
+    documentos: Vec<PdfCargado>,
+    limites: &LimitesVolumen,
+    opciones: &OpcionesUnion,
+    firmante: Option<&Firmante>,
+) -> Result<Vec<Volumen>, String> {
+    let mut pendientes = documentos
+        .into_iter()
+        .map(|d| {
+            let paginas = d.documento.get_pages().len();
+            (d, paginas)
+        })
+        .collect::<VecDeque<(PdfCargado, usize)>>();
+    let mut volumenes = Vec::new();
+    // Bytes que añade la firma, se conocen al firmar el primer volumen
+    let mut margen_firma = 0;
+    while !pendientes.is_empty() {
+        let mut cantidad = 0;
+        let mut bytes = 0;
+        let mut paginas = 0;
+        for (pdf, paginas_pdf) in &pendientes {
+            if cantidad > 0 && !limites.admite(bytes + pdf.tamaño, paginas + paginas_pdf) {
+                break;
+            }
+            cantidad += 1;
+            bytes += pdf.tamaño;
+            paginas += paginas_pdf;
+        }
+        loop {
+            let cabe = |candidato: &Candidato| {
+                limites.admite(candidato.contenido.len() + margen_firma, candidato.paginas)
+            };
+            let mut elegido = unir_primeros(&pendientes, cantidad, opciones)?;
+            if cantidad > 1 && !cabe(&elegido) {
+                // El mayor número de documentos que cabe está entre 1 y cantidad - 1
+                let (mut menor, mut mayor) = (1, cantidad - 1);
+                let mut mejor = None;
+                while menor < mayor {
+                    let medio = (menor + mayor).div_ceil(2);
+                    let candidato = unir_primeros(&pendientes, medio, opciones)?;
+                    match cabe(&candidato) {
+                        true => {
+                            menor = medio;
+                            mejor = Some(candidato);
+                        }
+                        false => mayor = medio - 1,
+                    }
+                }
+                elegido = match mejor {
+                    Some(candidato) if candidato.cantidad == menor => candidato,
+                    _ => unir_primeros(&pendientes, menor, opciones)?,
+                };
+            }
+            let mut contenido = elegido.contenido;
+            if let Some(firmante) = firmante {
+                let sin_firma = contenido.len();
+                contenido = firmar::firmar_pdf(&contenido, firmante)?;
+                // Si la firma ocupa más de lo previsto se vuelve a buscar con su tamaño real
+                if elegido.cantidad > 1 && !limites.admite(contenido.len(), elegido.paginas) {
+                    margen_firma = contenido.len() - sin_firma;
+                    cantidad = elegido.cantidad;
+                    continue;
+                }
+                margen_firma = margen_firma.max(contenido.len() - sin_firma);
+            }
+            let enlaces = pendientes
+                .drain(..elegido.cantidad)
+                .map(|(pdf, _)| pdf.enlace)
+                .collect::<Vec<EnlacePDF>>();
+            if !limites.admite(contenido.len(), elegido.paginas) {
+                warn!(
+                    "{} supera por sí solo el límite de los volúmenes y ocupa uno entero",
+                    enlaces[0].titulo
+                );
+            }
+            volumenes.push(Volumen {
+                enlaces,
+                contenido,
+                paginas: elegido.paginas,
+                optimizacion: elegido.optimizacion,
+            });
+            break;
+        }
+    }
+    Ok(volumenes)
+}
+
+// Los primeros documentos pendientes unidos y serializados, todavía sin firmar
+struct Candidato {
+    cantidad: usize,
+    contenido: Vec<u8>,
+    paginas: usize,
+    optimizacion: Option<ResultadoOptimizacion>,
+}
+
+fn unir_primeros(
+    pendientes: &VecDeque<(PdfCargado, usize)>,
+    cantidad: usize,
+    opciones: &OpcionesUnion,
+) -> Result<Candidato, String> {
+    let documentos = pendientes
+        .iter()
+        .take(cantidad)
+        .map(|(pdf, _)| (pdf.enlace.to_owned(), pdf.documento.clone()))
+        .collect();
+    let unido = unir_pdf::unir_documentos(documentos, opciones)?;
+    let mut documento = unido.documento;
+    let contenido = unir_pdf::serializar(&mut documento)?;
+    Ok(Candidato {
+        cantidad,
+        contenido,
+        paginas: documento.get_pages().len(),
+        optimizacion: unido.optimizacion,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use lopdf::Document;
+
+    use super::{super::firmas, *};
+
+    const UNO: &[u8] =
+        include_bytes!("../../tests/fixtures/boe.es/boe/dias/2024/12/07/pdfs/BOE-A-2024-25530.pdf");
+    const DOS: &[u8] =
+        include_bytes!("../../tests/fixtures/boe.es/boe/dias/2024/12/07/pdfs/BOE-B-2024-41020.pdf");
+
+    fn opciones() -> OpcionesUnion {
+        OpcionesUnion {
+            optimizar_recursos: false,
+            pdfa: false,
+            estampar: false,
+            fecha: String::from("07-12-2024"),
+            cifrado: None,
+        }
+    }
+
+    fn cargado(titulo: &str, bytes: &[u8]) -> PdfCargado {
+        PdfCargado {
+            enlace: EnlacePDF {
+                apartado: String::from("Disposiciones y anuncios"),
+                url: format!("https://boe.es/{}.pdf", titulo),
+                titulo: titulo.to_string(),
+                ..EnlacePDF::default()
+            },
+            documento: Document::load_mem(bytes).unwrap(),
+            tamaño: bytes.len(),
+        }
+    }
+
+    #[test]
+    fn saca_del_volumen_lo_que_no_cabe_una_vez_unido() {
+        let opciones = opciones();
+        let sin_limite = LimitesVolumen {
+            bytes: None,
+            paginas: None,
+        };
+        let juntos = vec![cargado("uno", UNO), cargado("dos", DOS)];
+        let juntos = repartir_en_volumenes(juntos, &sin_limite, &opciones, None).unwrap();
+        assert_eq!(juntos.len(), 1);
+
+        // Con un tamaño descargado casi nulo la estimación los junta, pero el PDF unido se pasa
+        let mut documentos = vec![cargado("uno", UNO), cargado("dos", DOS)];
+        for documento in &mut documentos {
+            documento.tamaño = 1;
+        }
+        let limites = LimitesVolumen {
+            bytes: Some(juntos[0].contenido.len() - 1),
+            paginas: None,
+        };
+        let volumenes = repartir_en_volumenes(documentos, &limites, &opciones, None).unwrap();
+        assert_eq!(volumenes.len(), 2);
+        assert_eq!(volumenes[0].enlaces[0].titulo, "uno");
+        assert_eq!(volumenes[1].enlaces[0].titulo, "dos");
+        assert!(volumenes.iter().all(|v| v.paginas == 1));
+    }
+
+    #[test]
+    fn cuenta_la_firma_en_el_tamaño_del_volumen() {
+        let firma = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/firma");
+        let firmante = firmar::cargar_firmante(&firma.join("firmante.p12"), "boe").unwrap();
+        let sin_limite = LimitesVolumen {
+            bytes: None,
+            paginas: None,
+        };
+        let juntos = vec![cargado("uno", UNO), cargado("dos", DOS)];
+        let firmados =
+            repartir_en_volumenes(juntos, &sin_limite, &opciones(), Some(&firmante)).unwrap();
+        assert_eq!(firmados.len(), 1);
+
+        // Sin firmar los dos caben de sobra, firmados no
+        let limites = LimitesVolumen {
+            bytes: Some(firmados[0].contenido.len() - 1),
+            paginas: None,
+        };
+        let juntos = vec![cargado("uno", UNO), cargado("dos", DOS)];
+        let sin_firma = repartir_en_volumenes(juntos, &limites, &opciones(), None).unwrap();
+        assert_eq!(sin_firma.len(), 1);
+        let juntos = vec![cargado("uno", UNO), cargado("dos", DOS)];
+        let volumenes =
+            repartir_en_volumenes(juntos, &limites, &opciones(), Some(&firmante)).unwrap();
+        assert_eq!(volumenes.len(), 2);
+        let almacen = firmas::cargar_almacen(&[firma.join("ca.pem")]).unwrap();
+        for volumen in &volumenes {
+            assert!(volumen.contenido.len() <= limites.bytes.unwrap());
+            let verificacion = firmas::verificar_documento(&volumen.contenido, &almacen);
+            assert_eq!(verificacion.estado, firmas::EstadoFirma::Valida);
+        }
+    }
+
+    #[test]
+    fn llena_cada_volumen_con_todo_lo_que_cabe() {
+        let opciones = opciones();
+        let documentos = || {
+            (0..9)
+                .map(|i| match i % 2 {
+                    0 => cargado(&format!("uno{}", i), UNO),
+                    _ => cargado(&format!("dos{}", i), DOS),
+                })
+                .collect::<Vec<_>>()
+        };
+        let sin_limite = LimitesVolumen {
+            bytes: None,
+            paginas: None,
+        };
+        let tres = documentos().into_iter().take(3).collect();
+        let tres = repartir_en_volumenes(tres, &sin_limite, &opciones, None).unwrap();
+        let limites = LimitesVolumen {
+            bytes: Some(tres[0].contenido.len()),
+            paginas: None,
+        };
+
+        // La estimación por tamaño descargado mete los nueve en el primer volumen
+        let mut pendientes = documentos();
+        for documento in &mut pendientes {
+            documento.tamaño = 1;
+        }
+        let volumenes = repartir_en_volumenes(pendientes, &limites, &opciones, None).unwrap();
+        assert_eq!(volumenes.iter().map(|v| v.enlaces.len()).sum::<usize>(), 9);
+        assert!(volumenes
+            .iter()
+            .all(|v| v.contenido.len() <= limites.bytes.unwrap()));
+        // Cada volumen se pasaría con el primer documento del siguiente
+        let mut inicio = 0;
+        for volumen in &volumenes[..volumenes.len() - 1] {
+            let fin = inicio + volumen.enlaces.len() + 1;
+            let con_uno_mas = documentos()
+                .into_iter()
+                .skip(inicio)
+                .take(fin - inicio)
+                .collect();
+            let con_uno_mas =
+                repartir_en_volumenes(con_uno_mas, &sin_limite, &opciones, None).unwrap();
+            assert!(con_uno_mas[0].contenido.len() > limites.bytes.unwrap());
+            inicio += volumen.enlaces.len();
+        }
+    }
+}
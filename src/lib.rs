@@ -0,0 +1 @@
+pub mod controlador_boe;
@@ -1,11 +1,48 @@
 use std::{env::args, process::exit};
 
-mod controlador_boe;
+use boe_completo_pdf::controlador_boe::{
+    self, argumentos::Modo, configuracion, progreso, registro,
+};
+use tracing::error;
+
 #[tokio::main]
 async fn main() {
     let argumentos = args().collect::<Vec<String>>();
-    match controlador_boe::descargar::crear_gestor_descargas(&argumentos[argumentos.len() - 1]).await {
+    let argumentos = match controlador_boe::argumentos::procesar_argumentos(&argumentos) {
+        Err(error) => {
+            eprintln!("{}", error);
+            exit(1);
+        }
+        Ok(ok) => ok,
+    };
+    registro::iniciar_registro(argumentos.nivel_registro);
+    if argumentos.progreso_json {
+        progreso::activar_progreso_json();
+    }
+    let configuracion = match configuracion::cargar_configuracion(&argumentos) {
+        Err(error) => {
+            error!("{}", error);
+            exit(1);
+        }
+        Ok(ok) => ok,
+    };
+    let resultado = match &argumentos.modo {
+        Modo::Descargar(fecha) => {
+            controlador_boe::descargar::crear_gestor_descargas(fecha, &configuracion).await
+        }
+        Modo::Vigilar => controlador_boe::vigilar::vigilar_publicaciones(&configuracion).await,
+        Modo::Servir => controlador_boe::servir::servir_boletines(&configuracion).await,
+        Modo::MostrarConfiguracion => configuracion::mostrar_configuracion(&configuracion),
+        Modo::Verificar(ruta) => controlador_boe::firmas::verificar_archivo(ruta, &configuracion),
+        Modo::Comparar(antes, despues) => {
+            controlador_boe::comparar::comparar_archivos(antes, despues)
+        }
+    };
+    match resultado {
         Ok(_) => exit(0),
-        Err(_) => exit(1),
+        Err(error) => {
+            error!("{}", error);
+            exit(1)
+        }
     }
 }
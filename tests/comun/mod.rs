@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use axum::{
+    http::{header, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use boe_completo_pdf::controlador_boe::configuracion::Configuracion;
+use tempfile::TempDir;
+use tokio::{fs, net::TcpListener};
+
+// Réplica local de boe.es que sirve las páginas y PDF guardados en tests/fixtures/boe.es
+pub async fn iniciar_servidor_boe() -> String {
+    iniciar_servidor_boe_en("").await
+}
+
+// Igual, pero bajo una subruta como "/boe-espejo", como una réplica publicada en un directorio.
+// Fuera de ella responde 404
+pub async fn iniciar_servidor_boe_en(prefijo: &'static str) -> String {
+    let rutas = Router::new().fallback(move |uri: Uri| servir_fixture(prefijo, uri));
+    let escucha = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let direccion = escucha.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(escucha, rutas).await.unwrap();
+    });
+    format!("http://{}{}", direccion, prefijo)
+}
+
+pub fn configuracion_pruebas(url_base: &str, salida: &TempDir) -> Configuracion {
+    Configuracion {
+        url_base: url_base.to_string(),
+        directorio_salida: salida.path().to_owned(),
+        espera: 0,
+        ..Configuracion::default()
+    }
+}
+
+async fn servir_fixture(prefijo: &str, uri: Uri) -> Response {
+    let Some(camino) = uri.path().strip_prefix(prefijo) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let mut ruta = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/boe.es")
+        .join(camino.trim_start_matches('/'));
+    if camino.ends_with('/') {
+        ruta = ruta.join("index.html");
+    }
+    // El XML de cada documento se pide como xml.php?id=BOE-A-...
+    if camino == "/diario_boe/xml.php" {
+        let identificador = uri.query().unwrap_or_default().trim_start_matches("id=");
+        ruta = ruta
+            .with_file_name("xml")
+            .join(format!("{}.xml", identificador));
+    }
+    let tipo_contenido = match ruta.extension().and_then(|e| e.to_str()) {
+        Some("pdf") => "application/pdf",
+        Some("xml") => "application/xml",
+        _ => "text/html; charset=utf-8",
+    };
+    match fs::read(&ruta).await {
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+        Ok(contenido) => ([(header::CONTENT_TYPE, tipo_contenido)], contenido).into_response(),
+    }
+}
@@ -0,0 +1,28 @@
+<!DOCTYPE html>
+<html lang="es">
+<head>
+<meta charset="utf-8">
+<title>BOE.es - Sumario de edictos judiciales del día 07/12/2024</title>
+</head>
+<body>
+<div id="contenido">
+<div id="indiceSumarioN">
+<div class="sumario">
+<h3>Edictos judiciales</h3>
+<h4>JUZGADOS DE PRIMERA INSTANCIA</h4>
+<h5>MADRID</h5>
+<ul>
+<li class="dispo">
+<p>Edicto del Juzgado de Primera Instancia n.º 12 de Madrid sobre expediente de dominio.</p>
+<div class="enlacesDoc" id="BOE-J-2024-45120">
+<ul>
+<li class="puntoPDF"><a href="/boe_j/dias/2024/12/07/pdfs/BOE-J-2024-45120.pdf" title="PDF firmado de BOE-J-2024-45120">PDF (BOE-J-2024-45120 - 3 págs. - 90 KB)</a></li>
+</ul>
+</div>
+</li>
+</ul>
+</div>
+</div>
+</div>
+</body>
+</html>
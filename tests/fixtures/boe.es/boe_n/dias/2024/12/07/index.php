@@ -0,0 +1,28 @@
+<!DOCTYPE html>
+<html lang="es">
+<head>
+<meta charset="utf-8">
+<title>BOE.es - Sumario de notificaciones del día 07/12/2024</title>
+</head>
+<body>
+<div id="contenido">
+<div id="indiceSumarioN">
+<div class="sumario">
+<h3>Notificaciones</h3>
+<h4>MINISTERIO DEL INTERIOR</h4>
+<h5>Jefatura Central de Tráfico</h5>
+<ul>
+<li class="dispo">
+<p>Anuncio de notificación de 3 de diciembre de 2024 en procedimiento de sanciones.</p>
+<div class="enlacesDoc" id="BOE-N-2024-321001">
+<ul>
+<li class="puntoPDF"><a href="/boe_n/dias/2024/12/07/pdfs/BOE-N-2024-321001.pdf" title="PDF firmado de BOE-N-2024-321001">PDF (BOE-N-2024-321001 - 1 pág. - 120 KB)</a></li>
+</ul>
+</div>
+</li>
+</ul>
+</div>
+</div>
+</div>
+</body>
+</html>
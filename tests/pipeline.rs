@@ -0,0 +1,917 @@
+mod comun;
+
+use std::{fs, io::Read, path::PathBuf};
+
+use boe_completo_pdf::controlador_boe::{
+    cifrar::{self, Permiso},
+    comparar,
+    descargar::{self, InformeDescargas},
+    dividir::Division,
+    duplicados,
+    firmas::{self, EstadoFirma},
+    marcadores, metadatos, servir, validar_fecha,
+};
+use lopdf::{Document, Object};
+use tempfile::TempDir;
+use zip::ZipArchive;
+
+#[tokio::test]
+async fn une_todos_los_apartados_sin_conexion() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let configuracion = comun::configuracion_pruebas(&url_base, &salida);
+
+    descargar::crear_gestor_descargas("07-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+    let unido = Document::load(descargar::ruta_salida(&configuracion, &fecha, ".pdf")).unwrap();
+    assert_eq!(unido.get_pages().len(), 8);
+    // Todos los documentos usan la misma fuente, que se guarda una sola vez
+    let fuentes = unido
+        .objects
+        .values()
+        .filter(|o| o.type_name().ok() == Some("Font"))
+        .count();
+    assert_eq!(fuentes, 1);
+    // Metadatos para los gestores documentales, también en XMP
+    let info = unido.trailer.get(b"Info").unwrap().as_reference().unwrap();
+    let info = unido.get_dictionary(info).unwrap();
+    let texto = |clave: &[u8]| lopdf::decode_text_string(info.get(clave).unwrap()).unwrap();
+    assert_eq!(texto(b"Title"), "BOE completo 07-12-2024");
+    assert!(texto(b"Keywords").contains("Notificaciones"));
+    assert_eq!(
+        texto(metadatos::CLAVE_FUENTES.as_bytes())
+            .split(' ')
+            .count(),
+        5
+    );
+    assert!(unido.catalog().unwrap().has(b"Metadata"));
+
+    let informe = fs::read(descargar::ruta_salida(
+        &configuracion,
+        &fecha,
+        "_informe.json",
+    ))
+    .unwrap();
+    let informe: InformeDescargas = serde_json::from_slice(&informe).unwrap();
+    assert_eq!(informe.total_documentos, 5);
+    let apartados = informe
+        .documentos
+        .iter()
+        .map(|d| d.apartado.as_str())
+        .collect::<Vec<&str>>();
+    assert_eq!(
+        apartados,
+        [
+            "Disposiciones y anuncios",
+            "Disposiciones y anuncios",
+            "Disposiciones y anuncios",
+            "Notificaciones",
+            "Edictos Judiciales"
+        ]
+    );
+    assert!(descargar::ruta_salida(&configuracion, &fecha, "_informe.tsv").exists());
+}
+
+#[tokio::test]
+async fn los_filtros_y_apartados_limitan_lo_unido() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.apartados = vec![String::from("disposiciones")];
+    configuracion.filtros.rangos = vec![String::from("real decreto")];
+
+    descargar::crear_gestor_descargas("07-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+    let unido = Document::load(descargar::ruta_salida(&configuracion, &fecha, ".pdf")).unwrap();
+    assert_eq!(unido.get_pages().len(), 2);
+}
+
+#[tokio::test]
+async fn falla_si_no_hay_sumario_publicado() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let configuracion = comun::configuracion_pruebas(&url_base, &salida);
+
+    let resultado = descargar::crear_gestor_descargas("08-12-2024", &configuracion).await;
+    assert!(resultado.unwrap_err().contains("404"));
+}
+
+#[tokio::test]
+async fn descarta_las_respuestas_que_no_son_pdf() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.apartados = vec![String::from("disposiciones")];
+
+    // Una página de error servida como PDF y una descarga cortada sin tabla de referencias
+    descargar::crear_gestor_descargas("09-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("09-12-2024").unwrap();
+    let unido = Document::load(descargar::ruta_salida(&configuracion, &fecha, ".pdf")).unwrap();
+    assert_eq!(unido.get_pages().len(), 1);
+}
+
+#[tokio::test]
+async fn repara_los_pdf_cortados_si_se_indica() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.apartados = vec![String::from("disposiciones")];
+    configuracion.reparar_pdf = true;
+
+    descargar::crear_gestor_descargas("09-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("09-12-2024").unwrap();
+    let unido = Document::load(descargar::ruta_salida(&configuracion, &fecha, ".pdf")).unwrap();
+    assert_eq!(unido.get_pages().len(), 3);
+}
+
+#[tokio::test]
+async fn conserva_los_marcadores_y_enlaces_de_cada_documento() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.apartados = vec![String::from("disposiciones")];
+
+    descargar::crear_gestor_descargas("07-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+    let unido = Document::load(descargar::ruta_salida(&configuracion, &fecha, ".pdf")).unwrap();
+    let paginas = unido.get_pages();
+
+    let indice = marcadores::leer_marcadores(&unido);
+    assert_eq!(indice.len(), 3);
+    assert!(indice[0].titulo.starts_with("Real Decreto 1155/2024"));
+    assert!(indice[2].titulo.starts_with("Anuncio de la Junta"));
+    // El índice del PDF original cuelga del marcador de su disposición
+    let hijos = &indice[0].hijos;
+    assert_eq!(hijos.len(), 2);
+    assert_eq!(hijos[0].titulo, "Artículo 1");
+    assert_eq!(hijos[0].pagina, paginas[&1]);
+    assert_eq!(hijos[1].titulo, "Anexo");
+    assert_eq!(hijos[1].pagina, paginas[&2]);
+
+    // El enlace con destino con nombre apunta ahora directamente a la página del anexo
+    let anotaciones = unido.get_page_annotations(paginas[&1]).unwrap();
+    let destino = anotaciones[0]
+        .get(b"A")
+        .and_then(|a| a.as_dict())
+        .and_then(|a| a.get(b"D"))
+        .and_then(|d| d.as_array())
+        .unwrap();
+    assert_eq!(destino[0].as_reference().unwrap(), paginas[&2]);
+    let eli = anotaciones[1]
+        .get(b"A")
+        .and_then(|a| a.as_dict())
+        .and_then(|a| a.get(b"URI"))
+        .and_then(|u| u.as_str())
+        .unwrap();
+    assert_eq!(eli, b"https://www.boe.es/eli/es/rd/2024/11/19/1155");
+}
+
+#[tokio::test]
+async fn enlaza_cada_correccion_de_errores_con_su_original() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.apartados = vec![String::from("disposiciones")];
+
+    descargar::crear_gestor_descargas("12-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("12-12-2024").unwrap();
+    let informe = fs::read(descargar::ruta_salida(
+        &configuracion,
+        &fecha,
+        "_informe.json",
+    ))
+    .unwrap();
+    let informe: InformeDescargas = serde_json::from_slice(&informe).unwrap();
+    let corrige = informe
+        .documentos
+        .iter()
+        .map(|d| d.corrige.as_deref())
+        .collect::<Vec<_>>();
+    // La primera corrección se enlaza por el título y la segunda por la referencia de su XML
+    assert_eq!(
+        corrige,
+        [None, Some("BOE-A-2024-25900"), Some("BOE-A-2024-24800")]
+    );
+    let tsv = fs::read_to_string(descargar::ruta_salida(
+        &configuracion,
+        &fecha,
+        "_informe.tsv",
+    ))
+    .unwrap();
+    assert!(tsv.lines().nth(2).unwrap().ends_with("\tBOE-A-2024-25900"));
+
+    let unido = Document::load(descargar::ruta_salida(&configuracion, &fecha, ".pdf")).unwrap();
+    let paginas = unido.get_pages();
+    let indice = marcadores::leer_marcadores(&unido);
+    assert_eq!(indice.len(), 3);
+    assert!(indice[0].hijos[0]
+        .titulo
+        .starts_with("Corregido por: Corrección de errores"));
+    assert_eq!(indice[0].hijos[0].pagina, paginas[&2]);
+    assert!(indice[1].hijos[0]
+        .titulo
+        .starts_with("Corrige: Orden HAC/1460/2024"));
+    assert_eq!(indice[1].hijos[0].pagina, paginas[&1]);
+    // El original de la segunda no está en el boletín, solo queda en el informe
+    assert!(indice[2].hijos.is_empty());
+}
+
+#[tokio::test]
+async fn guarda_los_metadatos_del_xml_en_el_informe_y_en_el_paquete() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.apartados = vec![String::from("disposiciones")];
+    configuracion.metadatos_xml = true;
+    configuracion.empaquetar = true;
+
+    descargar::crear_gestor_descargas("12-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("12-12-2024").unwrap();
+    let informe = fs::read(descargar::ruta_salida(
+        &configuracion,
+        &fecha,
+        "_informe.json",
+    ))
+    .unwrap();
+    let informe: InformeDescargas = serde_json::from_slice(&informe).unwrap();
+    let orden = informe.documentos[0].metadatos_xml.as_ref().unwrap();
+    assert_eq!(orden.rango.as_deref(), Some("Orden"));
+    assert_eq!(
+        orden.url_eli.as_deref(),
+        Some("https://www.boe.es/eli/es/o/2024/12/09/hac1460")
+    );
+    assert_eq!(
+        orden.referencias_posteriores[0].identificador,
+        "BOE-A-2024-25901"
+    );
+    // Sin XML el documento se une igualmente, y su corrección se sigue enlazando por el título
+    assert!(informe.documentos[1].metadatos_xml.is_none());
+    assert_eq!(
+        informe.documentos[1].corrige.as_deref(),
+        Some("BOE-A-2024-25900")
+    );
+    let correccion = informe.documentos[2].metadatos_xml.as_ref().unwrap();
+    assert_eq!(correccion.fecha_disposicion.as_deref(), Some("2024-11-28"));
+    assert_eq!(
+        informe.documentos[2].corrige.as_deref(),
+        Some("BOE-A-2024-24800")
+    );
+
+    let ruta_zip = descargar::ruta_salida(&configuracion, &fecha, ".zip");
+    let mut paquete = ZipArchive::new(fs::File::open(ruta_zip).unwrap()).unwrap();
+    let mut manifiesto = String::new();
+    paquete
+        .by_name("manifiesto.json")
+        .unwrap()
+        .read_to_string(&mut manifiesto)
+        .unwrap();
+    let manifiesto: serde_json::Value = serde_json::from_str(&manifiesto).unwrap();
+    let documentos = manifiesto
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|e| e["nombre"].as_str().unwrap().starts_with("documentos/"))
+        .collect::<Vec<_>>();
+    assert_eq!(documentos.len(), 3);
+    assert_eq!(
+        documentos[0]["metadatos_xml"]["numero_oficial"],
+        "HAC/1460/2024"
+    );
+    assert_eq!(
+        documentos[0]["sha256"],
+        informe.documentos[0].sha256.as_str()
+    );
+    assert!(documentos[1].get("metadatos_xml").is_none());
+}
+
+#[tokio::test]
+async fn descarga_de_una_replica_publicada_en_una_subruta() {
+    let url_base = comun::iniciar_servidor_boe_en("/boe-espejo").await;
+    let salida = TempDir::new().unwrap();
+    let configuracion = comun::configuracion_pruebas(&url_base, &salida);
+
+    // Los enlaces del sumario empiezan por /boe/dias/..., fuera de la subruta darían 404
+    descargar::crear_gestor_descargas("07-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+    let informe = fs::read(descargar::ruta_salida(
+        &configuracion,
+        &fecha,
+        "_informe.json",
+    ))
+    .unwrap();
+    let informe: InformeDescargas = serde_json::from_slice(&informe).unwrap();
+    assert_eq!(informe.total_documentos, 5);
+    assert!(informe
+        .documentos
+        .iter()
+        .all(|d| d.url.starts_with(&format!("{}/boe", url_base)) && d.sha256.len() == 64));
+    let unido = Document::load(descargar::ruta_salida(&configuracion, &fecha, ".pdf")).unwrap();
+    assert_eq!(unido.get_pages().len(), 8);
+}
+
+#[tokio::test]
+async fn une_una_sola_vez_los_documentos_repetidos() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.apartados = vec![String::from("disposiciones")];
+
+    // Un enlace aparece en dos secciones y otros dos apuntan a PDF idénticos
+    descargar::crear_gestor_descargas("10-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("10-12-2024").unwrap();
+    let unido = Document::load(descargar::ruta_salida(&configuracion, &fecha, ".pdf")).unwrap();
+    assert_eq!(unido.get_pages().len(), 3);
+
+    let informe = fs::read(descargar::ruta_salida(
+        &configuracion,
+        &fecha,
+        "_informe.json",
+    ))
+    .unwrap();
+    let informe: InformeDescargas = serde_json::from_slice(&informe).unwrap();
+    assert_eq!(informe.total_documentos, 2);
+    let real_decreto = &informe.documentos[0];
+    assert_eq!(real_decreto.seccion, "I. Disposiciones generales");
+    assert_eq!(real_decreto.tambien_en.len(), 1);
+    assert_eq!(
+        real_decreto.tambien_en[0].seccion,
+        "III. Otras disposiciones"
+    );
+    let resolucion = &informe.documentos[1];
+    assert_eq!(resolucion.sha256.len(), 64);
+    assert_eq!(resolucion.tambien_en.len(), 1);
+    assert_eq!(
+        resolucion.tambien_en[0].departamento,
+        "MINISTERIO DE TRABAJO Y ECONOMÍA SOCIAL"
+    );
+}
+
+#[tokio::test]
+async fn divide_el_boletin_por_apartado() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.division = Division::Apartado;
+
+    descargar::crear_gestor_descargas("07-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+    assert!(!descargar::ruta_salida(&configuracion, &fecha, ".pdf").exists());
+    assert!(descargar::ruta_salida(&configuracion, &fecha, "_informe.json").exists());
+    let partes = [
+        ("_disposiciones_y_anuncios", 4, 3),
+        ("_notificaciones", 1, 1),
+        ("_edictos_judiciales", 3, 1),
+    ];
+    for (sufijo, paginas, documentos) in partes {
+        let ruta_pdf = descargar::ruta_salida(&configuracion, &fecha, &format!("{}.pdf", sufijo));
+        let parte = Document::load(ruta_pdf).unwrap();
+        assert_eq!(parte.get_pages().len(), paginas);
+        assert_eq!(marcadores::leer_marcadores(&parte).len(), documentos);
+
+        let ruta_informe =
+            descargar::ruta_salida(&configuracion, &fecha, &format!("{}_informe.json", sufijo));
+        let informe: InformeDescargas =
+            serde_json::from_slice(&fs::read(ruta_informe).unwrap()).unwrap();
+        assert_eq!(informe.total_documentos, documentos);
+    }
+}
+
+#[tokio::test]
+async fn reparte_el_boletin_en_volumenes_por_paginas() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.volumen_maximo_paginas = Some(3);
+
+    descargar::crear_gestor_descargas("07-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+    assert!(!descargar::ruta_salida(&configuracion, &fecha, ".pdf").exists());
+    let mut paginas_totales = 0;
+    let mut numero = 1;
+    loop {
+        let sufijo = format!("_vol{}.pdf", numero);
+        let ruta_pdf = descargar::ruta_salida(&configuracion, &fecha, &sufijo);
+        if !ruta_pdf.exists() {
+            break;
+        }
+        let paginas = Document::load(ruta_pdf).unwrap().get_pages().len();
+        assert!(paginas <= 3);
+        paginas_totales += paginas;
+        numero += 1;
+    }
+    assert_eq!(paginas_totales, 8);
+
+    let ruta_informe = descargar::ruta_salida(&configuracion, &fecha, "_informe.json");
+    let informe: InformeDescargas =
+        serde_json::from_slice(&fs::read(ruta_informe).unwrap()).unwrap();
+    let volumenes = informe
+        .documentos
+        .iter()
+        .map(|d| d.volumen.clone().unwrap())
+        .collect::<Vec<String>>();
+    assert_eq!(
+        volumenes.first().unwrap(),
+        "07_12_2024_boe_completo_vol1.pdf"
+    );
+    assert_eq!(
+        volumenes.last().unwrap(),
+        &format!("07_12_2024_boe_completo_vol{}.pdf", numero - 1)
+    );
+}
+
+#[tokio::test]
+async fn distingue_los_volumenes_de_cada_parte() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.division = Division::Apartado;
+    configuracion.volumen_maximo_paginas = Some(3);
+
+    descargar::crear_gestor_descargas("07-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+    let ruta_informe = descargar::ruta_salida(&configuracion, &fecha, "_informe.json");
+    let informe: InformeDescargas =
+        serde_json::from_slice(&fs::read(ruta_informe).unwrap()).unwrap();
+    for documento in &informe.documentos {
+        let volumen = documento.volumen.as_deref().unwrap();
+        assert!(salida.path().join(volumen).exists(), "{}", volumen);
+    }
+    let primeros = informe
+        .documentos
+        .iter()
+        .filter(|d| d.volumen.as_deref().unwrap().ends_with("_vol1.pdf"))
+        .map(|d| d.volumen.as_deref().unwrap())
+        .collect::<std::collections::HashSet<&str>>();
+    assert!(primeros.len() > 1);
+}
+
+#[tokio::test]
+async fn deja_el_informe_aunque_falle_la_union() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.apartados = vec![String::from("disposiciones")];
+
+    // El único documento del día no se puede descargar
+    let error = descargar::crear_gestor_descargas("13-12-2024", &configuracion)
+        .await
+        .err()
+        .unwrap();
+    assert!(error.contains("ninguno"), "{}", error);
+
+    let fecha = validar_fecha::comprobar_formato("13-12-2024").unwrap();
+    assert!(!descargar::ruta_salida(&configuracion, &fecha, ".pdf").exists());
+    let ruta_informe = descargar::ruta_salida(&configuracion, &fecha, "_informe.json");
+    let informe: InformeDescargas =
+        serde_json::from_slice(&fs::read(ruta_informe).unwrap()).unwrap();
+    assert_eq!(informe.total_documentos, 1);
+    let tsv = fs::read_to_string(descargar::ruta_salida(
+        &configuracion,
+        &fecha,
+        "_informe.tsv",
+    ))
+    .unwrap();
+    assert!(tsv.contains("BOE-A-2024-26000.pdf"));
+}
+
+#[tokio::test]
+async fn guarda_cada_documento_por_seccion() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.guardar_individuales = true;
+
+    descargar::crear_gestor_descargas("07-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+    assert!(descargar::ruta_salida(&configuracion, &fecha, ".pdf").exists());
+    let ruta_informe = descargar::ruta_salida(&configuracion, &fecha, "_informe.json");
+    let informe: InformeDescargas =
+        serde_json::from_slice(&fs::read(ruta_informe).unwrap()).unwrap();
+    for documento in &informe.documentos {
+        let ruta_local = documento.ruta_local.as_deref().unwrap();
+        assert!(ruta_local.starts_with("07_12_2024_boe_completo_documentos"));
+        let nombre = std::path::Path::new(ruta_local).file_name().unwrap();
+        assert!(nombre.to_str().unwrap().starts_with("BOE-"));
+        let copia = Document::load(salida.path().join(ruta_local)).unwrap();
+        assert!(!copia.get_pages().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn empaqueta_el_boletin_en_un_zip_con_manifiesto() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.empaquetar = true;
+
+    descargar::crear_gestor_descargas("07-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+    let ruta_zip = descargar::ruta_salida(&configuracion, &fecha, ".zip");
+    let mut paquete = ZipArchive::new(fs::File::open(ruta_zip).unwrap()).unwrap();
+    let nombres: Vec<String> = paquete.file_names().map(|n| n.to_string()).collect();
+    for nombre in [
+        "07_12_2024_boe_completo.pdf",
+        "07_12_2024_boe_completo_informe.tsv",
+        "07_12_2024_boe_completo_informe.json",
+        "manifiesto.sha256",
+    ] {
+        assert!(nombres.iter().any(|n| n == nombre), "falta {}", nombre);
+    }
+    let originales = nombres
+        .iter()
+        .filter(|n| n.starts_with("documentos/"))
+        .count();
+    assert_eq!(originales, 5);
+
+    let mut manifiesto = String::new();
+    paquete
+        .by_name("manifiesto.sha256")
+        .unwrap()
+        .read_to_string(&mut manifiesto)
+        .unwrap();
+    assert_eq!(manifiesto.lines().count(), nombres.len() - 1);
+    for linea in manifiesto.lines() {
+        let (resumen, nombre) = linea.split_once("  ").unwrap();
+        let mut contenido = Vec::new();
+        paquete
+            .by_name(nombre)
+            .unwrap()
+            .read_to_end(&mut contenido)
+            .unwrap();
+        assert_eq!(duplicados::resumen_sha256(&contenido), resumen);
+    }
+}
+
+#[tokio::test]
+async fn compara_dos_ejecuciones_del_mismo_dia() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+    let salida_antes = TempDir::new().unwrap();
+    let mut antes = comun::configuracion_pruebas(&url_base, &salida_antes);
+    antes.apartados = vec![String::from("disposiciones")];
+    descargar::crear_gestor_descargas("07-12-2024", &antes)
+        .await
+        .unwrap();
+    let salida_despues = TempDir::new().unwrap();
+    let mut despues = comun::configuracion_pruebas(&url_base, &salida_despues);
+    despues.empaquetar = true;
+    descargar::crear_gestor_descargas("07-12-2024", &despues)
+        .await
+        .unwrap();
+
+    let informe_antes = descargar::ruta_salida(&antes, &fecha, "_informe.json");
+    let informe_despues = descargar::ruta_salida(&despues, &fecha, "_informe.json");
+    let documentos_antes = comparar::leer_documentos(&informe_antes).unwrap();
+    let documentos_despues = comparar::leer_documentos(&informe_despues).unwrap();
+    let comparacion = comparar::comparar_documentos(&documentos_antes, &documentos_despues);
+    assert!(comparacion.eliminados.is_empty() && comparacion.modificados.is_empty());
+    assert_eq!(comparacion.sin_cambios, documentos_antes.len());
+    let añadidos = comparacion
+        .añadidos
+        .iter()
+        .map(|d| d.identificador.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(añadidos, ["BOE-N-2024-321001", "BOE-J-2024-45120"]);
+
+    // El paquete y su manifiesto describen los mismos documentos que el informe
+    let paquete = descargar::ruta_salida(&despues, &fecha, ".zip");
+    let documentos_paquete = comparar::leer_documentos(&paquete).unwrap();
+    let comparacion = comparar::comparar_documentos(&documentos_despues, &documentos_paquete);
+    assert!(!comparacion.hay_diferencias());
+    let mut manifiesto = String::new();
+    ZipArchive::new(fs::File::open(&paquete).unwrap())
+        .unwrap()
+        .by_name("manifiesto.sha256")
+        .unwrap()
+        .read_to_string(&mut manifiesto)
+        .unwrap();
+    let documentos_manifiesto = comparar::documentos_manifiesto(&manifiesto);
+    let comparacion = comparar::comparar_documentos(&documentos_despues, &documentos_manifiesto);
+    assert!(!comparacion.hay_diferencias());
+    comparar::comparar_archivos(&informe_antes, &paquete).unwrap();
+}
+
+#[tokio::test]
+async fn anota_en_el_informe_la_firma_de_cada_documento() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.apartados = vec![String::from("disposiciones")];
+    configuracion.verificar_firmas = true;
+    configuracion.almacen_confianza =
+        vec![PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/firma")];
+
+    descargar::crear_gestor_descargas("11-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("11-12-2024").unwrap();
+    // La unión no se detiene por una firma alterada
+    let unido = Document::load(descargar::ruta_salida(&configuracion, &fecha, ".pdf")).unwrap();
+    assert_eq!(unido.get_pages().len(), 3);
+
+    let informe = fs::read(descargar::ruta_salida(
+        &configuracion,
+        &fecha,
+        "_informe.json",
+    ))
+    .unwrap();
+    let informe: InformeDescargas = serde_json::from_slice(&informe).unwrap();
+    let estados = informe
+        .documentos
+        .iter()
+        .map(|d| d.firma.as_ref().unwrap().estado)
+        .collect::<Vec<EstadoFirma>>();
+    assert_eq!(
+        estados,
+        [
+            EstadoFirma::Valida,
+            EstadoFirma::Invalida,
+            EstadoFirma::SinFirma
+        ]
+    );
+    let firma = informe.documentos[0].firma.as_ref().unwrap();
+    assert_eq!(
+        firma.firmante.as_deref(),
+        Some("Agencia Estatal BOE de pruebas")
+    );
+
+    let tsv = fs::read_to_string(descargar::ruta_salida(
+        &configuracion,
+        &fecha,
+        "_informe.tsv",
+    ))
+    .unwrap();
+    let lineas = tsv.lines().collect::<Vec<&str>>();
+    assert!(lineas[0].ends_with("\tFirma\tCorrige"));
+    assert!(lineas[1].ends_with("\tvalida: no se comprueba la revocación del certificado\t"));
+    assert!(lineas[2].ends_with("\tinvalida: el documento se ha modificado después de firmarse\t"));
+}
+
+#[tokio::test]
+async fn firma_el_pdf_unido_con_el_certificado_indicado() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let firma = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/firma");
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.apartados = vec![String::from("disposiciones")];
+    configuracion.certificado_firma = Some(firma.join("firmante.p12"));
+    configuracion.clave_firma = Some(String::from("boe"));
+    configuracion.almacen_confianza = vec![firma.join("ca.pem")];
+
+    descargar::crear_gestor_descargas("11-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    // Solo queda la firma propia, las de los documentos originales se quitan al unirlos
+    let fecha = validar_fecha::comprobar_formato("11-12-2024").unwrap();
+    let ruta = descargar::ruta_salida(&configuracion, &fecha, ".pdf");
+    let contenido = fs::read(&ruta).unwrap();
+    let almacen = firmas::cargar_almacen(&configuracion.almacen_confianza).unwrap();
+    let verificacion = firmas::verificar_documento(&contenido, &almacen);
+    assert_eq!(
+        verificacion.estado,
+        EstadoFirma::Valida,
+        "{}",
+        verificacion.detalle
+    );
+    assert_eq!(Document::load_mem(&contenido).unwrap().get_pages().len(), 3);
+    firmas::verificar_archivo(&ruta, &configuracion).unwrap();
+
+    // Cualquier cambio posterior hace fallar la verificación
+    let mut alterado = contenido.clone();
+    alterado.extend_from_slice(b"\n% comentario\n");
+    fs::write(&ruta, alterado).unwrap();
+    assert!(firmas::verificar_archivo(&ruta, &configuracion).is_err());
+}
+
+#[tokio::test]
+async fn firma_cada_volumen_sin_pasar_del_limite() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let firma = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/firma");
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.apartados = vec![String::from("disposiciones")];
+    configuracion.certificado_firma = Some(firma.join("firmante.p12"));
+    configuracion.clave_firma = Some(String::from("boe"));
+    configuracion.almacen_confianza = vec![firma.join("ca.pem")];
+    configuracion.volumen_maximo_mb = Some(1);
+    configuracion.volumen_maximo_paginas = Some(2);
+
+    descargar::crear_gestor_descargas("11-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("11-12-2024").unwrap();
+    let almacen = firmas::cargar_almacen(&configuracion.almacen_confianza).unwrap();
+    for numero in 1..=2 {
+        let ruta = descargar::ruta_salida(&configuracion, &fecha, &format!("_vol{}.pdf", numero));
+        let contenido = fs::read(&ruta).unwrap();
+        assert!(contenido.len() <= 1_000_000);
+        // Una sola firma por volumen, la que se ha tenido en cuenta para el límite
+        let verificacion = firmas::verificar_documento(&contenido, &almacen);
+        assert_eq!(
+            verificacion.estado,
+            EstadoFirma::Valida,
+            "{}",
+            verificacion.detalle
+        );
+    }
+    assert!(!descargar::ruta_salida(&configuracion, &fecha, "_vol3.pdf").exists());
+}
+
+#[tokio::test]
+async fn no_descarga_nada_si_no_se_puede_abrir_el_certificado_de_firma() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.certificado_firma =
+        Some(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/firma/firmante.p12"));
+    configuracion.clave_firma = Some(String::from("incorrecta"));
+
+    let error = descargar::crear_gestor_descargas("07-12-2024", &configuracion)
+        .await
+        .err()
+        .unwrap();
+    assert!(error.contains("contraseña"));
+    assert_eq!(fs::read_dir(salida.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn cifra_el_pdf_unido_con_las_claves_indicadas() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.cifrar_pdf = true;
+    configuracion.clave_usuario_pdf = Some(String::from("lector"));
+    configuracion.clave_propietario_pdf = Some(String::from("editor"));
+    configuracion.permisos_pdf = vec![Permiso::Imprimir];
+
+    descargar::crear_gestor_descargas("07-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+    let contenido = fs::read(descargar::ruta_salida(&configuracion, &fecha, ".pdf")).unwrap();
+    assert!(contenido.starts_with(b"%PDF-2.0"));
+    let unido = Document::load_mem(&contenido).unwrap();
+    assert!(unido.is_encrypted());
+    assert_eq!(unido.get_pages().len(), 8);
+
+    let cifrado = unido
+        .trailer
+        .get(b"Encrypt")
+        .and_then(Object::as_reference)
+        .and_then(|id| unido.get_dictionary(id))
+        .unwrap();
+    assert_eq!(cifrado.get(b"R").unwrap().as_i64().unwrap(), 6);
+    let permisos = cifrado.get(b"P").unwrap().as_i64().unwrap() as u32;
+    assert_eq!(permisos, cifrar::valor_permisos(&[Permiso::Imprimir]));
+    // U empieza por el hash de la clave de usuario con su sal de validación
+    let u = cifrado.get(b"U").unwrap().as_str().unwrap();
+    assert_eq!(cifrar::hash_r6(b"lector", &u[32..40], &[]), u[..32]);
+    assert_ne!(cifrar::hash_r6(b"", &u[32..40], &[]), u[..32]);
+}
+
+#[tokio::test]
+async fn no_guarda_como_pdfa_un_boletin_con_fuentes_sin_incrustar() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.pdfa = true;
+
+    let error = descargar::crear_gestor_descargas("07-12-2024", &configuracion)
+        .await
+        .unwrap_err();
+
+    // Los PDF de prueba usan Helvetica sin incrustar, algo que PDF/A no admite
+    assert!(error.contains("PDF/A-2b"), "{}", error);
+    assert!(
+        error.contains("la fuente Helvetica no está incrustada"),
+        "{}",
+        error
+    );
+    let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+    assert!(!descargar::ruta_salida(&configuracion, &fecha, ".pdf").exists());
+}
+
+#[tokio::test]
+async fn estampa_las_paginas_con_su_identificador() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let mut configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    configuracion.estampar_paginas = true;
+
+    descargar::crear_gestor_descargas("07-12-2024", &configuracion)
+        .await
+        .unwrap();
+
+    let fecha = validar_fecha::comprobar_formato("07-12-2024").unwrap();
+    let unido = Document::load(descargar::ruta_salida(&configuracion, &fecha, ".pdf")).unwrap();
+    let paginas = unido.get_pages().into_values().collect::<Vec<_>>();
+    assert_eq!(paginas.len(), 8);
+    for (indice, pagina) in paginas.iter().enumerate() {
+        let contenido = unido.get_page_content(*pagina).unwrap();
+        let numero = format!("{} de 8", indice + 1).into_bytes();
+        assert!(contenido
+            .windows(numero.len())
+            .any(|v| v == numero.as_slice()));
+    }
+    let primera = unido.get_page_content(paginas[0]).unwrap();
+    assert!(primera.windows(16).any(|v| v == b"BOE-A-2024-25512"));
+
+    let etiquetas = unido
+        .catalog()
+        .unwrap()
+        .get(b"PageLabels")
+        .and_then(Object::as_dict)
+        .and_then(|e| e.get(b"Nums"))
+        .and_then(Object::as_array)
+        .unwrap();
+    // Una etiqueta por documento, que empieza en su primera página
+    assert_eq!(etiquetas.len(), 10);
+    assert_eq!(etiquetas[0].as_i64().unwrap(), 0);
+    let prefijo = etiquetas[1].as_dict().unwrap().get(b"P").unwrap();
+    assert_eq!(
+        lopdf::decode_text_string(prefijo).unwrap(),
+        "BOE-A-2024-25512 p."
+    );
+}
+
+#[tokio::test]
+async fn sirve_el_boletin_y_recuerda_las_fechas_que_fallan() {
+    let url_base = comun::iniciar_servidor_boe().await;
+    let salida = TempDir::new().unwrap();
+    let configuracion = comun::configuracion_pruebas(&url_base, &salida);
+    let escucha = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let direccion = escucha.local_addr().unwrap();
+    let rutas = servir::crear_rutas(&configuracion);
+    tokio::spawn(async move {
+        axum::serve(escucha, rutas).await.unwrap();
+    });
+    let cliente = reqwest::Client::new();
+    let pedir = |ruta: &str| cliente.get(format!("http://{}{}", direccion, ruta)).send();
+
+    let respuesta = pedir("/boe/2024-12-07.pdf").await.unwrap();
+    assert_eq!(respuesta.status(), 200);
+    let pdf = respuesta.bytes().await.unwrap();
+    assert_eq!(Document::load_mem(&pdf).unwrap().get_pages().len(), 8);
+    let documentos = pedir("/boe/2024-12-07/documentos").await.unwrap();
+    let documentos: Vec<serde_json::Value> =
+        serde_json::from_slice(&documentos.bytes().await.unwrap()).unwrap();
+    assert_eq!(documentos.len(), 5);
+
+    // No hay boletín de ese día, el fallo se recuerda en lugar de volver a pedirlo
+    let primera = pedir("/boe/2024-12-08.pdf").await.unwrap();
+    assert_eq!(primera.status(), 502);
+    let segunda = pedir("/boe/2024-12-08/informe.json").await.unwrap();
+    assert_eq!(segunda.status(), 502);
+    assert!(segunda
+        .text()
+        .await
+        .unwrap()
+        .contains("no se volverá a intentar"));
+    assert_eq!(pedir("/boe/08-12-2024.pdf").await.unwrap().status(), 400);
+}